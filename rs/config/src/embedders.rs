@@ -17,6 +17,16 @@ pub struct Config {
     pub num_runtime_query_threads: usize,
     pub max_globals: usize,
     pub max_functions: usize,
+    /// Whether wasm memories should be backed by transparent huge pages if
+    /// the kernel provides them. `MAP_HUGETLB` is not used, since such
+    /// memories could not be tracked page by page. Only applies to the
+    /// `Sigsegv` persistence type.
+    pub use_huge_pages: bool,
+    /// The number of unused wasm memory mappings kept around for reuse, to
+    /// avoid an `mmap` and `munmap` for every instantiation. Zero disables
+    /// the pool. Only applies to the `Sigsegv` persistence type without huge
+    /// pages.
+    pub mmap_memory_pool_size: usize,
 }
 
 impl Config {
@@ -28,6 +38,8 @@ impl Config {
             num_runtime_query_threads: 4,
             max_globals: MAX_GLOBALS,
             max_functions: MAX_FUNCTIONS,
+            use_huge_pages: false,
+            mmap_memory_pool_size: 0,
         }
     }
 }
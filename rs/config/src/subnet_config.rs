@@ -5,14 +5,38 @@ use crate::execution_environment::SUBNET_HEAP_DELTA_CAPACITY;
 use ic_base_types::NumBytes;
 use ic_registry_subnet_type::SubnetType;
 use ic_types::{Cycles, NumInstructions};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
 
 // We assume 1 cycles unit ≅ 1 CPU cycle, so on a 2 GHz CPU one message has
 // approximately 2.5 seconds to be processed.
 pub(crate) const MAX_INSTRUCTIONS_PER_MESSAGE: NumInstructions =
     NumInstructions::new((1 << 30) * 5);
 
+// Installing or upgrading a canister may have to run an expensive `init` or
+// `post_upgrade` function, and to migrate the canister's state, so these
+// messages get a larger budget than ordinary messages.
+const INSTALL_MESSAGE_INSTRUCTIONS_FACTOR: u64 = 4;
+
+// On verified application subnets the canister code is trusted more than on
+// application subnets, so the scheduler may run more canisters in parallel and
+// execute more messages per round.
+const VERIFIED_APPLICATION_SUBNET_SCHEDULER_CORES: usize = 48;
+const VERIFIED_APPLICATION_SUBNET_MESSAGES_PER_ROUND: u64 = 10;
+
+// Environment variables read by `SubnetConfigs::from_env`. Each overrides the
+// `SchedulerConfig` field of the same name on application and verified
+// application subnets.
+pub const SCHEDULER_CORES_ENV_VAR: &str = "IC_SCHEDULER_CORES";
+pub const MAX_INSTRUCTIONS_PER_ROUND_ENV_VAR: &str = "IC_MAX_INSTRUCTIONS_PER_ROUND";
+pub const MAX_INSTRUCTIONS_PER_MESSAGE_ENV_VAR: &str = "IC_MAX_INSTRUCTIONS_PER_MESSAGE";
+pub const MAX_INSTRUCTIONS_PER_INSTALL_MESSAGE_ENV_VAR: &str =
+    "IC_MAX_INSTRUCTIONS_PER_INSTALL_MESSAGE";
+pub const SUBNET_HEAP_DELTA_CAPACITY_ENV_VAR: &str = "IC_SUBNET_HEAP_DELTA_CAPACITY";
+
 /// The per subnet type configuration for the scheduler component
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SchedulerConfig {
     /// Number of canisters that the scheduler is allowed to schedule in
     /// parallel.
@@ -26,6 +50,10 @@ pub struct SchedulerConfig {
     /// This should be significantly smaller than `max_instructions_per_round`.
     pub max_instructions_per_message: NumInstructions,
 
+    /// Maximum amount of instructions a single install or upgrade message's
+    /// execution can consume. This is at least `max_instructions_per_message`.
+    pub max_instructions_per_install_message: NumInstructions,
+
     /// This specifies the upper limit on how much heap delta all the canisters
     /// together on the subnet can produce in between checkpoints. This is a
     /// soft limit in the sense, that we will continue to execute canisters as
@@ -47,6 +75,8 @@ impl SchedulerConfig {
             subnet_heap_delta_capacity: SUBNET_HEAP_DELTA_CAPACITY,
             max_instructions_per_round: MAX_INSTRUCTIONS_PER_MESSAGE * 5,
             max_instructions_per_message: MAX_INSTRUCTIONS_PER_MESSAGE,
+            max_instructions_per_install_message: MAX_INSTRUCTIONS_PER_MESSAGE
+                * INSTALL_MESSAGE_INSTRUCTIONS_FACTOR,
         }
     }
 
@@ -62,25 +92,35 @@ impl SchedulerConfig {
             subnet_heap_delta_capacity: SUBNET_HEAP_DELTA_CAPACITY,
             max_instructions_per_round: max_instructions_per_message * 5,
             max_instructions_per_message,
+            max_instructions_per_install_message: max_instructions_per_message
+                * INSTALL_MESSAGE_INSTRUCTIONS_FACTOR,
         }
     }
 
     pub fn verified_application_subnet() -> Self {
         Self {
-            // The gen 1 production machines should have 64 cores. We expect
-            // that up to half might be needed for the IC protocol so we
-            // allow the scheduler to use the other half for running
-            // canisters in parallel.
-            scheduler_cores: 32,
+            // The gen 1 production machines should have 64 cores. Since the
+            // code running on verified subnets is trusted more, we let the
+            // scheduler use three quarters of them for running canisters in
+            // parallel.
+            scheduler_cores: VERIFIED_APPLICATION_SUBNET_SCHEDULER_CORES,
 
             subnet_heap_delta_capacity: SUBNET_HEAP_DELTA_CAPACITY,
-            max_instructions_per_round: MAX_INSTRUCTIONS_PER_MESSAGE * 5,
+            max_instructions_per_round: MAX_INSTRUCTIONS_PER_MESSAGE
+                * VERIFIED_APPLICATION_SUBNET_MESSAGES_PER_ROUND,
             max_instructions_per_message: MAX_INSTRUCTIONS_PER_MESSAGE,
+            max_instructions_per_install_message: MAX_INSTRUCTIONS_PER_MESSAGE
+                * INSTALL_MESSAGE_INSTRUCTIONS_FACTOR,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// The maximum ratio between a per-message fee (update message execution,
+/// xnet call, ingress message reception) and the fee for executing 10
+/// instructions that `CyclesAccountManagerConfig::validate` accepts.
+const MAX_MESSAGE_FEE_TO_INSTRUCTION_FEE_RATIO: u128 = 1_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CyclesAccountManagerConfig {
     /// Fee for creating canisters on a subnet
     pub canister_creation_fee: Cycles,
@@ -114,16 +154,28 @@ pub struct CyclesAccountManagerConfig {
     /// reserved compute allocation is a scarce resource, and should be
     /// appropriately charged for.
     pub compute_percent_allocated_per_second_fee: Cycles,
+
+    /// Additional fee for each percent of the reserved compute allocation
+    /// that a canister does not use. It is meant to discourage canisters from
+    /// hoarding compute allocation and is zero by default. Configurations
+    /// serialized before the fee was introduced deserialize with a zero fee.
+    #[serde(default = "zero_cycles")]
+    pub idle_reserved_compute_penalty_fee: Cycles,
+}
+
+fn zero_cycles() -> Cycles {
+    Cycles::new(0)
 }
 
 impl CyclesAccountManagerConfig {
     pub fn application_subnet() -> Self {
-        Self {
+        let config = Self {
             // Setting the fee to create canisters to a significant amount to
             // discourage potential DoS attacks that involve creating a ton of
             // canisters on the network.
             canister_creation_fee: Cycles::new(1_000_000_000_000),
             compute_percent_allocated_per_second_fee: Cycles::new(100_000),
+            idle_reserved_compute_penalty_fee: Cycles::new(0),
 
             // The following fields are set based on an experiment where we
             // measured how many resources a representative benchmark on a
@@ -136,13 +188,16 @@ impl CyclesAccountManagerConfig {
             ingress_byte_reception_fee: Cycles::new(140_000),
             // 40 SDR per GiB per year => 40e12 Cycles per year
             gib_storage_per_second_fee: Cycles::new(1_270_000),
-        }
+        };
+        debug_assert_eq!(config.validate(), Ok(()));
+        config
     }
 
     pub fn verified_application_subnet() -> Self {
-        Self {
+        let config = Self {
             canister_creation_fee: Cycles::new(100_000_000_000),
             compute_percent_allocated_per_second_fee: Cycles::new(100_000),
+            idle_reserved_compute_penalty_fee: Cycles::new(0),
 
             // The following fields are set based on a thought experiment where
             // we estimated how many resources a representative benchmark on a
@@ -155,14 +210,17 @@ impl CyclesAccountManagerConfig {
             ingress_byte_reception_fee: Cycles::new(2_000),
             // 4 SDR per GiB per year => 4e12 Cycles per year
             gib_storage_per_second_fee: Cycles::new(127_000),
-        }
+        };
+        debug_assert_eq!(config.validate(), Ok(()));
+        config
     }
 
     /// All processing is free on system subnets
     pub fn system_subnet() -> Self {
-        Self {
+        let config = Self {
             canister_creation_fee: Cycles::new(0),
             compute_percent_allocated_per_second_fee: Cycles::new(0),
+            idle_reserved_compute_penalty_fee: Cycles::new(0),
             update_message_execution_fee: Cycles::new(0),
             ten_update_instructions_execution_fee: Cycles::new(0),
             xnet_call_fee: Cycles::new(0),
@@ -170,12 +228,130 @@ impl CyclesAccountManagerConfig {
             ingress_message_reception_fee: Cycles::new(0),
             ingress_byte_reception_fee: Cycles::new(0),
             gib_storage_per_second_fee: Cycles::new(0),
+        };
+        debug_assert_eq!(config.validate(), Ok(()));
+        debug_assert!(config.is_free());
+        config
+    }
+
+    /// Returns true if all fees are zero.
+    pub fn is_free(&self) -> bool {
+        self.fees().iter().all(|(_, fee)| fee.get() == 0)
+    }
+
+    /// Returns, for each fee, the ratio of the fee in this config to the same
+    /// fee in `other`, keyed by the name of the fee field. The ratio is `None`
+    /// if the fee in `other` is zero, e.g. when comparing against a system
+    /// subnet.
+    pub fn fee_ratio(&self, other: &Self) -> BTreeMap<&'static str, Option<f64>> {
+        self.fees()
+            .into_iter()
+            .zip(other.fees())
+            .map(|((name, fee), (_, other_fee))| {
+                let ratio = match other_fee.get() {
+                    0 => None,
+                    other_fee => Some(fee.get() as f64 / other_fee as f64),
+                };
+                (name, ratio)
+            })
+            .collect()
+    }
+
+    /// Checks that the fees are consistent with each other:
+    ///
+    /// * If executing instructions is free, then everything is free.
+    /// * The fee per byte of a message does not exceed the fee for the message
+    ///   itself, both for ingress messages and xnet calls.
+    /// * No per-message fee exceeds the fee for executing 10 instructions by
+    ///   more than a factor of `MAX_MESSAGE_FEE_TO_INSTRUCTION_FEE_RATIO`.
+    pub fn validate(&self) -> Result<(), String> {
+        let instruction_fee = self.ten_update_instructions_execution_fee.get();
+        if instruction_fee == 0 {
+            return match self.fees().iter().find(|(_, fee)| fee.get() != 0) {
+                Some((name, fee)) => Err(format!(
+                    "{} is {} although ten_update_instructions_execution_fee is 0",
+                    name, fee
+                )),
+                None => Ok(()),
+            };
+        }
+        if self.ingress_byte_reception_fee > self.ingress_message_reception_fee {
+            return Err(format!(
+                "ingress_byte_reception_fee ({}) exceeds ingress_message_reception_fee ({})",
+                self.ingress_byte_reception_fee, self.ingress_message_reception_fee
+            ));
+        }
+        if self.xnet_byte_transmission_fee > self.xnet_call_fee {
+            return Err(format!(
+                "xnet_byte_transmission_fee ({}) exceeds xnet_call_fee ({})",
+                self.xnet_byte_transmission_fee, self.xnet_call_fee
+            ));
         }
+        let max_message_fee =
+            instruction_fee.saturating_mul(MAX_MESSAGE_FEE_TO_INSTRUCTION_FEE_RATIO);
+        for (name, fee) in &[
+            (
+                "update_message_execution_fee",
+                self.update_message_execution_fee,
+            ),
+            ("xnet_call_fee", self.xnet_call_fee),
+            (
+                "ingress_message_reception_fee",
+                self.ingress_message_reception_fee,
+            ),
+        ] {
+            if fee.get() > max_message_fee {
+                return Err(format!(
+                    "{} ({}) exceeds {} times ten_update_instructions_execution_fee ({})",
+                    name, fee, MAX_MESSAGE_FEE_TO_INSTRUCTION_FEE_RATIO, instruction_fee
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn fees(&self) -> Vec<(&'static str, Cycles)> {
+        vec![
+            ("canister_creation_fee", self.canister_creation_fee),
+            (
+                "update_message_execution_fee",
+                self.update_message_execution_fee,
+            ),
+            (
+                "ten_update_instructions_execution_fee",
+                self.ten_update_instructions_execution_fee,
+            ),
+            ("xnet_call_fee", self.xnet_call_fee),
+            (
+                "xnet_byte_transmission_fee",
+                self.xnet_byte_transmission_fee,
+            ),
+            (
+                "ingress_message_reception_fee",
+                self.ingress_message_reception_fee,
+            ),
+            (
+                "ingress_byte_reception_fee",
+                self.ingress_byte_reception_fee,
+            ),
+            (
+                "gib_storage_per_second_fee",
+                self.gib_storage_per_second_fee,
+            ),
+            (
+                "compute_percent_allocated_per_second_fee",
+                self.compute_percent_allocated_per_second_fee,
+            ),
+            (
+                "idle_reserved_compute_penalty_fee",
+                self.idle_reserved_compute_penalty_fee,
+            ),
+        ]
     }
 }
 
 /// The per subnet type configuration for CoW Memory Manager
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CowMemoryManagerConfig {
     /// Flag to enable or disable the feature
     pub enabled: bool,
@@ -197,7 +373,7 @@ impl CowMemoryManagerConfig {
 
 /// If a component has at least one static configuration that is different for
 /// different subnet types, then it is included in this struct.
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SubnetConfig {
     pub scheduler_config: SchedulerConfig,
     pub cycles_account_manager_config: CyclesAccountManagerConfig,
@@ -235,6 +411,258 @@ impl SubnetConfig {
     }
 }
 
+/// A flat snapshot of the scheduler and cycles account manager settings of a
+/// `SubnetConfig`, using primitive types only, e.g. for telemetry.
+///
+/// Instruction limits are in instructions, the heap delta capacity is in
+/// bytes and all fees are in cycles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubnetConfigSummary {
+    pub scheduler_cores: usize,
+    pub max_instructions_per_round: u64,
+    pub max_instructions_per_message: u64,
+    pub max_instructions_per_install_message: u64,
+    pub subnet_heap_delta_capacity: u64,
+    pub canister_creation_fee: u128,
+    pub update_message_execution_fee: u128,
+    pub ten_update_instructions_execution_fee: u128,
+    pub xnet_call_fee: u128,
+    pub xnet_byte_transmission_fee: u128,
+    pub ingress_message_reception_fee: u128,
+    pub ingress_byte_reception_fee: u128,
+    pub gib_storage_per_second_fee: u128,
+    pub compute_percent_allocated_per_second_fee: u128,
+    pub idle_reserved_compute_penalty_fee: u128,
+}
+
+impl From<&SubnetConfig> for SubnetConfigSummary {
+    fn from(config: &SubnetConfig) -> Self {
+        let scheduler_config = &config.scheduler_config;
+        let cycles_config = &config.cycles_account_manager_config;
+        Self {
+            scheduler_cores: scheduler_config.scheduler_cores,
+            max_instructions_per_round: scheduler_config.max_instructions_per_round.get(),
+            max_instructions_per_message: scheduler_config.max_instructions_per_message.get(),
+            max_instructions_per_install_message: scheduler_config
+                .max_instructions_per_install_message
+                .get(),
+            subnet_heap_delta_capacity: scheduler_config.subnet_heap_delta_capacity.get(),
+            canister_creation_fee: cycles_config.canister_creation_fee.get(),
+            update_message_execution_fee: cycles_config.update_message_execution_fee.get(),
+            ten_update_instructions_execution_fee: cycles_config
+                .ten_update_instructions_execution_fee
+                .get(),
+            xnet_call_fee: cycles_config.xnet_call_fee.get(),
+            xnet_byte_transmission_fee: cycles_config.xnet_byte_transmission_fee.get(),
+            ingress_message_reception_fee: cycles_config.ingress_message_reception_fee.get(),
+            ingress_byte_reception_fee: cycles_config.ingress_byte_reception_fee.get(),
+            gib_storage_per_second_fee: cycles_config.gib_storage_per_second_fee.get(),
+            compute_percent_allocated_per_second_fee: cycles_config
+                .compute_percent_allocated_per_second_fee
+                .get(),
+            idle_reserved_compute_penalty_fee: cycles_config
+                .idle_reserved_compute_penalty_fee
+                .get(),
+        }
+    }
+}
+
+/// Errors returned when building an invalid `SubnetConfig`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubnetConfigError {
+    /// The scheduler must be allowed to use at least one core.
+    ZeroSchedulerCores,
+
+    /// A single message must not be allowed to use more instructions than a
+    /// whole round.
+    MaxInstructionsPerMessageExceedsMaxInstructionsPerRound {
+        max_instructions_per_message: NumInstructions,
+        max_instructions_per_round: NumInstructions,
+    },
+
+    /// Install and upgrade messages must be allowed to use at least as many
+    /// instructions as ordinary messages.
+    MaxInstructionsPerInstallMessageBelowMaxInstructionsPerMessage {
+        max_instructions_per_install_message: NumInstructions,
+        max_instructions_per_message: NumInstructions,
+    },
+
+    /// An environment variable read by `SubnetConfigs::from_env` holds a value
+    /// that cannot be parsed.
+    InvalidEnvironmentVariable { name: &'static str, value: String },
+
+    /// The fees fail `CyclesAccountManagerConfig::validate`, which describes
+    /// the reason.
+    InvalidCyclesAccountManagerConfig(String),
+}
+
+impl std::fmt::Display for SubnetConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubnetConfigError::ZeroSchedulerCores => {
+                write!(f, "The number of scheduler cores must be positive")
+            }
+            SubnetConfigError::MaxInstructionsPerMessageExceedsMaxInstructionsPerRound {
+                max_instructions_per_message,
+                max_instructions_per_round,
+            } => write!(
+                f,
+                "The maximum number of instructions per message ({}) exceeds the maximum number of instructions per round ({})",
+                max_instructions_per_message, max_instructions_per_round
+            ),
+            SubnetConfigError::MaxInstructionsPerInstallMessageBelowMaxInstructionsPerMessage {
+                max_instructions_per_install_message,
+                max_instructions_per_message,
+            } => write!(
+                f,
+                "The maximum number of instructions per install message ({}) is below the maximum number of instructions per message ({})",
+                max_instructions_per_install_message, max_instructions_per_message
+            ),
+            SubnetConfigError::InvalidEnvironmentVariable { name, value } => write!(
+                f,
+                "The value {:?} of the environment variable {} is invalid",
+                value, name
+            ),
+            SubnetConfigError::InvalidCyclesAccountManagerConfig(reason) => {
+                write!(f, "The cycles account manager config is invalid: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SubnetConfigError {}
+
+/// A builder for a `SubnetConfig` that starts from the default configuration
+/// of a subnet type and allows to override individual fields.
+///
+/// ```
+/// use ic_config::subnet_config::SubnetConfigBuilder;
+/// use ic_types::NumInstructions;
+///
+/// let config = SubnetConfigBuilder::application_subnet()
+///     .with_scheduler_cores(4)
+///     .with_max_instructions_per_round(NumInstructions::from(1_000_000))
+///     .with_max_instructions_per_message(NumInstructions::from(100_000))
+///     .build()
+///     .unwrap();
+/// assert_eq!(config.scheduler_config.scheduler_cores, 4);
+/// ```
+pub struct SubnetConfigBuilder {
+    config: SubnetConfig,
+}
+
+impl SubnetConfigBuilder {
+    /// Starts from the default configuration of the application subnet type.
+    pub fn application_subnet() -> Self {
+        Self {
+            config: SubnetConfig::default_application_subnet(),
+        }
+    }
+
+    /// Starts from the default configuration of the system subnet type.
+    pub fn system_subnet() -> Self {
+        Self {
+            config: SubnetConfig::default_system_subnet(),
+        }
+    }
+
+    /// Starts from the default configuration of the verified application
+    /// subnet type.
+    pub fn verified_application_subnet() -> Self {
+        Self {
+            config: SubnetConfig::default_verified_application_subnet(),
+        }
+    }
+
+    pub fn with_scheduler_cores(mut self, scheduler_cores: usize) -> Self {
+        self.config.scheduler_config.scheduler_cores = scheduler_cores;
+        self
+    }
+
+    pub fn with_max_instructions_per_round(
+        mut self,
+        max_instructions_per_round: NumInstructions,
+    ) -> Self {
+        self.config.scheduler_config.max_instructions_per_round = max_instructions_per_round;
+        self
+    }
+
+    pub fn with_max_instructions_per_message(
+        mut self,
+        max_instructions_per_message: NumInstructions,
+    ) -> Self {
+        self.config.scheduler_config.max_instructions_per_message = max_instructions_per_message;
+        self
+    }
+
+    pub fn with_max_instructions_per_install_message(
+        mut self,
+        max_instructions_per_install_message: NumInstructions,
+    ) -> Self {
+        self.config
+            .scheduler_config
+            .max_instructions_per_install_message = max_instructions_per_install_message;
+        self
+    }
+
+    pub fn with_subnet_heap_delta_capacity(mut self, subnet_heap_delta_capacity: NumBytes) -> Self {
+        self.config.scheduler_config.subnet_heap_delta_capacity = subnet_heap_delta_capacity;
+        self
+    }
+
+    pub fn with_gib_storage_per_second_fee(mut self, gib_storage_per_second_fee: Cycles) -> Self {
+        self.config
+            .cycles_account_manager_config
+            .gib_storage_per_second_fee = gib_storage_per_second_fee;
+        self
+    }
+
+    pub fn with_idle_reserved_compute_penalty_fee(
+        mut self,
+        idle_reserved_compute_penalty_fee: Cycles,
+    ) -> Self {
+        self.config
+            .cycles_account_manager_config
+            .idle_reserved_compute_penalty_fee = idle_reserved_compute_penalty_fee;
+        self
+    }
+
+    /// Returns the configuration, or an error if the overrides resulted in an
+    /// inconsistent configuration.
+    pub fn build(self) -> Result<SubnetConfig, SubnetConfigError> {
+        let scheduler_config = &self.config.scheduler_config;
+        if scheduler_config.scheduler_cores == 0 {
+            return Err(SubnetConfigError::ZeroSchedulerCores);
+        }
+        if scheduler_config.max_instructions_per_message
+            > scheduler_config.max_instructions_per_round
+        {
+            return Err(
+                SubnetConfigError::MaxInstructionsPerMessageExceedsMaxInstructionsPerRound {
+                    max_instructions_per_message: scheduler_config.max_instructions_per_message,
+                    max_instructions_per_round: scheduler_config.max_instructions_per_round,
+                },
+            );
+        }
+        if scheduler_config.max_instructions_per_install_message
+            < scheduler_config.max_instructions_per_message
+        {
+            return Err(
+                SubnetConfigError::MaxInstructionsPerInstallMessageBelowMaxInstructionsPerMessage {
+                    max_instructions_per_install_message: scheduler_config
+                        .max_instructions_per_install_message,
+                    max_instructions_per_message: scheduler_config.max_instructions_per_message,
+                },
+            );
+        }
+        self.config
+            .cycles_account_manager_config
+            .validate()
+            .map_err(SubnetConfigError::InvalidCyclesAccountManagerConfig)?;
+        Ok(self.config)
+    }
+}
+
 /// A struct that holds the per subnet configuration for all the subnet types on
 /// the internet computer.
 pub struct SubnetConfigs {
@@ -254,6 +682,72 @@ impl Default for SubnetConfigs {
 }
 
 impl SubnetConfigs {
+    /// Returns the default subnet configurations, except that `config` is used
+    /// for subnets of type `subnet_type`.
+    pub fn with_override(subnet_type: SubnetType, config: SubnetConfig) -> Self {
+        let mut configs = Self::default();
+        match subnet_type {
+            SubnetType::Application => configs.application_subnet = config,
+            SubnetType::System => configs.system_subnet = config,
+            SubnetType::VerifiedApplication => configs.verified_application_subnet = config,
+        }
+        configs
+    }
+
+    /// Returns `defaults` with the scheduler configuration of application and
+    /// verified application subnets overridden by the following environment
+    /// variables, if set:
+    ///
+    /// * `IC_SCHEDULER_CORES`
+    /// * `IC_MAX_INSTRUCTIONS_PER_ROUND`
+    /// * `IC_MAX_INSTRUCTIONS_PER_MESSAGE`
+    /// * `IC_MAX_INSTRUCTIONS_PER_INSTALL_MESSAGE`
+    /// * `IC_SUBNET_HEAP_DELTA_CAPACITY` (in bytes)
+    ///
+    /// This is meant for experiments on testnets only. System subnets always
+    /// keep their defaults, so that such experiments cannot affect the
+    /// subnets hosting the NNS. Unset variables leave the defaults untouched. Returns an error if a variable cannot be parsed
+    /// or if the overrides result in an inconsistent configuration.
+    pub fn from_env(defaults: SubnetConfigs) -> Result<Self, SubnetConfigError> {
+        Self::from_vars(defaults, |name| {
+            std::env::var_os(name).map(|value| value.to_string_lossy().into_owned())
+        })
+    }
+
+    fn from_vars<F>(defaults: SubnetConfigs, var: F) -> Result<Self, SubnetConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let overrides = SchedulerConfigOverrides {
+            scheduler_cores: parse_var(&var, SCHEDULER_CORES_ENV_VAR)?,
+            max_instructions_per_round: parse_var::<u64, _>(
+                &var,
+                MAX_INSTRUCTIONS_PER_ROUND_ENV_VAR,
+            )?
+            .map(NumInstructions::from),
+            max_instructions_per_message: parse_var::<u64, _>(
+                &var,
+                MAX_INSTRUCTIONS_PER_MESSAGE_ENV_VAR,
+            )?
+            .map(NumInstructions::from),
+            max_instructions_per_install_message: parse_var::<u64, _>(
+                &var,
+                MAX_INSTRUCTIONS_PER_INSTALL_MESSAGE_ENV_VAR,
+            )?
+            .map(NumInstructions::from),
+            subnet_heap_delta_capacity: parse_var::<u64, _>(
+                &var,
+                SUBNET_HEAP_DELTA_CAPACITY_ENV_VAR,
+            )?
+            .map(NumBytes::from),
+        };
+        Ok(Self {
+            system_subnet: defaults.system_subnet,
+            application_subnet: overrides.apply(defaults.application_subnet)?,
+            verified_application_subnet: overrides.apply(defaults.verified_application_subnet)?,
+        })
+    }
+
     /// Returns the appropriate subnet configuration based on the subnet type.
     pub fn own_subnet_config(&self, own_subnet_type: SubnetType) -> SubnetConfig {
         match own_subnet_type {
@@ -263,3 +757,491 @@ impl SubnetConfigs {
         }
     }
 }
+
+/// The scheduler configuration fields that `SubnetConfigs::from_env` can
+/// override on application and verified application subnets.
+struct SchedulerConfigOverrides {
+    scheduler_cores: Option<usize>,
+    max_instructions_per_round: Option<NumInstructions>,
+    max_instructions_per_message: Option<NumInstructions>,
+    max_instructions_per_install_message: Option<NumInstructions>,
+    subnet_heap_delta_capacity: Option<NumBytes>,
+}
+
+impl SchedulerConfigOverrides {
+    fn apply(&self, config: SubnetConfig) -> Result<SubnetConfig, SubnetConfigError> {
+        let mut builder = SubnetConfigBuilder { config };
+        if let Some(scheduler_cores) = self.scheduler_cores {
+            builder = builder.with_scheduler_cores(scheduler_cores);
+        }
+        if let Some(max_instructions_per_round) = self.max_instructions_per_round {
+            builder = builder.with_max_instructions_per_round(max_instructions_per_round);
+        }
+        if let Some(max_instructions_per_message) = self.max_instructions_per_message {
+            builder = builder.with_max_instructions_per_message(max_instructions_per_message);
+        }
+        if let Some(max_instructions_per_install_message) =
+            self.max_instructions_per_install_message
+        {
+            builder = builder
+                .with_max_instructions_per_install_message(max_instructions_per_install_message);
+        }
+        if let Some(subnet_heap_delta_capacity) = self.subnet_heap_delta_capacity {
+            builder = builder.with_subnet_heap_delta_capacity(subnet_heap_delta_capacity);
+        }
+        builder.build()
+    }
+}
+
+fn parse_var<T, F>(var: &F, name: &'static str) -> Result<Option<T>, SubnetConfigError>
+where
+    T: FromStr,
+    F: Fn(&str) -> Option<String>,
+{
+    match var(name) {
+        None => Ok(None),
+        Some(value) => match value.trim().parse() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(_) => Err(SubnetConfigError::InvalidEnvironmentVariable { name, value }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_build_default_configs() {
+        for builder in vec![
+            SubnetConfigBuilder::application_subnet(),
+            SubnetConfigBuilder::system_subnet(),
+            SubnetConfigBuilder::verified_application_subnet(),
+        ] {
+            assert!(builder.build().is_ok());
+        }
+    }
+
+    #[test]
+    fn should_apply_overrides() {
+        let config = SubnetConfigBuilder::application_subnet()
+            .with_scheduler_cores(4)
+            .with_max_instructions_per_message(NumInstructions::from(100))
+            .with_gib_storage_per_second_fee(Cycles::new(42))
+            .build()
+            .unwrap();
+
+        assert_eq!(config.scheduler_config.scheduler_cores, 4);
+        assert_eq!(
+            config.scheduler_config.max_instructions_per_message,
+            NumInstructions::from(100)
+        );
+        assert_eq!(
+            config.scheduler_config.max_instructions_per_round,
+            SchedulerConfig::application_subnet().max_instructions_per_round
+        );
+        assert_eq!(
+            config
+                .cycles_account_manager_config
+                .gib_storage_per_second_fee,
+            Cycles::new(42)
+        );
+    }
+
+    #[test]
+    fn should_override_config_of_given_subnet_type_only() {
+        let config = SubnetConfigBuilder::application_subnet()
+            .with_max_instructions_per_message(NumInstructions::from(100))
+            .build()
+            .unwrap();
+
+        let configs = SubnetConfigs::with_override(SubnetType::Application, config.clone());
+
+        assert_eq!(configs.own_subnet_config(SubnetType::Application), config);
+        assert_eq!(
+            configs.own_subnet_config(SubnetType::System),
+            SubnetConfig::default_system_subnet()
+        );
+        assert_eq!(
+            configs.own_subnet_config(SubnetType::VerifiedApplication),
+            SubnetConfig::default_verified_application_subnet()
+        );
+    }
+
+    #[test]
+    fn should_compute_fee_ratio_of_application_to_verified_application_subnet() {
+        let ratios = CyclesAccountManagerConfig::application_subnet()
+            .fee_ratio(&CyclesAccountManagerConfig::verified_application_subnet());
+
+        assert_eq!(ratios.len(), 10);
+        assert_eq!(ratios["canister_creation_fee"], Some(10.0));
+        assert_eq!(ratios["ten_update_instructions_execution_fee"], Some(10.0));
+        assert_eq!(
+            ratios["compute_percent_allocated_per_second_fee"],
+            Some(1.0)
+        );
+        assert_eq!(ratios["idle_reserved_compute_penalty_fee"], None);
+    }
+
+    #[test]
+    fn should_report_no_fee_ratio_against_system_subnet() {
+        let ratios = CyclesAccountManagerConfig::application_subnet()
+            .fee_ratio(&CyclesAccountManagerConfig::system_subnet());
+
+        assert!(ratios.values().all(|ratio| ratio.is_none()));
+    }
+
+    #[test]
+    fn should_summarize_default_application_subnet_config() {
+        let config = SubnetConfig::default_application_subnet();
+        let cycles_config = config.cycles_account_manager_config;
+        let max_instructions_per_message = MAX_INSTRUCTIONS_PER_MESSAGE.get();
+
+        let summary = SubnetConfigSummary::from(&config);
+
+        assert_eq!(
+            summary,
+            SubnetConfigSummary {
+                scheduler_cores: 32,
+                max_instructions_per_round: max_instructions_per_message * 5,
+                max_instructions_per_message,
+                max_instructions_per_install_message: max_instructions_per_message
+                    * INSTALL_MESSAGE_INSTRUCTIONS_FACTOR,
+                subnet_heap_delta_capacity: SUBNET_HEAP_DELTA_CAPACITY.get(),
+                canister_creation_fee: 1_000_000_000_000,
+                update_message_execution_fee: cycles_config.update_message_execution_fee.get(),
+                ten_update_instructions_execution_fee: cycles_config
+                    .ten_update_instructions_execution_fee
+                    .get(),
+                xnet_call_fee: cycles_config.xnet_call_fee.get(),
+                xnet_byte_transmission_fee: cycles_config.xnet_byte_transmission_fee.get(),
+                ingress_message_reception_fee: cycles_config.ingress_message_reception_fee.get(),
+                ingress_byte_reception_fee: cycles_config.ingress_byte_reception_fee.get(),
+                gib_storage_per_second_fee: cycles_config.gib_storage_per_second_fee.get(),
+                compute_percent_allocated_per_second_fee: 100_000,
+                idle_reserved_compute_penalty_fee: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn should_serialize_and_deserialize_subnet_config() {
+        for config in vec![
+            SubnetConfig::default_application_subnet(),
+            SubnetConfig::default_system_subnet(),
+            SubnetConfig::default_verified_application_subnet(),
+        ] {
+            let json = serde_json::to_string(&config).unwrap();
+            let deserialized_config: SubnetConfig = serde_json::from_str(&json).unwrap();
+            assert_eq!(deserialized_config, config);
+        }
+    }
+
+    #[test]
+    fn should_use_higher_parallelism_on_verified_application_subnets() {
+        let application = SchedulerConfig::application_subnet();
+        let verified = SchedulerConfig::verified_application_subnet();
+
+        assert!(verified.scheduler_cores > application.scheduler_cores);
+        assert!(verified.max_instructions_per_round > application.max_instructions_per_round);
+        assert_eq!(
+            verified.max_instructions_per_message,
+            application.max_instructions_per_message
+        );
+        assert_eq!(
+            verified.subnet_heap_delta_capacity,
+            application.subnet_heap_delta_capacity
+        );
+    }
+
+    #[test]
+    fn should_allow_more_instructions_per_install_message_on_application_subnets() {
+        let config = SchedulerConfig::application_subnet();
+
+        assert_eq!(
+            config.max_instructions_per_install_message,
+            MAX_INSTRUCTIONS_PER_MESSAGE * INSTALL_MESSAGE_INSTRUCTIONS_FACTOR
+        );
+        assert!(config.max_instructions_per_install_message <= config.max_instructions_per_round);
+    }
+
+    #[test]
+    fn should_allow_more_instructions_per_install_message_on_system_subnets() {
+        let config = SchedulerConfig::system_subnet();
+
+        assert_eq!(
+            config.max_instructions_per_install_message,
+            config.max_instructions_per_message * INSTALL_MESSAGE_INSTRUCTIONS_FACTOR
+        );
+        assert!(config.max_instructions_per_install_message <= config.max_instructions_per_round);
+    }
+
+    #[test]
+    fn should_allow_more_instructions_per_install_message_on_verified_application_subnets() {
+        let config = SchedulerConfig::verified_application_subnet();
+
+        assert_eq!(
+            config.max_instructions_per_install_message,
+            MAX_INSTRUCTIONS_PER_MESSAGE * INSTALL_MESSAGE_INSTRUCTIONS_FACTOR
+        );
+        assert!(config.max_instructions_per_install_message <= config.max_instructions_per_round);
+    }
+
+    #[test]
+    fn should_reject_fewer_instructions_per_install_message_than_per_message() {
+        let result = SubnetConfigBuilder::application_subnet()
+            .with_max_instructions_per_message(NumInstructions::from(100))
+            .with_max_instructions_per_install_message(NumInstructions::from(99))
+            .build();
+
+        assert_eq!(
+            result,
+            Err(
+                SubnetConfigError::MaxInstructionsPerInstallMessageBelowMaxInstructionsPerMessage {
+                    max_instructions_per_install_message: NumInstructions::from(99),
+                    max_instructions_per_message: NumInstructions::from(100),
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn should_accept_default_cycles_account_manager_configs() {
+        assert_eq!(
+            CyclesAccountManagerConfig::application_subnet().validate(),
+            Ok(())
+        );
+        assert_eq!(
+            CyclesAccountManagerConfig::verified_application_subnet().validate(),
+            Ok(())
+        );
+        assert_eq!(
+            CyclesAccountManagerConfig::system_subnet().validate(),
+            Ok(())
+        );
+        assert!(CyclesAccountManagerConfig::system_subnet().is_free());
+    }
+
+    #[test]
+    fn should_charge_no_idle_reserved_compute_penalty_unless_set() {
+        for config in &[
+            CyclesAccountManagerConfig::application_subnet(),
+            CyclesAccountManagerConfig::verified_application_subnet(),
+            CyclesAccountManagerConfig::system_subnet(),
+        ] {
+            assert_eq!(config.idle_reserved_compute_penalty_fee, Cycles::new(0));
+        }
+
+        let config = SubnetConfigBuilder::application_subnet()
+            .with_idle_reserved_compute_penalty_fee(Cycles::new(7))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            config
+                .cycles_account_manager_config
+                .idle_reserved_compute_penalty_fee,
+            Cycles::new(7)
+        );
+        assert_eq!(
+            config
+                .cycles_account_manager_config
+                .compute_percent_allocated_per_second_fee,
+            CyclesAccountManagerConfig::application_subnet()
+                .compute_percent_allocated_per_second_fee
+        );
+    }
+
+    #[test]
+    fn should_deserialize_missing_idle_reserved_compute_penalty_fee_as_zero() {
+        let mut json =
+            serde_json::to_value(CyclesAccountManagerConfig::application_subnet()).unwrap();
+        json.as_object_mut()
+            .unwrap()
+            .remove("idle_reserved_compute_penalty_fee");
+
+        let config: CyclesAccountManagerConfig = serde_json::from_value(json).unwrap();
+
+        assert_eq!(config, CyclesAccountManagerConfig::application_subnet());
+    }
+
+    #[test]
+    fn should_reject_non_zero_idle_reserved_compute_penalty_if_instructions_are_free() {
+        let mut config = CyclesAccountManagerConfig::system_subnet();
+        config.idle_reserved_compute_penalty_fee = Cycles::new(1);
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn should_reject_ingress_byte_fee_above_ingress_message_fee() {
+        let mut config = CyclesAccountManagerConfig::application_subnet();
+        config.ingress_byte_reception_fee = config.ingress_message_reception_fee + Cycles::new(1);
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn should_reject_xnet_byte_fee_above_xnet_call_fee() {
+        let mut config = CyclesAccountManagerConfig::application_subnet();
+        config.xnet_byte_transmission_fee = config.xnet_call_fee + Cycles::new(1);
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn should_reject_message_fee_too_large_relative_to_instruction_fee() {
+        let mut config = CyclesAccountManagerConfig::application_subnet();
+        config.update_message_execution_fee = Cycles::new(
+            config.ten_update_instructions_execution_fee.get()
+                * MAX_MESSAGE_FEE_TO_INSTRUCTION_FEE_RATIO
+                + 1,
+        );
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn should_reject_non_zero_fee_if_instructions_are_free() {
+        let mut config = CyclesAccountManagerConfig::system_subnet();
+        config.gib_storage_per_second_fee = Cycles::new(1);
+
+        assert!(config.validate().is_err());
+        assert!(!config.is_free());
+    }
+
+    #[test]
+    fn should_reject_invalid_cycles_account_manager_config() {
+        let result = SubnetConfigBuilder::system_subnet()
+            .with_gib_storage_per_second_fee(Cycles::new(1))
+            .build();
+
+        assert!(matches!(
+            result,
+            Err(SubnetConfigError::InvalidCyclesAccountManagerConfig(reason))
+                if reason.starts_with("gib_storage_per_second_fee is 1")
+        ));
+    }
+
+    #[test]
+    fn should_reject_zero_scheduler_cores() {
+        let result = SubnetConfigBuilder::application_subnet()
+            .with_scheduler_cores(0)
+            .build();
+
+        assert_eq!(result.err(), Some(SubnetConfigError::ZeroSchedulerCores));
+    }
+
+    #[test]
+    fn should_reject_max_instructions_per_message_above_max_instructions_per_round() {
+        let result = SubnetConfigBuilder::system_subnet()
+            .with_max_instructions_per_round(NumInstructions::from(100))
+            .with_max_instructions_per_message(NumInstructions::from(101))
+            .build();
+
+        assert_eq!(
+            result.err(),
+            Some(
+                SubnetConfigError::MaxInstructionsPerMessageExceedsMaxInstructionsPerRound {
+                    max_instructions_per_message: NumInstructions::from(101),
+                    max_instructions_per_round: NumInstructions::from(100),
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn should_override_scheduler_config_from_env() {
+        let configs = SubnetConfigs::from_vars(SubnetConfigs::default(), |name| {
+            if name == SCHEDULER_CORES_ENV_VAR {
+                Some("4".to_string())
+            } else {
+                None
+            }
+        })
+        .unwrap();
+
+        for (subnet_type, default_config) in vec![
+            (
+                SubnetType::Application,
+                SubnetConfig::default_application_subnet(),
+            ),
+            (
+                SubnetType::VerifiedApplication,
+                SubnetConfig::default_verified_application_subnet(),
+            ),
+        ] {
+            let mut expected_config = default_config;
+            expected_config.scheduler_config.scheduler_cores = 4;
+            assert_eq!(configs.own_subnet_config(subnet_type), expected_config);
+        }
+    }
+
+    #[test]
+    fn should_keep_system_subnet_defaults_despite_env_vars() {
+        let configs = SubnetConfigs::from_vars(SubnetConfigs::default(), |name| {
+            if name == SCHEDULER_CORES_ENV_VAR {
+                Some("4".to_string())
+            } else if name == SUBNET_HEAP_DELTA_CAPACITY_ENV_VAR {
+                Some("1024".to_string())
+            } else {
+                None
+            }
+        })
+        .unwrap();
+
+        assert_eq!(
+            configs.own_subnet_config(SubnetType::System),
+            SubnetConfig::default_system_subnet()
+        );
+    }
+
+    #[test]
+    fn should_keep_defaults_if_no_env_var_is_set() {
+        let configs = SubnetConfigs::from_vars(SubnetConfigs::default(), |_| None).unwrap();
+
+        assert_eq!(
+            configs.own_subnet_config(SubnetType::Application),
+            SubnetConfig::default_application_subnet()
+        );
+        assert_eq!(
+            configs.own_subnet_config(SubnetType::System),
+            SubnetConfig::default_system_subnet()
+        );
+    }
+
+    #[test]
+    fn should_reject_unparsable_env_var() {
+        let result = SubnetConfigs::from_vars(SubnetConfigs::default(), |name| {
+            if name == MAX_INSTRUCTIONS_PER_MESSAGE_ENV_VAR {
+                Some("many".to_string())
+            } else {
+                None
+            }
+        });
+
+        assert_eq!(
+            result.err(),
+            Some(SubnetConfigError::InvalidEnvironmentVariable {
+                name: MAX_INSTRUCTIONS_PER_MESSAGE_ENV_VAR,
+                value: "many".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_inconsistent_env_var_overrides() {
+        let result = SubnetConfigs::from_vars(SubnetConfigs::default(), |name| {
+            if name == MAX_INSTRUCTIONS_PER_ROUND_ENV_VAR {
+                Some("100".to_string())
+            } else {
+                None
+            }
+        });
+
+        assert!(matches!(
+            result.err(),
+            Some(SubnetConfigError::MaxInstructionsPerMessageExceedsMaxInstructionsPerRound { .. })
+        ));
+    }
+}
@@ -1,19 +1,35 @@
 use context::*;
+use lazy_static::lazy_static;
 use openssl::error::ErrorStack;
-use openssl::ssl::{SslAcceptorBuilder, SslConnectorBuilder, SslContextBuilder, SslVersion};
+use openssl::ex_data::Index;
+use openssl::ssl::{
+    select_next_proto, AlpnError, Ssl, SslAcceptorBuilder, SslConnectorBuilder, SslContextBuilder,
+    SslRef, SslVersion,
+};
 use openssl::x509::store::{X509Store, X509StoreBuilder};
+use openssl::x509::X509VerifyResult;
 use openssl::{
     pkey::{PKey, Private},
     ssl::{ConnectConfiguration, SslAcceptor, SslConnector, SslMethod, SslVerifyMode},
     x509::X509,
 };
+use openssl_sys::X509_V_ERR_CERT_HAS_EXPIRED;
 
-pub use acceptor::{tls_acceptor, CreateTlsAcceptorError};
+pub use acceptor::{
+    no_matching_alpn_protocol, tls_acceptor, tls_acceptor_with_cipher_suites,
+    tls_acceptor_with_cipher_suites_and_alpn, tls_acceptor_without_client_auth,
+    CreateTlsAcceptorError,
+};
 pub use connector::{tls_connector, CreateTlsConnectorError};
 
 const MIN_PROTOCOL_VERSION: Option<SslVersion> = Some(SslVersion::TLS1_3);
-const ALLOWED_CIPHER_SUITES: &str = "TLS_AES_128_GCM_SHA256:TLS_AES_256_GCM_SHA384";
+const ALLOWED_CIPHER_SUITES: &[&str] = &["TLS_AES_128_GCM_SHA256", "TLS_AES_256_GCM_SHA384"];
+const TLS12_FALLBACK_MIN_PROTOCOL_VERSION: Option<SslVersion> = Some(SslVersion::TLS1_2);
+// TLS 1.2 cipher suites that can be used with the server's ed25519 certificate.
+const TLS12_FALLBACK_CIPHER_LIST: &str =
+    "ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-ECDSA-AES256-GCM-SHA384";
 const ALLOWED_SIGNATURE_ALGORITHMS: &str = "ed25519";
+const MAX_ALPN_PROTOCOL_LEN: usize = 255;
 
 #[cfg(test)]
 mod tests;
@@ -21,6 +37,16 @@ mod tests;
 mod acceptor {
     use super::*;
 
+    lazy_static! {
+        // The following call is on hard-coded input and so we panic:
+        static ref NO_MATCHING_ALPN_PROTOCOL_INDEX: Index<Ssl, NoMatchingAlpnProtocol> =
+            Ssl::new_ex_index().expect("Failed to create the SSL ex data index.");
+    }
+
+    /// Marks a connection whose handshake was aborted because the client
+    /// offered only ALPN protocols that the server does not support.
+    struct NoMatchingAlpnProtocol;
+
     /// Builds a TLS acceptor to establish TLS connections on the server side.
     ///
     /// For the exact configuration details, see the documentation of the
@@ -32,11 +58,74 @@ mod acceptor {
         private_key: &PKey<Private>,
         server_cert: &X509,
         trusted_client_certs: Vec<X509>,
+    ) -> Result<SslAcceptor, CreateTlsAcceptorError> {
+        tls_acceptor_with_cipher_suites(
+            private_key,
+            server_cert,
+            trusted_client_certs,
+            ALLOWED_CIPHER_SUITES,
+        )
+    }
+
+    /// Builds a TLS acceptor like `tls_acceptor`, but only allows the given
+    /// TLS 1.3 cipher suites (given by their RFC 8446 names, e.g.
+    /// `TLS_AES_128_GCM_SHA256`).
+    ///
+    /// # Errors
+    /// * `CreateTlsAcceptorError` if `allowed_cipher_suites` is empty or
+    ///   contains an unknown cipher suite, or if the creation of the acceptor
+    ///   failed
+    pub fn tls_acceptor_with_cipher_suites(
+        private_key: &PKey<Private>,
+        server_cert: &X509,
+        trusted_client_certs: Vec<X509>,
+        allowed_cipher_suites: &[&str],
+    ) -> Result<SslAcceptor, CreateTlsAcceptorError> {
+        tls_acceptor_with_cipher_suites_and_alpn(
+            private_key,
+            server_cert,
+            trusted_client_certs,
+            allowed_cipher_suites,
+            None,
+        )
+    }
+
+    /// Builds a TLS acceptor like `tls_acceptor_with_cipher_suites`. If an
+    /// `alpn_protocol` is given, the acceptor selects it via ALPN and aborts
+    /// the handshake with a `no_application_protocol` alert if the client
+    /// offers ALPN protocols but not this one.
+    ///
+    /// Whether the client offered ALPN protocols but not the `alpn_protocol`
+    /// can be queried from a failed handshake with `no_matching_alpn_protocol`.
+    /// Note that a client that offers no ALPN protocols at all is still
+    /// accepted; the caller has to check the selected protocol after the
+    /// handshake.
+    ///
+    /// Note also that a client certificate that has expired is not rejected
+    /// during the handshake, so that the caller can report it explicitly; the
+    /// caller has to check the client certificate's `notAfter` after the
+    /// handshake.
+    ///
+    /// # Errors
+    /// * `CreateTlsAcceptorError` if `allowed_cipher_suites` is empty or
+    ///   contains an unknown cipher suite, if `alpn_protocol` is empty or
+    ///   longer than 255 bytes, or if the creation of the acceptor failed
+    pub fn tls_acceptor_with_cipher_suites_and_alpn(
+        private_key: &PKey<Private>,
+        server_cert: &X509,
+        trusted_client_certs: Vec<X509>,
+        allowed_cipher_suites: &[&str],
+        alpn_protocol: Option<&[u8]>,
     ) -> Result<SslAcceptor, CreateTlsAcceptorError> {
         ensure_trusted_client_certs_not_empty(&trusted_client_certs)?;
+        ensure_allowed_cipher_suites_not_empty(allowed_cipher_suites)?;
         let mut builder = SslAcceptor::mozilla_modern_v5(SslMethod::tls_server())
             .expect("Failed to initialize the acceptor.");
-        restrict_tls_version_and_cipher_suites_and_sig_algs(&mut builder);
+        restrict_tls_version_and_sig_algs(&mut builder);
+        set_cipher_suites(allowed_cipher_suites, &mut builder)?;
+        if let Some(alpn_protocol) = alpn_protocol {
+            select_alpn_protocol(alpn_protocol, &mut builder)?;
+        }
         allow_but_dont_enforce_client_authentication(&mut builder);
         set_peer_verification_cert_store(trusted_client_certs, &mut builder)?;
         set_maximum_number_of_intermediate_ca_certificates(1, &mut builder);
@@ -46,6 +135,54 @@ mod acceptor {
         Ok(builder.build())
     }
 
+    /// Builds a TLS acceptor to establish TLS connections on the server side
+    /// with clients that do not authenticate, e.g. external monitoring
+    /// agents.
+    ///
+    /// The acceptor does not request a client certificate. By default it only
+    /// accepts TLS 1.3 with the configuration of `tls_acceptor`. If
+    /// `allow_tls12` is set, it additionally accepts TLS 1.2 with the cipher
+    /// suites ECDHE-ECDSA-AES128-GCM-SHA256 and ECDHE-ECDSA-AES256-GCM-SHA384
+    /// for clients that cannot do TLS 1.3.
+    ///
+    /// Node-to-node connections must not use this acceptor: they require
+    /// client authentication and TLS 1.3.
+    ///
+    /// # Errors
+    /// * `CreateTlsAcceptorError` if the creation of the acceptor failed
+    pub fn tls_acceptor_without_client_auth(
+        private_key: &PKey<Private>,
+        server_cert: &X509,
+        allow_tls12: bool,
+    ) -> Result<SslAcceptor, CreateTlsAcceptorError> {
+        let mut builder = if allow_tls12 {
+            SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())
+        } else {
+            SslAcceptor::mozilla_modern_v5(SslMethod::tls_server())
+        }
+        .expect("Failed to initialize the acceptor.");
+        restrict_tls_version_and_sig_algs(&mut builder);
+        set_cipher_suites(ALLOWED_CIPHER_SUITES, &mut builder)?;
+        if allow_tls12 {
+            allow_tls12_fallback(&mut builder);
+        }
+        builder.set_verify(SslVerifyMode::NONE);
+        set_private_key(private_key, server_cert, &mut builder)?;
+        set_certificate(server_cert, &mut builder)?;
+        check_private_key(server_cert, &mut builder)?;
+        Ok(builder.build())
+    }
+
+    fn allow_tls12_fallback(builder: &mut SslAcceptorBuilder) {
+        // The following calls are on hard-coded input and so we panic:
+        builder
+            .set_min_proto_version(TLS12_FALLBACK_MIN_PROTOCOL_VERSION)
+            .expect("Failed to set the minimum protocol version.");
+        builder
+            .set_cipher_list(TLS12_FALLBACK_CIPHER_LIST)
+            .expect("Failed to set the TLS 1.2 cipher list.");
+    }
+
     fn ensure_trusted_client_certs_not_empty(
         trusted_client_certs: &[X509],
     ) -> Result<(), CreateTlsAcceptorError> {
@@ -59,10 +196,70 @@ mod acceptor {
         Ok(())
     }
 
+    fn ensure_allowed_cipher_suites_not_empty(
+        allowed_cipher_suites: &[&str],
+    ) -> Result<(), CreateTlsAcceptorError> {
+        if allowed_cipher_suites.is_empty() {
+            return Err(CreateTlsAcceptorError {
+                description: "The allowed cipher suites must not be empty.".to_string(),
+                cert_der: None,
+                internal_error: None,
+            });
+        }
+        Ok(())
+    }
+
+    fn select_alpn_protocol(
+        alpn_protocol: &[u8],
+        builder: &mut SslAcceptorBuilder,
+    ) -> Result<(), CreateTlsAcceptorError> {
+        if alpn_protocol.is_empty() || alpn_protocol.len() > MAX_ALPN_PROTOCOL_LEN {
+            return Err(CreateTlsAcceptorError {
+                description: "The ALPN protocol must be between 1 and 255 bytes long.".to_string(),
+                cert_der: None,
+                internal_error: None,
+            });
+        }
+        // ALPN protocol lists are encoded as a sequence of length-prefixed
+        // protocol names, see https://tools.ietf.org/html/rfc7301#section-3.1
+        let mut server_protocols = vec![alpn_protocol.len() as u8];
+        server_protocols.extend_from_slice(alpn_protocol);
+        builder.set_alpn_select_callback(move |ssl, client_protocols| {
+            match select_next_proto(&server_protocols, client_protocols) {
+                Some(protocol) => Ok(protocol),
+                None => {
+                    ssl.set_ex_data(*NO_MATCHING_ALPN_PROTOCOL_INDEX, NoMatchingAlpnProtocol);
+                    Err(AlpnError::ALERT_FATAL)
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Returns whether the handshake on the connection `ssl` (accepted with
+    /// an acceptor built by `tls_acceptor_with_cipher_suites_and_alpn`) was
+    /// aborted because the client offered only ALPN protocols other than the
+    /// acceptor's ALPN protocol.
+    pub fn no_matching_alpn_protocol(ssl: &SslRef) -> bool {
+        ssl.ex_data(*NO_MATCHING_ALPN_PROTOCOL_INDEX).is_some()
+    }
+
     fn allow_but_dont_enforce_client_authentication(builder: &mut SslAcceptorBuilder) {
         // We do not set the `FAIL_IF_NO_PEER_CERT` flag since client authentication
         // should be allowed, but not enforced.
-        builder.set_verify(SslVerifyMode::PEER);
+        builder.set_verify_callback(SslVerifyMode::PEER, |preverify_ok, x509_ctx| {
+            // An expired client certificate would only surface as a generic
+            // handshake failure, so it is left to the caller to reject it.
+            // Expired CA certificates (at a depth > 0) are still rejected.
+            if !preverify_ok
+                && x509_ctx.error_depth() == 0
+                && x509_ctx.error().as_raw() == X509_V_ERR_CERT_HAS_EXPIRED
+            {
+                x509_ctx.set_error(X509VerifyResult::OK);
+                return true;
+            }
+            preverify_ok
+        });
     }
 
     /// A TLS acceptor couldn't be created.
@@ -101,7 +298,8 @@ mod connector {
     ) -> Result<ConnectConfiguration, CreateTlsConnectorError> {
         let mut builder = SslConnector::builder(SslMethod::tls_client())
             .expect("Failed to initialize connector.");
-        restrict_tls_version_and_cipher_suites_and_sig_algs(&mut builder);
+        restrict_tls_version_and_sig_algs(&mut builder);
+        set_cipher_suites(ALLOWED_CIPHER_SUITES, &mut builder)?;
         set_peer_verification_cert_store(vec![trusted_server_cert.clone()], &mut builder)?;
         set_most_restrictive_certificate_verification_depth(&mut builder);
         set_private_key(private_key, client_cert, &mut builder)?;
@@ -168,20 +366,30 @@ mod connector {
 mod context {
     use super::*;
 
-    pub fn restrict_tls_version_and_cipher_suites_and_sig_algs(builder: &mut SslContextBuilder) {
+    pub fn restrict_tls_version_and_sig_algs(builder: &mut SslContextBuilder) {
         // The following calls are on hard-coded input and so we panic:
         builder
             .set_min_proto_version(MIN_PROTOCOL_VERSION)
             .expect("Failed to set the minimum protocol version.");
-        builder
-            .set_ciphersuites(ALLOWED_CIPHER_SUITES)
-            .expect("Failed to set the ciphersuites.");
         // The list of allowed values for TLS 1.3 can be found in https://tools.ietf.org/html/rfc8446#appendix-B.3.1.3
         builder
             .set_sigalgs_list(ALLOWED_SIGNATURE_ALGORITHMS)
             .expect("Failed to set the sigalgs list.");
     }
 
+    pub fn set_cipher_suites(
+        cipher_suites: &[&str],
+        builder: &mut SslContextBuilder,
+    ) -> Result<(), CreateTlsContextError> {
+        builder
+            .set_ciphersuites(&cipher_suites.join(":"))
+            .map_err(|e| CreateTlsContextError {
+                description: "Failed to set the ciphersuites.".to_string(),
+                cert_der: None,
+                internal_error: format!("{}", e),
+            })
+    }
+
     pub fn set_private_key(
         private_key: &PKey<Private>,
         cert: &X509,
@@ -3,12 +3,17 @@ use ic_crypto_test_utils::tls::x509_certificates::{
     ed25519_key_pair, generate_cert, generate_ed25519_cert, prime256v1_key_pair,
 };
 use openssl::hash::MessageDigest;
-use openssl::ssl::{SslAcceptor, SslVerifyMode};
+use openssl::ssl::{SslAcceptor, SslConnector, SslMethod, SslVerifyMode, SslVersion};
 use openssl::x509::X509;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
 
 mod acceptor {
     use super::*;
-    use crate::tls_acceptor;
+    use crate::{
+        tls_acceptor, tls_acceptor_with_cipher_suites, tls_acceptor_with_cipher_suites_and_alpn,
+        tls_acceptor_without_client_auth,
+    };
 
     #[test]
     fn should_allow_client_authentication() {
@@ -82,6 +87,84 @@ mod acceptor {
         );
     }
 
+    #[test]
+    fn should_return_error_if_allowed_cipher_suites_empty() {
+        let (cert_key_pair, server_cert) = generate_ed25519_cert();
+
+        let error = tls_acceptor_with_cipher_suites(
+            &cert_key_pair,
+            &server_cert,
+            dummy_trusted_client_certs(),
+            &[],
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            error.description,
+            "The allowed cipher suites must not be empty.".to_string()
+        );
+    }
+
+    #[test]
+    fn should_return_error_if_allowed_cipher_suite_unknown() {
+        let (cert_key_pair, server_cert) = generate_ed25519_cert();
+
+        let error = tls_acceptor_with_cipher_suites(
+            &cert_key_pair,
+            &server_cert,
+            dummy_trusted_client_certs(),
+            &["TLS_UNKNOWN_CIPHER_SUITE"],
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            error.description,
+            "Failed to set the ciphersuites.".to_string()
+        );
+    }
+
+    #[test]
+    fn should_return_error_if_alpn_protocol_empty() {
+        let (cert_key_pair, server_cert) = generate_ed25519_cert();
+
+        let error = tls_acceptor_with_cipher_suites_and_alpn(
+            &cert_key_pair,
+            &server_cert,
+            dummy_trusted_client_certs(),
+            &["TLS_AES_128_GCM_SHA256"],
+            Some(b""),
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            error.description,
+            "The ALPN protocol must be between 1 and 255 bytes long.".to_string()
+        );
+    }
+
+    #[test]
+    fn should_return_error_if_alpn_protocol_too_long() {
+        let (cert_key_pair, server_cert) = generate_ed25519_cert();
+
+        let error = tls_acceptor_with_cipher_suites_and_alpn(
+            &cert_key_pair,
+            &server_cert,
+            dummy_trusted_client_certs(),
+            &["TLS_AES_128_GCM_SHA256"],
+            Some(&[b'a'; 256]),
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            error.description,
+            "The ALPN protocol must be between 1 and 255 bytes long.".to_string()
+        );
+    }
+
     #[test]
     fn should_return_error_if_certificate_does_not_match_private_key() {
         let (_cert_key_pair, server_cert) = generate_ed25519_cert();
@@ -120,6 +203,43 @@ mod acceptor {
         );
     }
 
+    #[test]
+    fn should_not_request_client_certificate_without_client_auth() {
+        let (key_pair, server_cert) = generate_ed25519_cert();
+
+        let acceptor = tls_acceptor_without_client_auth(&key_pair, &server_cert, false).unwrap();
+
+        assert_eq!(acceptor.context().verify_mode(), SslVerifyMode::NONE);
+    }
+
+    #[test]
+    fn should_negotiate_tls_12_without_client_auth_if_allowed() {
+        let (key_pair, server_cert) = generate_ed25519_cert();
+        let acceptor = tls_acceptor_without_client_auth(&key_pair, &server_cert, true).unwrap();
+
+        let negotiated_version = tls_12_client_handshake(acceptor).unwrap();
+
+        assert_eq!(negotiated_version, SslVersion::TLS1_2);
+    }
+
+    #[test]
+    fn should_fail_tls_12_handshake_without_client_auth_if_not_allowed() {
+        let (key_pair, server_cert) = generate_ed25519_cert();
+        let acceptor = tls_acceptor_without_client_auth(&key_pair, &server_cert, false).unwrap();
+
+        assert!(tls_12_client_handshake(acceptor).is_err());
+    }
+
+    #[test]
+    fn should_still_negotiate_tls_13_without_client_auth_if_tls_12_allowed() {
+        let (key_pair, server_cert) = generate_ed25519_cert();
+        let acceptor = tls_acceptor_without_client_auth(&key_pair, &server_cert, true).unwrap();
+
+        let negotiated_version = client_handshake(acceptor, SslVersion::TLS1_3).unwrap();
+
+        assert_eq!(negotiated_version, SslVersion::TLS1_3);
+    }
+
     #[test]
     #[should_panic(expected = "extra chain certs must not be null")]
     fn should_not_add_extra_chain_certs() {
@@ -138,6 +258,40 @@ mod acceptor {
         let some_cert = generate_cert(&key_pair, MessageDigest::null());
         vec![some_cert]
     }
+
+    fn tls_12_client_handshake(acceptor: SslAcceptor) -> Result<SslVersion, String> {
+        client_handshake(acceptor, SslVersion::TLS1_2)
+    }
+
+    /// Performs a handshake between `acceptor` and a client that does not
+    /// authenticate and supports TLS versions up to `max_version`. Returns
+    /// the negotiated version.
+    fn client_handshake(
+        acceptor: SslAcceptor,
+        max_version: SslVersion,
+    ) -> Result<SslVersion, String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let _ = acceptor.accept(stream);
+        });
+
+        let mut builder = SslConnector::builder(SslMethod::tls_client()).unwrap();
+        builder.set_max_proto_version(Some(max_version)).unwrap();
+        builder.set_verify(SslVerifyMode::NONE);
+        let connector = builder.build();
+        let stream = TcpStream::connect(server_addr).unwrap();
+        let result = connector
+            .configure()
+            .unwrap()
+            .verify_hostname(false)
+            .connect("localhost", stream)
+            .map(|stream| stream.ssl().version2().unwrap())
+            .map_err(|e| format!("{}", e));
+        server.join().unwrap();
+        result
+    }
 }
 
 mod connector {
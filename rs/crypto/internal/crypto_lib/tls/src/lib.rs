@@ -13,5 +13,7 @@ pub mod keygen;
 
 mod connection;
 pub use connection::{
-    tls_acceptor, tls_connector, CreateTlsAcceptorError, CreateTlsConnectorError,
+    no_matching_alpn_protocol, tls_acceptor, tls_acceptor_with_cipher_suites,
+    tls_acceptor_with_cipher_suites_and_alpn, tls_acceptor_without_client_auth, tls_connector,
+    CreateTlsAcceptorError, CreateTlsConnectorError,
 };
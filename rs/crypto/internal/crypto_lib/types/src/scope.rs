@@ -28,7 +28,17 @@ mod tests;
 ///   functionality may include scopes for delegated keys, canister keys and
 ///   others and that design space must not be restricted.
 #[derive(
-    Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, IntoStaticStr, EnumDiscriminants,
+    Copy,
+    Clone,
+    Debug,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Serialize,
+    Deserialize,
+    IntoStaticStr,
+    EnumDiscriminants,
 )]
 // Create an enum `ScopeSchemeNames` used for string matching.
 #[strum_discriminants(name(ScopeSchemeNames))]
@@ -42,6 +52,10 @@ pub enum Scope {
     /// labels has no syntactic structure.  Integers may be used in place of
     /// strings.  There is no nesting.
     Const(ConstScope),
+    /// Short-lived keys, e.g. session keys, that must not survive a restart.
+    ///
+    /// Keys in this scope are kept in memory only and are never persisted.
+    Ephemeral,
 }
 
 /// A serialisation to String.  This is guaranteed to be stable and SHOULD be
@@ -55,6 +69,7 @@ impl std::fmt::Display for Scope {
         let scheme_name: &'static str = self.into();
         match self {
             Scope::Const(variant) => write!(f, "{}:{}", scheme_name, variant),
+            Scope::Ephemeral => write!(f, "{}", scheme_name),
         }
     }
 }
@@ -66,6 +81,8 @@ impl std::str::FromStr for Scope {
         let scheme = ScopeSchemeNames::from_str(&s[0..boundary])?;
         match scheme {
             ScopeSchemeNames::Const => Ok(Scope::Const(ConstScope::from_str(&s[boundary + 1..])?)),
+            ScopeSchemeNames::Ephemeral if boundary == s.len() => Ok(Scope::Ephemeral),
+            ScopeSchemeNames::Ephemeral => Err(::strum::ParseError::VariantNotFound),
         }
     }
 }
@@ -83,7 +100,18 @@ impl From<&Scope> for String {
 ///
 /// Adding a variant here requires no further code changes.
 #[derive(
-    Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, IntoStaticStr, EnumString, EnumIter,
+    Copy,
+    Clone,
+    Debug,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Serialize,
+    Deserialize,
+    IntoStaticStr,
+    EnumString,
+    EnumIter,
 )]
 pub enum ConstScope {
     /// Placeholders, used for testing.
@@ -27,4 +27,10 @@ fn serialisation_is_stable() {
     // There are more variants but they share the exact same code paths, so if
     // the above are correct, it is highly likely that the remainder will be
     // correct as well.
+    test_string_representation(&Scope::Ephemeral, "Ephemeral");
+}
+
+#[test]
+fn should_not_parse_ephemeral_scope_with_label() {
+    assert!(Scope::from_str("Ephemeral:Test0").is_err());
 }
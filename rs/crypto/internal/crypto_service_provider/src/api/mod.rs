@@ -9,6 +9,7 @@ pub use keygen::{CspKeyGenerator, CspSecretKeyStoreChecker, NodePublicKeyData};
 pub use sign::CspSigner;
 pub use threshold::{
     threshold_sign_error::CspThresholdSignError, CspSecretKeyInjector,
-    DistributedKeyGenerationCspClient, NiDkgCspClient, ThresholdSignatureCspClient,
+    DistributedKeyGenerationCspClient, NiDkgCspClient, ThresholdRetentionReport,
+    ThresholdSignatureCspClient,
 };
 pub use tls_stub::{tls_errors, CspTlsClientHandshake, CspTlsServerHandshake};
@@ -997,6 +997,24 @@ pub trait NiDkgCspClient {
     /// public coefficients.  If this method is requested to retain a key that
     /// is not in the secret key store, that key will be ignored.
     fn retain_threshold_keys_if_present(&self, active_keys: BTreeSet<CspPublicCoefficients>);
+
+    /// Like `retain_threshold_keys_if_present`, but additionally reports
+    /// which of the threshold keys in the secret key store were retained and
+    /// which were removed.
+    fn retain_threshold_keys_if_present_with_report(
+        &self,
+        active_keys: BTreeSet<CspPublicCoefficients>,
+    ) -> ThresholdRetentionReport;
+}
+
+/// The outcome of retaining NiDKG threshold keys.
+///
+/// Both lists contain IDs of keys in the NiDKG threshold scope only, sorted by
+/// `KeyId`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ThresholdRetentionReport {
+    pub retained: Vec<KeyId>,
+    pub removed: Vec<KeyId>,
 }
 
 // TODO (CRP-309): Remove CspSecretKeyInjector once DKG is fully implemented
@@ -1,7 +1,7 @@
 use crate::api::tls_stub::tls_errors::{CspTlsClientHandshakeError, CspTlsServerHandshakeError};
 use crate::tls_stub::cert_chain::CspCertificateChain;
 use async_trait::async_trait;
-use ic_crypto_tls_interfaces::TlsStream;
+use ic_crypto_tls_interfaces::{TlsCipherSuite, TlsStream};
 use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
 use openssl::x509::X509;
 use tokio::net::TcpStream;
@@ -23,8 +23,11 @@ pub trait CspTlsServerHandshake {
     /// For the handshake, the server uses the following configuration:
     /// * Minimum protocol version: TLS 1.3
     /// * Supported signature algorithms: ed25519
-    /// * Allowed cipher suites: TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384
+    /// * Allowed cipher suites: `allowed_cipher_suites`
     /// * Client authentication: optional, with ed25519 certificate
+    /// * ALPN: if `alpn_protocol` is given, it is selected if the client
+    ///   offers it, and the handshake fails with `NoMatchingAlpnProtocol` if
+    ///   the client offers only other protocols
     ///
     /// The given `tcp_stream` is consumed. If an error is returned, the TCP
     /// connection is therefore dropped.
@@ -44,9 +47,13 @@ pub trait CspTlsServerHandshake {
     /// * CspTlsServerHandshakeError::MalformedClientCertificate if one of the
     ///   `trusted_client_certs` is malformed.
     /// * CspTlsServerHandshakeError::CreateAcceptorError if there is a problem
-    ///   configuring the server for accepting connections from clients.
+    ///   configuring the server for accepting connections from clients, e.g.
+    ///   if `allowed_cipher_suites` or `alpn_protocol` is empty.
     /// * CspTlsServerHandshakeError::HandshakeError if there is an error during
     ///   the TLS handshake, or the handshake fails.
+    /// * CspTlsServerHandshakeError::NoMatchingAlpnProtocol if an
+    ///   `alpn_protocol` is given, but the client offered only other ALPN
+    ///   protocols.
     /// * CspTlsServerHandshakeError::SecretKeyNotFound if the secret key
     ///   corresponding to `self_cert` cannot be found in the secret key store.
     /// * CspTlsServerHandshakeError::MalformedSecretKey if the secret key
@@ -59,7 +66,48 @@ pub trait CspTlsServerHandshake {
         tcp_stream: TcpStream,
         self_cert: X509PublicKeyCert,
         trusted_client_certs: Vec<X509PublicKeyCert>,
+        allowed_cipher_suites: Vec<TlsCipherSuite>,
+        alpn_protocol: Option<Vec<u8>>,
     ) -> Result<(TlsStream, Option<CspCertificateChain>), CspTlsServerHandshakeError>;
+
+    /// Transforms a TCP stream into a TLS stream by performing a TLS server
+    /// handshake with a client that does not authenticate.
+    ///
+    /// The `self_cert` is used as server certificate and the corresponding
+    /// private key must be in the secret key store. The server does not
+    /// request a client certificate.
+    ///
+    /// For the handshake, the server uses the following configuration:
+    /// * Minimum protocol version: TLS 1.3, or TLS 1.2 if `allow_tls12` is set
+    /// * Supported signature algorithms: ed25519
+    /// * Allowed cipher suites: TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384,
+    ///   and for TLS 1.2 ECDHE-ECDSA-AES128-GCM-SHA256,
+    ///   ECDHE-ECDSA-AES256-GCM-SHA384
+    /// * Client authentication: none
+    ///
+    /// The given `tcp_stream` is consumed. If an error is returned, the TCP
+    /// connection is therefore dropped.
+    ///
+    /// # Errors
+    /// * CspTlsServerHandshakeError::MalformedSelfCertificate if `self_cert` is
+    ///   malformed.
+    /// * CspTlsServerHandshakeError::CreateAcceptorError if there is a problem
+    ///   configuring the server for accepting connections from clients.
+    /// * CspTlsServerHandshakeError::HandshakeError if there is an error during
+    ///   the TLS handshake, or the handshake fails.
+    /// * CspTlsServerHandshakeError::SecretKeyNotFound if the secret key
+    ///   corresponding to `self_cert` cannot be found in the secret key store.
+    /// * CspTlsServerHandshakeError::MalformedSecretKey if the secret key
+    ///   corresponding to `self_cert` is malformed in the secret key store.
+    /// * CspTlsServerHandshakeError::WrongSecretKeyType if the secret key
+    ///   corresponding to `self_cert` has the wrong type in the secret key
+    ///   store.
+    async fn perform_tls_server_handshake_without_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        self_cert: X509PublicKeyCert,
+        allow_tls12: bool,
+    ) -> Result<TlsStream, CspTlsServerHandshakeError>;
 }
 
 /// A trait that exposes TLS client-side handshaking
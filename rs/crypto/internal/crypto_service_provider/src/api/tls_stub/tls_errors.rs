@@ -100,6 +100,10 @@ pub enum CspTlsServerHandshakeError {
     HandshakeError {
         internal_error: String,
     },
+    PeerCertificateExpired {
+        not_after: String,
+    },
+    NoMatchingAlpnProtocol,
     SecretKeyNotFound,
     MalformedSecretKey,
     WrongSecretKeyType,
@@ -129,6 +133,12 @@ impl From<CspTlsServerHandshakeError> for TlsServerHandshakeError {
             CspTlsServerHandshakeError::HandshakeError { internal_error } => {
                 TlsServerHandshakeError::HandshakeError { internal_error }
             }
+            CspTlsServerHandshakeError::PeerCertificateExpired { not_after } => {
+                TlsServerHandshakeError::PeerCertificateExpired { not_after }
+            }
+            CspTlsServerHandshakeError::NoMatchingAlpnProtocol => {
+                TlsServerHandshakeError::NoMatchingAlpnProtocol
+            }
             CspTlsServerHandshakeError::SecretKeyNotFound => {
                 // This would be a problem in the node's setup, so we panic:
                 panic!("{}The secret key was not found", panic_prefix);
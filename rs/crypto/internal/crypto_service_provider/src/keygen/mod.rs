@@ -118,6 +118,9 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> Csp<R, S> {
             Err(SecretKeyStoreError::DuplicateKeyId(key_id)) => {
                 panic!("A key with ID {} has already been inserted", key_id);
             }
+            Err(err) => {
+                panic!("Could not insert key: {}", err);
+            }
         };
     }
 }
@@ -0,0 +1,269 @@
+//! Secret key store that encrypts its data file at rest
+use crate::secret_key_store::proto_store::ProtoSecretKeyStore;
+use crate::secret_key_store::{
+    Scope, SecretKeyStore, SecretKeyStoreError, SecretKeyStorePersistenceError,
+};
+use crate::types::CspSecretKey;
+use ic_logger::ReplicaLogger;
+use ic_types::crypto::KeyId;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use std::collections::BTreeMap;
+use std::path::Path;
+use zeroize::Zeroize;
+
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+/// Additional authenticated data binding the ciphertext to its purpose.
+const SKS_DATA_AAD: &[u8] = b"ic-crypto-sks-data-v1";
+
+/// A 256-bit AES-GCM key with which the data file of an
+/// `EncryptedSecretKeyStore` is encrypted.
+#[derive(Zeroize)]
+#[zeroize(drop)]
+pub struct MasterKey([u8; 32]);
+
+impl MasterKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// Encrypts `plaintext` under a fresh random nonce. The result is the
+    /// nonce, followed by the ciphertext, followed by the tag.
+    pub(crate) fn encrypt(
+        &self,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, SecretKeyStorePersistenceError> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        openssl::rand::rand_bytes(&mut nonce).map_err(|err| {
+            SecretKeyStorePersistenceError::SerializationError(format!(
+                "failed to generate nonce: {}",
+                err
+            ))
+        })?;
+        let mut tag = [0u8; TAG_SIZE];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(&nonce),
+            SKS_DATA_AAD,
+            plaintext,
+            &mut tag,
+        )
+        .map_err(|err| {
+            SecretKeyStorePersistenceError::SerializationError(format!(
+                "failed to encrypt SKS data: {}",
+                err
+            ))
+        })?;
+        let mut encrypted = Vec::with_capacity(NONCE_SIZE + ciphertext.len() + TAG_SIZE);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        encrypted.extend_from_slice(&tag);
+        Ok(encrypted)
+    }
+
+    /// Decrypts data produced by `encrypt`. Fails if the data was not
+    /// encrypted with this key or was tampered with.
+    pub(crate) fn decrypt(
+        &self,
+        encrypted: &[u8],
+    ) -> Result<Vec<u8>, SecretKeyStorePersistenceError> {
+        if encrypted.len() < NONCE_SIZE + TAG_SIZE {
+            return Err(SecretKeyStorePersistenceError::SerializationError(
+                "encrypted SKS data is too short".to_string(),
+            ));
+        }
+        let (nonce, rest) = encrypted.split_at(NONCE_SIZE);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_SIZE);
+        decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.0,
+            Some(nonce),
+            SKS_DATA_AAD,
+            ciphertext,
+            tag,
+        )
+        .map_err(|err| {
+            SecretKeyStorePersistenceError::SerializationError(format!(
+                "failed to decrypt SKS data: {}",
+                err
+            ))
+        })
+    }
+}
+
+/// A secret key store that behaves like the `ProtoSecretKeyStore`, but
+/// encrypts the serialized protobuf with AES-GCM under a master key before
+/// writing it to the filesystem.
+///
+/// The store uses its own data file, so a directory may hold both an
+/// encrypted and a plaintext store.
+pub struct EncryptedSecretKeyStore {
+    store: ProtoSecretKeyStore,
+}
+
+impl EncryptedSecretKeyStore {
+    /// Opens the store in `dir`, decrypting the existing data file, if any,
+    /// with `master_key`.
+    ///
+    /// Returns a `SerializationError` if the data file cannot be decrypted,
+    /// e.g. because it was written with a different master key.
+    pub fn open(
+        dir: &Path,
+        master_key: MasterKey,
+        logger: Option<ReplicaLogger>,
+    ) -> Result<Self, SecretKeyStorePersistenceError> {
+        Ok(Self {
+            store: ProtoSecretKeyStore::open_encrypted(dir, logger, master_key)?,
+        })
+    }
+}
+
+impl SecretKeyStore for EncryptedSecretKeyStore {
+    fn insert(
+        &mut self,
+        id: KeyId,
+        key: CspSecretKey,
+        scope: Option<Scope>,
+    ) -> Result<(), SecretKeyStoreError> {
+        self.store.insert(id, key, scope)
+    }
+
+    fn insert_batch(
+        &mut self,
+        entries: Vec<(KeyId, CspSecretKey, Option<Scope>)>,
+    ) -> Result<(), SecretKeyStoreError> {
+        self.store.insert_batch(entries)
+    }
+
+    fn get(&self, id: &KeyId) -> Option<CspSecretKey> {
+        self.store.get(id)
+    }
+
+    fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>> {
+        self.store.get_scope(id)
+    }
+
+    fn contains(&self, id: &KeyId) -> bool {
+        self.store.contains(id)
+    }
+
+    fn remove(&mut self, id: &KeyId) -> bool {
+        self.store.remove(id)
+    }
+
+    fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId> {
+        self.store.keys_in_scope(scope)
+    }
+
+    fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize> {
+        self.store.count_by_scope()
+    }
+
+    fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError> {
+        self.store.clear_scope(scope)
+    }
+
+    fn drop_ephemeral(&mut self) {
+        self.store.drop_ephemeral()
+    }
+
+    fn retain<F>(&mut self, filter: F, scope: Scope)
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.store.retain(filter, scope)
+    }
+
+    fn try_retain<F>(&mut self, filter: F, scope: Scope) -> Result<(), SecretKeyStoreError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.store.try_retain(filter, scope)
+    }
+
+    fn retain_then_insert<F>(
+        &mut self,
+        filter: F,
+        scope: Scope,
+        new: (KeyId, CspSecretKey, Option<Scope>),
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.store.retain_then_insert(filter, scope, new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils;
+    use super::*;
+    use ic_crypto_internal_csp_test_utils::files::mk_temp_dir_with_permissions;
+    use std::fs;
+
+    const MASTER_KEY: [u8; 32] = [42; 32];
+
+    #[test]
+    fn should_retrieve_keys_after_reopening() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let (key_id, secret_key) = (test_utils::make_key_id(1), test_utils::make_secret_key(1));
+        let mut store =
+            EncryptedSecretKeyStore::open(dir.path(), MasterKey::new(MASTER_KEY), None).unwrap();
+        store.insert(key_id, secret_key.clone(), None).unwrap();
+
+        let reopened_store =
+            EncryptedSecretKeyStore::open(dir.path(), MasterKey::new(MASTER_KEY), None).unwrap();
+
+        assert_eq!(reopened_store.get(&key_id), Some(secret_key));
+    }
+
+    #[test]
+    fn should_fail_to_open_with_wrong_master_key() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let mut store =
+            EncryptedSecretKeyStore::open(dir.path(), MasterKey::new(MASTER_KEY), None).unwrap();
+        store
+            .insert(
+                test_utils::make_key_id(1),
+                test_utils::make_secret_key(1),
+                None,
+            )
+            .unwrap();
+
+        let result = EncryptedSecretKeyStore::open(dir.path(), MasterKey::new([43; 32]), None);
+
+        assert!(matches!(
+            result,
+            Err(SecretKeyStorePersistenceError::SerializationError(_))
+        ));
+    }
+
+    #[test]
+    fn should_not_write_plaintext_proto_file() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let mut store =
+            EncryptedSecretKeyStore::open(dir.path(), MasterKey::new(MASTER_KEY), None).unwrap();
+        store
+            .insert(
+                test_utils::make_key_id(1),
+                test_utils::make_secret_key(1),
+                None,
+            )
+            .unwrap();
+
+        let file_names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(file_names, vec!["sks_data.pb.enc"]);
+    }
+
+    #[test]
+    fn should_retain_expected_keys() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let store =
+            EncryptedSecretKeyStore::open(dir.path(), MasterKey::new(MASTER_KEY), None).unwrap();
+        test_utils::should_retain_expected_keys(store);
+    }
+}
@@ -4,15 +4,21 @@ use crate::types::CspSecretKey;
 pub use ic_crypto_internal_types::scope;
 use ic_types::crypto::KeyId;
 pub use scope::Scope;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
+use zeroize::Zeroize;
 
 // Implementations
+pub mod encrypted_store;
 pub mod proto_store;
 pub mod volatile_store;
 
 #[cfg(test)]
 pub mod test_utils;
 
+#[cfg(test)]
+mod tests;
+
 /// A store for secret key material
 ///
 /// If errors occur regarding reading from or writing to the underlying
@@ -32,6 +38,28 @@ pub trait SecretKeyStore: Send + Sync {
         scope: Option<Scope>,
     ) -> Result<(), SecretKeyStoreError>;
 
+    /// Adds several keys to the store at once.
+    ///
+    /// Either all keys are inserted or none is: if any `id` in `entries`
+    /// already exists in the store, or occurs more than once in `entries`,
+    /// a `DuplicateKeyId` error identifying the colliding `id` is returned
+    /// and the store is left unchanged.
+    fn insert_batch(
+        &mut self,
+        entries: Vec<(KeyId, CspSecretKey, Option<Scope>)>,
+    ) -> Result<(), SecretKeyStoreError> {
+        let mut batch_ids = HashSet::new();
+        for (id, _, _) in &entries {
+            if self.contains(id) || !batch_ids.insert(*id) {
+                return Err(SecretKeyStoreError::DuplicateKeyId(*id));
+            }
+        }
+        for (id, key, scope) in entries {
+            self.insert(id, key, scope)?;
+        }
+        Ok(())
+    }
+
     /// Inserts a key with a given `id` into the store, replacing any existing
     /// entry.
     ///
@@ -56,15 +84,51 @@ pub trait SecretKeyStore: Send + Sync {
     // reference is constructive.  Also, the key should be scrubbed on removal.
     fn get(&self, id: &KeyId) -> Option<CspSecretKey>;
 
+    /// Retrieves the scope of the key with the given `id`.
+    ///
+    /// Returns `None` if the store does not contain a key with the given `id`,
+    /// and `Some(None)` if the key is stored without a scope.
+    fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>>;
+
     /// Checks if the store contains a key with the given `id`.
     fn contains(&self, id: &KeyId) -> bool;
 
     /// Removes the key with the given `id` from the store.
     ///
     /// The return value indicates whether a key with the given `id` was
-    /// previously contained and removed.
+    /// previously contained and removed. The key is scrubbed on removal.
     fn remove(&mut self, id: &KeyId) -> bool;
 
+    /// Returns the IDs of all keys in the given `scope`, sorted by `KeyId`.
+    ///
+    /// Only the IDs are returned, never the secret key material, so callers
+    /// can inspect the contents of a scope (e.g. before calling `retain`)
+    /// without copying secret keys around.
+    fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId>;
+
+    /// Returns the number of keys in the store, grouped by scope.
+    ///
+    /// Keys without a scope are counted under `None`.
+    fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize>;
+
+    /// Removes all keys in the given `scope` and returns how many were
+    /// removed.
+    ///
+    /// Keys without a scope or in other scopes are left untouched. The
+    /// removal is persisted in a single write: if an error occurs, the store
+    /// is left unchanged.
+    fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError>;
+
+    /// Removes all keys in `Scope::Ephemeral`.
+    ///
+    /// Ephemeral keys are never persisted, so this only affects the keys held
+    /// in memory.
+    fn drop_ephemeral(&mut self) {
+        for id in self.keys_in_scope(Scope::Ephemeral) {
+            self.remove(&id);
+        }
+    }
+
     /// Keeps only entries in a scope for which the filter function returns
     /// `true` and removes the rest.
     ///
@@ -94,12 +158,72 @@ pub trait SecretKeyStore: Send + Sync {
     {
         unimplemented!()
     }
+
+    /// Like `retain`, but does not panic if the filter panics.
+    ///
+    /// The filter is evaluated for all entries in the `scope` inside
+    /// `std::panic::catch_unwind` before any entry is removed. If the filter
+    /// panics, a `FilterPanicked` error is returned and the store is left
+    /// unchanged. Note that this requires `panic="unwind"`: with
+    /// `panic="abort"` a panicking filter still aborts the process.
+    ///
+    /// # Logs
+    /// Implementations SHOULD log the KeyId of any deleted keys
+    fn try_retain<F>(&mut self, filter: F, scope: Scope) -> Result<(), SecretKeyStoreError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool;
+
+    /// Atomically applies `retain(filter, scope)` and then inserts the `new`
+    /// key.
+    ///
+    /// Both changes are persisted together: if an error occurs, the store is
+    /// left in the state it had before the call. This is useful e.g. for key
+    /// rotation, where stale keys are to be removed and a fresh key inserted
+    /// without ever leaving the store without an active key.
+    ///
+    /// If the `new` key's ID is still in the store after applying the filter,
+    /// a `DuplicateKeyId` error is returned. If the `new` key's scope would
+    /// exceed its limit after applying the filter, a `ScopeFull` error is
+    /// returned.
+    ///
+    /// # Panics
+    /// This MAY panic if the predicate panics, see `retain`.
+    fn retain_then_insert<F>(
+        &mut self,
+        filter: F,
+        scope: Scope,
+        new: (KeyId, CspSecretKey, Option<Scope>),
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool;
+}
+
+/// Removes the key with the given `id` from `keys`, scrubbing it first.
+///
+/// The key is zeroized in place: `HashMap::remove` moves the key out of the
+/// map, so zeroizing it only when it is dropped would leave a copy of the
+/// secret bytes in the map's storage. All store implementations must remove
+/// keys through this function.
+pub(crate) fn remove_scrubbed<K: Zeroize>(
+    keys: &mut HashMap<KeyId, (K, Option<Scope>)>,
+    id: &KeyId,
+) -> bool {
+    match keys.get_mut(id) {
+        Some((secret_key, _scope)) => {
+            secret_key.zeroize();
+            keys.remove(id);
+            true
+        }
+        None => false,
+    }
 }
 
 /// Errors that can occur while interacting with the secret key store
 #[derive(Clone, Debug)]
 pub enum SecretKeyStoreError {
     DuplicateKeyId(KeyId),
+    ScopeFull { scope: Scope, max: usize },
+    FilterPanicked,
 }
 
 impl std::error::Error for SecretKeyStoreError {}
@@ -110,6 +234,47 @@ impl fmt::Display for SecretKeyStoreError {
             SecretKeyStoreError::DuplicateKeyId(key_id) => {
                 write!(f, "Key with ID {} already exists in the key store", key_id)
             }
+            SecretKeyStoreError::ScopeFull { scope, max } => write!(
+                f,
+                "Scope {} already contains the maximum number of {} keys",
+                scope, max
+            ),
+            SecretKeyStoreError::FilterPanicked => write!(
+                f,
+                "The filter function panicked, the key store was left unchanged"
+            ),
+        }
+    }
+}
+
+/// Errors that can occur while persisting changes to the secret key store
+#[derive(Clone, Debug)]
+pub enum SecretKeyStorePersistenceError {
+    DuplicateKeyId(KeyId),
+    SerializationError(String),
+    IoError(String),
+    ScopeFull { scope: Scope, max: usize },
+}
+
+impl std::error::Error for SecretKeyStorePersistenceError {}
+
+impl fmt::Display for SecretKeyStorePersistenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretKeyStorePersistenceError::DuplicateKeyId(key_id) => {
+                write!(f, "Key with ID {} already exists in the key store", key_id)
+            }
+            SecretKeyStorePersistenceError::SerializationError(msg) => {
+                write!(f, "Error serializing key store data: {}", msg)
+            }
+            SecretKeyStorePersistenceError::IoError(msg) => {
+                write!(f, "IO error persisting key store data: {}", msg)
+            }
+            SecretKeyStorePersistenceError::ScopeFull { scope, max } => write!(
+                f,
+                "Scope {} already contains the maximum number of {} keys",
+                scope, max
+            ),
         }
     }
 }
@@ -1,28 +1,44 @@
 //! Filesystem-backed secret key store
 #![allow(clippy::unwrap_used)]
-use crate::secret_key_store::{Scope, SecretKeyStore, SecretKeyStoreError};
+use crate::secret_key_store::encrypted_store::MasterKey;
+use crate::secret_key_store::{
+    remove_scrubbed, Scope, SecretKeyStore, SecretKeyStoreError, SecretKeyStorePersistenceError,
+};
 use crate::threshold::ni_dkg::{NIDKG_FS_SCOPE, NIDKG_THRESHOLD_SCOPE};
 use crate::types::CspSecretKey;
 use ic_crypto_internal_threshold_sig_bls12381::ni_dkg::groth20_bls12_381::types::convert_keyset_to_keyset_with_pop;
 use ic_crypto_internal_threshold_sig_bls12381::ni_dkg::types::CspFsEncryptionKeySet;
+use ic_crypto_sha256::Sha256;
 use ic_logger::{replica_logger::no_op_logger, warn, ReplicaLogger};
 use ic_types::crypto::KeyId;
 use parking_lot::RwLock;
 use prost::Message;
 use std::borrow::{Borrow, BorrowMut};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
 use std::fs;
 use std::io::ErrorKind;
 use std::os::unix::fs::PermissionsExt;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use zeroize::Zeroizing;
 
 const SKS_DATA_FILENAME: &str = "sks_data.pb";
 const TEMP_SKS_DATA_FILENAME: &str = "sks_data.pb.temp";
+const ENCRYPTED_SKS_DATA_FILENAME: &str = "sks_data.pb.enc";
+const TEMP_ENCRYPTED_SKS_DATA_FILENAME: &str = "sks_data.pb.enc.temp";
 const CURRENT_SKS_VERSION: u32 = 2;
 
+// SKS data files start with this magic, followed by the length (as u64 in
+// little endian) and the SHA-256 hash of the serialized protobuf, followed by
+// the protobuf itself. Legacy files without checksum start directly with the
+// protobuf, whose first byte can never be `S`, as that would start a group
+// with field number 10, which the SecretKeyStore proto does not have.
+const SKS_DATA_CHECKSUM_MAGIC: &[u8] = b"SKS1";
+const SKS_DATA_CHECKSUM_HEADER_SIZE: usize = SKS_DATA_CHECKSUM_MAGIC.len() + 8 + 32;
+
 // TODO(CRP-523): turn this to FromStr-trait once KeyId is not public.
 const KEY_ID_PREFIX: &str = "KeyId(0x";
 const KEY_ID_SUFFIX: &str = ")";
@@ -50,6 +66,46 @@ fn key_id_from_hex(key_id_hex: &str) -> KeyId {
     KeyId::from(bytes)
 }
 
+/// Prepends the checksum header to the serialized protobuf `proto_bytes`.
+fn prepend_checksum(proto_bytes: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(SKS_DATA_CHECKSUM_HEADER_SIZE + proto_bytes.len());
+    data.extend_from_slice(SKS_DATA_CHECKSUM_MAGIC);
+    data.extend_from_slice(&(proto_bytes.len() as u64).to_le_bytes());
+    data.extend_from_slice(&Sha256::hash(proto_bytes));
+    data.extend_from_slice(proto_bytes);
+    data
+}
+
+/// Returns the serialized protobuf contained in the SKS data, verifying its
+/// length and checksum. Legacy data without checksum header is returned as is.
+/// Truncated or corrupted data is reported as an `IoError`.
+fn strip_checksum(data: &[u8]) -> Result<&[u8], SecretKeyStorePersistenceError> {
+    if !data.starts_with(SKS_DATA_CHECKSUM_MAGIC) {
+        return Ok(data);
+    }
+    if data.len() < SKS_DATA_CHECKSUM_HEADER_SIZE {
+        return Err(SecretKeyStorePersistenceError::IoError(
+            "truncated SKS data: incomplete checksum header".to_string(),
+        ));
+    }
+    let (header, proto_bytes) = data.split_at(SKS_DATA_CHECKSUM_HEADER_SIZE);
+    let (length_bytes, checksum) = header[SKS_DATA_CHECKSUM_MAGIC.len()..].split_at(8);
+    let length = u64::from_le_bytes(length_bytes.try_into().unwrap());
+    if proto_bytes.len() as u64 != length {
+        return Err(SecretKeyStorePersistenceError::IoError(format!(
+            "length mismatch: expected {} bytes of SKS data, found {}",
+            length,
+            proto_bytes.len()
+        )));
+    }
+    if Sha256::hash(proto_bytes)[..] != checksum[..] {
+        return Err(SecretKeyStorePersistenceError::IoError(
+            "checksum mismatch".to_string(),
+        ));
+    }
+    Ok(proto_bytes)
+}
+
 /// The secret key store protobuf definitions
 // Include the prost-build generated registry protos.
 #[path = "../../gen/ic.crypto.v1.rs"]
@@ -58,42 +114,142 @@ pub mod pb;
 
 type SecretKeys = HashMap<KeyId, (CspSecretKey, Option<Scope>)>;
 
+/// The file in which the secret keys are persisted, and the master key the
+/// file's contents are encrypted with, if any.
+struct SksDataFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    master_key: Option<MasterKey>,
+}
+
+impl SksDataFile {
+    fn plaintext(dir: &Path) -> Self {
+        Self {
+            path: dir.join(SKS_DATA_FILENAME),
+            temp_path: dir.join(TEMP_SKS_DATA_FILENAME),
+            master_key: None,
+        }
+    }
+
+    fn encrypted(dir: &Path, master_key: MasterKey) -> Self {
+        Self {
+            path: dir.join(ENCRYPTED_SKS_DATA_FILENAME),
+            temp_path: dir.join(TEMP_ENCRYPTED_SKS_DATA_FILENAME),
+            master_key: Some(master_key),
+        }
+    }
+}
+
 /// A secret key store that persists data to the filesystem, using protobufs for
 /// serialization
 pub struct ProtoSecretKeyStore {
-    proto_file: PathBuf,
+    sks_data_file: SksDataFile,
     keys: Arc<RwLock<SecretKeys>>,
     logger: ReplicaLogger,
+    scope_limits: BTreeMap<Scope, usize>,
 }
 
 impl ProtoSecretKeyStore {
     /// Creates a database instance.
     pub fn open(dir: &Path, logger: Option<ReplicaLogger>) -> Self {
+        Self::open_with_scope_limits(dir, logger, BTreeMap::new())
+    }
+
+    /// Creates a database instance that holds at most `scope_limits[scope]`
+    /// keys in each scope listed in `scope_limits`.
+    ///
+    /// Inserting a key into a full scope fails with a `ScopeFull` error.
+    /// Keys without a scope and keys in unlisted scopes are not limited. The
+    /// limits are a safeguard against unbounded growth of the store and are
+    /// not checked for keys that are already stored when the store is opened.
+    pub fn open_with_scope_limits(
+        dir: &Path,
+        logger: Option<ReplicaLogger>,
+        scope_limits: BTreeMap<Scope, usize>,
+    ) -> Self {
+        Self::try_open(dir, logger, scope_limits, SksDataFile::plaintext(dir))
+            .unwrap_or_else(|e| panic!("Error reading SKS data: {}", e))
+    }
+
+    /// Creates a database instance whose data file is encrypted with
+    /// `master_key`, see `EncryptedSecretKeyStore`.
+    pub(crate) fn open_encrypted(
+        dir: &Path,
+        logger: Option<ReplicaLogger>,
+        master_key: MasterKey,
+    ) -> Result<Self, SecretKeyStorePersistenceError> {
+        Self::try_open(
+            dir,
+            logger,
+            BTreeMap::new(),
+            SksDataFile::encrypted(dir, master_key),
+        )
+    }
+
+    fn try_open(
+        dir: &Path,
+        logger: Option<ReplicaLogger>,
+        scope_limits: BTreeMap<Scope, usize>,
+        sks_data_file: SksDataFile,
+    ) -> Result<Self, SecretKeyStorePersistenceError> {
         Self::check_path(dir);
-        let proto_file = dir.join(SKS_DATA_FILENAME);
-        let secret_keys = match Self::read_sks_data_from_disk(&proto_file) {
+        let secret_keys = match Self::read_sks_data_from_disk(&sks_data_file)? {
             Some(sks_proto) => sks_proto,
             None => SecretKeys::new(),
         };
-        ProtoSecretKeyStore {
-            proto_file,
+        Ok(ProtoSecretKeyStore {
+            sks_data_file,
             keys: Arc::new(RwLock::new(secret_keys)),
             logger: logger.unwrap_or_else(no_op_logger),
+            scope_limits,
+        })
+    }
+
+    /// Checks that adding `additional` keys to `scope` does not exceed the
+    /// scope's limit, if any.
+    fn ensure_scope_has_room(
+        scope_limits: &BTreeMap<Scope, usize>,
+        keys: &SecretKeys,
+        scope: Scope,
+        additional: usize,
+    ) -> Result<(), SecretKeyStoreError> {
+        if let Some(&max) = scope_limits.get(&scope) {
+            let count = keys
+                .values()
+                .filter(|(_, maybe_scope)| *maybe_scope == Some(scope))
+                .count();
+            if count + additional > max {
+                return Err(SecretKeyStoreError::ScopeFull { scope, max });
+            }
         }
+        Ok(())
     }
 
-    fn read_sks_data_from_disk(sks_data_file: &Path) -> Option<SecretKeys> {
-        match fs::read(sks_data_file) {
+    fn read_sks_data_from_disk(
+        sks_data_file: &SksDataFile,
+    ) -> Result<Option<SecretKeys>, SecretKeyStorePersistenceError> {
+        match fs::read(&sks_data_file.path) {
             Ok(data) => {
-                let sks_pb = pb::SecretKeyStore::decode(&*data).expect("error parsing SKS data");
+                // The plaintext contains the secret keys, so it is scrubbed
+                // on all paths once it has been parsed.
+                let data = match &sks_data_file.master_key {
+                    Some(master_key) => Zeroizing::new(master_key.decrypt(&data)?),
+                    None => Zeroizing::new(data),
+                };
+                let sks_pb = pb::SecretKeyStore::decode(strip_checksum(&data)?).map_err(|err| {
+                    SecretKeyStorePersistenceError::SerializationError(format!(
+                        "error parsing SKS data: {}",
+                        err
+                    ))
+                })?;
                 let keys = ProtoSecretKeyStore::migrate_to_current_version(sks_pb);
-                Some(keys)
+                Ok(Some(keys))
             }
             Err(err) => {
                 if err.kind() == ErrorKind::NotFound {
-                    None
+                    Ok(None)
                 } else {
-                    panic!("Error reading SKS data: {}", err)
+                    Err(SecretKeyStorePersistenceError::IoError(err.to_string()))
                 }
             }
         }
@@ -197,6 +353,10 @@ impl ProtoSecretKeyStore {
         let mut sks_proto = pb::SecretKeyStore::default();
         sks_proto.version = CURRENT_SKS_VERSION;
         for (key_id, (csp_key, maybe_scope)) in secret_keys {
+            if *maybe_scope == Some(Scope::Ephemeral) {
+                // Ephemeral keys must not survive a restart.
+                continue;
+            }
             let key_id_hex = key_id_to_hex(key_id);
             let key_as_cbor = serde_cbor::to_vec(&csp_key)
                 .unwrap_or_else(|_| panic!("Error serializing key with ID {}", key_id));
@@ -215,20 +375,141 @@ impl ProtoSecretKeyStore {
         sks_proto
     }
 
-    fn write_secret_keys_to_disk(sks_data_file: &Path, secret_keys: &SecretKeys) {
-        let mut tmp_data_file = sks_data_file.to_owned();
-        tmp_data_file.set_file_name(TEMP_SKS_DATA_FILENAME);
+    fn write_secret_keys_to_disk(sks_data_file: &SksDataFile, secret_keys: &SecretKeys) {
+        ProtoSecretKeyStore::try_write_secret_keys_to_disk(sks_data_file, secret_keys)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_write_secret_keys_to_disk(
+        sks_data_file: &SksDataFile,
+        secret_keys: &SecretKeys,
+    ) -> Result<(), SecretKeyStorePersistenceError> {
         let sks_proto = ProtoSecretKeyStore::secret_keys_to_sks_proto(secret_keys);
-        let mut buf = Vec::new();
-        match sks_proto.encode(&mut buf) {
-            Ok(_) => match fs::write(&tmp_data_file, &buf) {
-                Ok(_) => {
-                    fs::rename(&tmp_data_file, sks_data_file).expect("Could not update SKS file.")
+        // The serialized keys are scrubbed on all paths, including errors.
+        // The buffer is allocated up front so that encoding does not leave
+        // copies behind when it grows.
+        let mut buf = Zeroizing::new(Vec::with_capacity(sks_proto.encoded_len()));
+        sks_proto
+            .encode(&mut *buf)
+            .map_err(|err| SecretKeyStorePersistenceError::SerializationError(err.to_string()))?;
+        let plaintext = Zeroizing::new(prepend_checksum(&buf));
+        let data = match &sks_data_file.master_key {
+            Some(master_key) => Zeroizing::new(master_key.encrypt(&plaintext)?),
+            None => plaintext,
+        };
+        fs::write(&sks_data_file.temp_path, &*data)
+            .map_err(|err| SecretKeyStorePersistenceError::IoError(err.to_string()))?;
+        fs::rename(&sks_data_file.temp_path, &sks_data_file.path).map_err(|err| {
+            SecretKeyStorePersistenceError::IoError(format!("Could not update SKS file: {}", err))
+        })
+    }
+
+    /// Implements `retain_then_insert`, persisting the resulting keys with
+    /// `write`.  The in-memory keys are only updated if `write` succeeds.
+    fn retain_then_insert_with_writer<F, W>(
+        &mut self,
+        filter: F,
+        scope: Scope,
+        new: (KeyId, CspSecretKey, Option<Scope>),
+        write: W,
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+        W: FnOnce(&SksDataFile, &SecretKeys) -> Result<(), SecretKeyStorePersistenceError>,
+    {
+        let (new_id, new_key, new_scope) = new;
+        let mut keys = self.keys.write();
+        let deleted_key_ids: Vec<KeyId> = keys
+            .iter()
+            .filter(|(key_id, (csp_key, maybe_scope))| {
+                *maybe_scope == Some(scope) && !filter(key_id, csp_key)
+            })
+            .map(|(key_id, _)| *key_id)
+            .collect();
+        if keys.contains_key(&new_id) && !deleted_key_ids.contains(&new_id) {
+            return Err(SecretKeyStorePersistenceError::DuplicateKeyId(new_id));
+        }
+        self.persist_then_update(
+            &mut keys,
+            scope,
+            &deleted_key_ids,
+            Some((new_id, new_key, new_scope)),
+            write,
+        )
+    }
+
+    /// Implements `clear_scope`, persisting the resulting keys with `write`.
+    /// The in-memory keys are only updated if `write` succeeds.
+    fn clear_scope_with_writer<W>(
+        &mut self,
+        scope: Scope,
+        write: W,
+    ) -> Result<usize, SecretKeyStorePersistenceError>
+    where
+        W: FnOnce(&SksDataFile, &SecretKeys) -> Result<(), SecretKeyStorePersistenceError>,
+    {
+        let mut keys = self.keys.write();
+        let deleted_key_ids: Vec<KeyId> = keys
+            .iter()
+            .filter(|(_, (_, maybe_scope))| *maybe_scope == Some(scope))
+            .map(|(key_id, _)| *key_id)
+            .collect();
+        if deleted_key_ids.is_empty() {
+            return Ok(0);
+        }
+        self.persist_then_update(&mut keys, scope, &deleted_key_ids, None, write)?;
+        Ok(deleted_key_ids.len())
+    }
+
+    /// Persists the `keys` without the keys with the `deleted_key_ids` (of the
+    /// given `scope`) and with the `new` key, if any, using `write`. Only if
+    /// `write` succeeds, the same changes are applied to the in-memory `keys`.
+    ///
+    /// If the `new` key's scope would exceed its limit, a `ScopeFull` error is
+    /// returned and nothing is written.
+    fn persist_then_update<W>(
+        &self,
+        keys: &mut SecretKeys,
+        scope: Scope,
+        deleted_key_ids: &[KeyId],
+        new: Option<(KeyId, CspSecretKey, Option<Scope>)>,
+        write: W,
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        W: FnOnce(&SksDataFile, &SecretKeys) -> Result<(), SecretKeyStorePersistenceError>,
+    {
+        // The copy of the keys to persist is scrubbed when it is dropped; the
+        // in-memory keys are then updated in place.
+        let mut updated_keys: SecretKeys = keys
+            .iter()
+            .filter(|(key_id, _)| !deleted_key_ids.contains(key_id))
+            .map(|(key_id, (csp_key, maybe_scope))| (*key_id, (csp_key.clone(), *maybe_scope)))
+            .collect();
+        let new_id = new.as_ref().map(|(new_id, _, _)| *new_id);
+        if let Some((new_id, new_key, new_scope)) = new {
+            if let Some(new_scope) = new_scope {
+                if let Err(SecretKeyStoreError::ScopeFull { scope, max }) =
+                    Self::ensure_scope_has_room(&self.scope_limits, &updated_keys, new_scope, 1)
+                {
+                    return Err(SecretKeyStorePersistenceError::ScopeFull { scope, max });
                 }
-                Err(err) => panic!("IO error {}", err),
-            },
-            Err(err) => panic!("Error serializing data: {}", err),
+            }
+            updated_keys.insert(new_id, (new_key, new_scope));
+        }
+        write(&self.sks_data_file, &updated_keys)?;
+        for key_id in deleted_key_ids {
+            warn!(
+                self.logger,
+                "WARNING: deleting key with ID {} with scope {}", key_id, scope
+            );
+            remove_scrubbed(keys, key_id);
+        }
+        if let Some(new_id) = new_id {
+            if let Some(new_entry) = updated_keys.get(&new_id) {
+                keys.insert(new_id, new_entry.clone());
+            }
         }
+        Ok(())
     }
 
     fn check_path(path: &Path) {
@@ -273,16 +554,47 @@ impl SecretKeyStore for ProtoSecretKeyStore {
         key: CspSecretKey,
         scope: Option<Scope>,
     ) -> Result<(), SecretKeyStoreError> {
+        let scope_limits = &self.scope_limits;
         with_write_lock(&self.keys, |keys| match keys.get(&id) {
             Some(_) => Err(SecretKeyStoreError::DuplicateKeyId(id)),
             None => {
+                if let Some(scope) = scope {
+                    Self::ensure_scope_has_room(scope_limits, keys, scope, 1)?;
+                }
                 keys.insert(id, (key, scope));
-                ProtoSecretKeyStore::write_secret_keys_to_disk(&self.proto_file, keys);
+                ProtoSecretKeyStore::write_secret_keys_to_disk(&self.sks_data_file, keys);
                 Ok(())
             }
         })
     }
 
+    fn insert_batch(
+        &mut self,
+        entries: Vec<(KeyId, CspSecretKey, Option<Scope>)>,
+    ) -> Result<(), SecretKeyStoreError> {
+        let scope_limits = &self.scope_limits;
+        with_write_lock(&self.keys, |keys| {
+            let mut batch_ids = HashSet::new();
+            let mut batch_counts_by_scope = BTreeMap::new();
+            for (id, _, scope) in &entries {
+                if keys.contains_key(id) || !batch_ids.insert(*id) {
+                    return Err(SecretKeyStoreError::DuplicateKeyId(*id));
+                }
+                if let Some(scope) = scope {
+                    *batch_counts_by_scope.entry(*scope).or_insert(0) += 1;
+                }
+            }
+            for (scope, count) in batch_counts_by_scope {
+                Self::ensure_scope_has_room(scope_limits, keys, scope, count)?;
+            }
+            for (id, key, scope) in entries {
+                keys.insert(id, (key, scope));
+            }
+            ProtoSecretKeyStore::write_secret_keys_to_disk(&self.sks_data_file, keys);
+            Ok(())
+        })
+    }
+
     fn get(&self, id: &KeyId) -> Option<CspSecretKey> {
         with_read_lock(&self.keys, |keys| match keys.get(id) {
             Some((csp_key, _)) => Some(csp_key.to_owned()),
@@ -290,47 +602,132 @@ impl SecretKeyStore for ProtoSecretKeyStore {
         })
     }
 
+    fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>> {
+        with_read_lock(&self.keys, |keys| keys.get(id).map(|(_, scope)| *scope))
+    }
+
     fn contains(&self, id: &KeyId) -> bool {
         self.get(id).is_some()
     }
 
     fn remove(&mut self, id: &KeyId) -> bool {
-        let result = with_write_lock(&self.keys, |keys| match keys.get(id) {
-            Some(_) => {
-                keys.remove(id);
-                ProtoSecretKeyStore::write_secret_keys_to_disk(&self.proto_file, keys);
-                Ok(true)
+        let result = with_write_lock(&self.keys, |keys| {
+            let removed = remove_scrubbed(keys, id);
+            if removed {
+                ProtoSecretKeyStore::write_secret_keys_to_disk(&self.sks_data_file, keys);
             }
-            None => Ok(false),
+            Ok(removed)
         });
         result.expect("lambda unexpectedly returned Err")
     }
 
+    fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId> {
+        let keys = self.keys.read();
+        let mut key_ids: Vec<KeyId> = keys
+            .iter()
+            .filter(|(_, (_, maybe_scope))| *maybe_scope == Some(scope))
+            .map(|(key_id, _)| *key_id)
+            .collect();
+        key_ids.sort();
+        key_ids
+    }
+
+    fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize> {
+        let keys = self.keys.read();
+        let mut counts = BTreeMap::new();
+        for (_, maybe_scope) in keys.values() {
+            *counts.entry(*maybe_scope).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError> {
+        self.clear_scope_with_writer(scope, ProtoSecretKeyStore::try_write_secret_keys_to_disk)
+    }
+
+    fn drop_ephemeral(&mut self) {
+        let mut keys = self.keys.write();
+        let ephemeral_key_ids: Vec<KeyId> = keys
+            .iter()
+            .filter(|(_, (_, maybe_scope))| *maybe_scope == Some(Scope::Ephemeral))
+            .map(|(key_id, _)| *key_id)
+            .collect();
+        for key_id in &ephemeral_key_ids {
+            remove_scrubbed(&mut keys, key_id);
+        }
+    }
+
     fn retain<F>(&mut self, filter: F, scope: Scope)
     where
         F: Fn(&KeyId, &CspSecretKey) -> bool,
     {
         with_write_lock(&self.keys, |keys| {
-            let mut all_keys = SecretKeys::new();
-            core::mem::swap(&mut all_keys, keys);
-            let orig_keys_count = all_keys.len();
-            for (key_id, (csp_key, maybe_scope)) in all_keys.drain() {
-                if maybe_scope != Some(scope) || filter(&key_id, &csp_key) {
-                    keys.insert(key_id, (csp_key, maybe_scope));
-                } else {
-                    warn!(
-                        self.logger,
-                        "WARNING: deleting key with ID {} with scope {}", key_id, scope
-                    );
-                }
+            let keys_to_remove: Vec<KeyId> = keys
+                .iter()
+                .filter(|(key_id, (csp_key, maybe_scope))| {
+                    *maybe_scope == Some(scope) && !filter(key_id, csp_key)
+                })
+                .map(|(key_id, _)| *key_id)
+                .collect();
+            for key_id in &keys_to_remove {
+                warn!(
+                    self.logger,
+                    "WARNING: deleting key with ID {} with scope {}", key_id, scope
+                );
+                remove_scrubbed(keys, key_id);
             }
-            if keys.len() < orig_keys_count {
-                ProtoSecretKeyStore::write_secret_keys_to_disk(&self.proto_file, keys);
+            if !keys_to_remove.is_empty() {
+                ProtoSecretKeyStore::write_secret_keys_to_disk(&self.sks_data_file, keys);
             }
             Ok(())
         })
         .unwrap_or_else(|e| panic!("retain failed for scope {} with error {}", scope, e));
     }
+
+    fn try_retain<F>(&mut self, filter: F, scope: Scope) -> Result<(), SecretKeyStoreError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        with_write_lock(&self.keys, |keys| {
+            let keys_to_remove = panic::catch_unwind(AssertUnwindSafe(|| {
+                keys.iter()
+                    .filter(|(key_id, (csp_key, maybe_scope))| {
+                        *maybe_scope == Some(scope) && !filter(key_id, csp_key)
+                    })
+                    .map(|(key_id, _)| *key_id)
+                    .collect::<Vec<_>>()
+            }))
+            .map_err(|_| SecretKeyStoreError::FilterPanicked)?;
+            for key_id in &keys_to_remove {
+                remove_scrubbed(keys, key_id);
+                warn!(
+                    self.logger,
+                    "WARNING: deleting key with ID {} with scope {}", key_id, scope
+                );
+            }
+            if !keys_to_remove.is_empty() {
+                ProtoSecretKeyStore::write_secret_keys_to_disk(&self.sks_data_file, keys);
+            }
+            Ok(())
+        })
+    }
+
+    fn retain_then_insert<F>(
+        &mut self,
+        filter: F,
+        scope: Scope,
+        new: (KeyId, CspSecretKey, Option<Scope>),
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.retain_then_insert_with_writer(
+            filter,
+            scope,
+            new,
+            ProtoSecretKeyStore::try_write_secret_keys_to_disk,
+        )
+    }
 }
 
 fn with_write_lock<T, I, R, F>(v: T, f: F) -> Result<R, SecretKeyStoreError>
@@ -355,6 +752,7 @@ where
 pub mod tests {
     use super::super::test_utils;
     use super::*;
+    use crate::secret_key_store::scope::ConstScope;
     use crate::secret_key_store::test_utils::TempSecretKeyStore;
     use ic_crypto_internal_csp_test_utils::files::mk_temp_dir_with_permissions;
     use proptest::prelude::*;
@@ -414,6 +812,21 @@ pub mod tests {
             test_utils::no_overwrites(seed1, seed2, seed3, proto_key_store());
         }
 
+        #[test]
+        fn should_insert_batch_of_keys(seed: u64) {
+            test_utils::should_insert_batch_of_keys(seed, proto_key_store());
+        }
+
+        #[test]
+        fn insert_batch_should_not_insert_any_key_if_one_exists(seed: u64) {
+            test_utils::insert_batch_should_not_insert_any_key_if_one_exists(seed, proto_key_store());
+        }
+
+        #[test]
+        fn insert_batch_should_not_insert_any_key_if_batch_contains_duplicates(seed: u64) {
+            test_utils::insert_batch_should_not_insert_any_key_if_batch_contains_duplicates(seed, proto_key_store());
+        }
+
         ////////////////////////////////////////////////////////////////////////////////////////
         // If you add tests here, remember to also add them for the VolatileSecretKeyStore
         ////////////////////////////////////////////////////////////////////////////////////////
@@ -424,6 +837,387 @@ pub mod tests {
         test_utils::should_retain_expected_keys(proto_key_store());
     }
 
+    #[test]
+    fn should_get_scope_of_key() {
+        test_utils::should_get_scope_of_key(proto_key_store());
+    }
+
+    #[test]
+    fn should_list_sorted_keys_in_scope() {
+        test_utils::should_list_sorted_keys_in_scope(proto_key_store());
+    }
+
+    #[test]
+    fn should_count_keys_by_scope() {
+        test_utils::should_count_keys_by_scope(proto_key_store());
+    }
+
+    #[test]
+    fn should_clear_only_keys_in_given_scope() {
+        test_utils::should_clear_only_keys_in_given_scope(proto_key_store());
+    }
+
+    #[test]
+    fn should_try_retain_expected_keys() {
+        test_utils::should_try_retain_expected_keys(proto_key_store());
+    }
+
+    #[test]
+    fn should_leave_store_unchanged_if_try_retain_filter_panics() {
+        test_utils::should_leave_store_unchanged_if_try_retain_filter_panics(proto_key_store());
+    }
+
+    #[test]
+    fn should_drop_only_ephemeral_keys() {
+        test_utils::should_drop_only_ephemeral_keys(proto_key_store());
+    }
+
+    #[test]
+    fn should_not_persist_ephemeral_keys() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let mut store = ProtoSecretKeyStore::open(dir.path(), None);
+        let ephemeral_key = (test_utils::make_key_id(0), test_utils::make_secret_key(0));
+        let persistent_key = (test_utils::make_key_id(1), test_utils::make_secret_key(1));
+        store
+            .insert(
+                ephemeral_key.0,
+                ephemeral_key.1.clone(),
+                Some(Scope::Ephemeral),
+            )
+            .unwrap();
+        store
+            .insert(persistent_key.0, persistent_key.1.clone(), None)
+            .unwrap();
+        assert_eq!(store.get(&ephemeral_key.0), Some(ephemeral_key.1));
+
+        let reopened_store = ProtoSecretKeyStore::open(dir.path(), None);
+
+        assert!(!reopened_store.contains(&ephemeral_key.0));
+        assert_eq!(
+            reopened_store.get(&persistent_key.0),
+            Some(persistent_key.1)
+        );
+    }
+
+    #[test]
+    fn clear_scope_should_leave_store_unchanged_if_persisting_fails() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let mut store = ProtoSecretKeyStore::open(dir.path(), None);
+        let scope = Scope::Const(ConstScope::Test0);
+        let key_to_remove = (test_utils::make_key_id(0), test_utils::make_secret_key(0));
+        store
+            .insert(key_to_remove.0, key_to_remove.1.clone(), Some(scope))
+            .unwrap();
+
+        let result = store.clear_scope_with_writer(scope, |_, _| {
+            Err(SecretKeyStorePersistenceError::IoError(
+                "injected".to_string(),
+            ))
+        });
+
+        assert!(matches!(
+            result,
+            Err(SecretKeyStorePersistenceError::IoError(_))
+        ));
+        assert_eq!(store.get(&key_to_remove.0), Some(key_to_remove.1.clone()));
+        let reopened_store = ProtoSecretKeyStore::open(dir.path(), None);
+        assert_eq!(reopened_store.get(&key_to_remove.0), Some(key_to_remove.1));
+    }
+
+    #[test]
+    fn should_fail_to_insert_into_full_scope_until_key_is_removed() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let limited_scope = Scope::Const(ConstScope::Test0);
+        let other_scope = Scope::Const(ConstScope::Test1);
+        let mut scope_limits = BTreeMap::new();
+        scope_limits.insert(limited_scope, 2);
+        let mut store = ProtoSecretKeyStore::open_with_scope_limits(dir.path(), None, scope_limits);
+        for seed in 0..2 {
+            store
+                .insert(
+                    test_utils::make_key_id(seed),
+                    test_utils::make_secret_key(seed),
+                    Some(limited_scope),
+                )
+                .unwrap();
+        }
+
+        let result = store.insert(
+            test_utils::make_key_id(2),
+            test_utils::make_secret_key(2),
+            Some(limited_scope),
+        );
+
+        assert!(matches!(
+            result,
+            Err(SecretKeyStoreError::ScopeFull { scope, max: 2 }) if scope == limited_scope
+        ));
+        assert!(!store.contains(&test_utils::make_key_id(2)));
+        store
+            .insert(
+                test_utils::make_key_id(3),
+                test_utils::make_secret_key(3),
+                Some(other_scope),
+            )
+            .unwrap();
+        store
+            .insert(
+                test_utils::make_key_id(4),
+                test_utils::make_secret_key(4),
+                None,
+            )
+            .unwrap();
+
+        assert!(store.remove(&test_utils::make_key_id(0)));
+        store
+            .insert(
+                test_utils::make_key_id(2),
+                test_utils::make_secret_key(2),
+                Some(limited_scope),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn should_fail_to_insert_batch_exceeding_scope_limit() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let limited_scope = Scope::Const(ConstScope::Test0);
+        let mut scope_limits = BTreeMap::new();
+        scope_limits.insert(limited_scope, 2);
+        let mut store = ProtoSecretKeyStore::open_with_scope_limits(dir.path(), None, scope_limits);
+        let entries = (0..3)
+            .map(|seed| {
+                (
+                    test_utils::make_key_id(seed),
+                    test_utils::make_secret_key(seed),
+                    Some(limited_scope),
+                )
+            })
+            .collect();
+
+        let result = store.insert_batch(entries);
+
+        assert!(matches!(
+            result,
+            Err(SecretKeyStoreError::ScopeFull { max: 2, .. })
+        ));
+        assert!(store.keys_in_scope(limited_scope).is_empty());
+    }
+
+    #[test]
+    fn retain_then_insert_should_respect_scope_limit() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let limited_scope = Scope::Const(ConstScope::Test0);
+        let mut scope_limits = BTreeMap::new();
+        scope_limits.insert(limited_scope, 2);
+        let mut store = ProtoSecretKeyStore::open_with_scope_limits(dir.path(), None, scope_limits);
+        for seed in 0..2 {
+            store
+                .insert(
+                    test_utils::make_key_id(seed),
+                    test_utils::make_secret_key(seed),
+                    Some(limited_scope),
+                )
+                .unwrap();
+        }
+        let new_key = |seed| {
+            (
+                test_utils::make_key_id(seed),
+                test_utils::make_secret_key(seed),
+                Some(limited_scope),
+            )
+        };
+
+        let result = store.retain_then_insert(|_, _| true, limited_scope, new_key(2));
+
+        assert!(matches!(
+            result,
+            Err(SecretKeyStorePersistenceError::ScopeFull { scope, max: 2 }) if scope == limited_scope
+        ));
+        assert!(!store.contains(&test_utils::make_key_id(2)));
+        assert_eq!(store.keys_in_scope(limited_scope).len(), 2);
+        let reopened_store = ProtoSecretKeyStore::open(dir.path(), None);
+        assert!(!reopened_store.contains(&test_utils::make_key_id(2)));
+
+        let key_id_to_drop = test_utils::make_key_id(0);
+        store
+            .retain_then_insert(|id, _| *id != key_id_to_drop, limited_scope, new_key(2))
+            .unwrap();
+        assert!(!store.contains(&key_id_to_drop));
+        assert!(store.contains(&test_utils::make_key_id(2)));
+    }
+
+    #[test]
+    fn should_retain_then_insert() {
+        test_utils::should_retain_then_insert(proto_key_store());
+    }
+
+    #[test]
+    fn retain_then_insert_should_fail_if_new_key_is_retained() {
+        test_utils::retain_then_insert_should_fail_if_new_key_is_retained(proto_key_store());
+    }
+
+    #[test]
+    fn clear_scope_should_return_injected_error_and_leave_store_unchanged() {
+        test_utils::clear_scope_should_return_injected_error_and_leave_store_unchanged(
+            proto_key_store(),
+        );
+    }
+
+    #[test]
+    fn retain_then_insert_should_return_injected_error_and_leave_store_unchanged() {
+        test_utils::retain_then_insert_should_return_injected_error_and_leave_store_unchanged(
+            proto_key_store(),
+        );
+    }
+
+    #[test]
+    fn should_forward_persisting_calls_if_injected_error_is_discarded() {
+        test_utils::should_forward_persisting_calls_if_injected_error_is_discarded(
+            proto_key_store(),
+        );
+    }
+
+    #[test]
+    fn retain_then_insert_should_leave_store_unchanged_if_persisting_fails() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let mut store = ProtoSecretKeyStore::open(dir.path(), None);
+        let scope = Scope::Const(ConstScope::Test0);
+        let key_to_remove = (test_utils::make_key_id(0), test_utils::make_secret_key(0));
+        let new_key = (test_utils::make_key_id(1), test_utils::make_secret_key(1));
+        store
+            .insert(key_to_remove.0, key_to_remove.1.clone(), Some(scope))
+            .unwrap();
+
+        let result = store.retain_then_insert_with_writer(
+            |_, _| false,
+            scope,
+            (new_key.0, new_key.1, Some(scope)),
+            |_, _| {
+                Err(SecretKeyStorePersistenceError::IoError(
+                    "injected".to_string(),
+                ))
+            },
+        );
+
+        assert!(matches!(
+            result,
+            Err(SecretKeyStorePersistenceError::IoError(_))
+        ));
+        assert_eq!(store.get(&key_to_remove.0), Some(key_to_remove.1.clone()));
+        assert!(!store.contains(&new_key.0));
+        let reopened_store = ProtoSecretKeyStore::open(dir.path(), None);
+        assert_eq!(reopened_store.get(&key_to_remove.0), Some(key_to_remove.1));
+        assert!(!reopened_store.contains(&new_key.0));
+    }
+
+    #[test]
+    fn should_write_checksum_and_read_keys_back() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let mut store = ProtoSecretKeyStore::open(dir.path(), None);
+        let key = (test_utils::make_key_id(0), test_utils::make_secret_key(0));
+        store.insert(key.0, key.1.clone(), None).unwrap();
+
+        let data = fs::read(dir.path().join(SKS_DATA_FILENAME)).unwrap();
+        assert!(data.starts_with(SKS_DATA_CHECKSUM_MAGIC));
+        let reopened_store = ProtoSecretKeyStore::open(dir.path(), None);
+        assert_eq!(reopened_store.get(&key.0), Some(key.1));
+    }
+
+    #[test]
+    fn should_reject_corrupted_sks_data_file() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let mut store = ProtoSecretKeyStore::open(dir.path(), None);
+        store
+            .insert(
+                test_utils::make_key_id(0),
+                test_utils::make_secret_key(0),
+                None,
+            )
+            .unwrap();
+        let path = dir.path().join(SKS_DATA_FILENAME);
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(&path, &data).unwrap();
+
+        let result = ProtoSecretKeyStore::try_open(
+            dir.path(),
+            None,
+            BTreeMap::new(),
+            SksDataFile::plaintext(dir.path()),
+        );
+
+        assert!(matches!(
+            result,
+            Err(SecretKeyStorePersistenceError::IoError(msg)) if msg == "checksum mismatch"
+        ));
+    }
+
+    #[test]
+    fn should_reject_truncated_sks_data_file() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let mut store = ProtoSecretKeyStore::open(dir.path(), None);
+        store
+            .insert(
+                test_utils::make_key_id(0),
+                test_utils::make_secret_key(0),
+                None,
+            )
+            .unwrap();
+        let path = dir.path().join(SKS_DATA_FILENAME);
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 1]).unwrap();
+
+        let result = ProtoSecretKeyStore::try_open(
+            dir.path(),
+            None,
+            BTreeMap::new(),
+            SksDataFile::plaintext(dir.path()),
+        );
+
+        assert!(matches!(
+            result,
+            Err(SecretKeyStorePersistenceError::IoError(msg)) if msg.starts_with("length mismatch")
+        ));
+    }
+
+    #[test]
+    fn should_reject_sks_data_file_with_truncated_checksum_header() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let path = dir.path().join(SKS_DATA_FILENAME);
+        fs::write(&path, SKS_DATA_CHECKSUM_MAGIC).unwrap();
+
+        let result = ProtoSecretKeyStore::try_open(
+            dir.path(),
+            None,
+            BTreeMap::new(),
+            SksDataFile::plaintext(dir.path()),
+        );
+
+        assert!(matches!(
+            result,
+            Err(SecretKeyStorePersistenceError::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn should_read_legacy_sks_data_file_without_checksum() {
+        let dir = mk_temp_dir_with_permissions(0o700);
+        let key = (test_utils::make_key_id(0), test_utils::make_secret_key(0));
+        let mut secret_keys = SecretKeys::new();
+        secret_keys.insert(key.0, (key.1.clone(), None));
+        let mut proto_bytes = Vec::new();
+        ProtoSecretKeyStore::secret_keys_to_sks_proto(&secret_keys)
+            .encode(&mut proto_bytes)
+            .unwrap();
+        fs::write(dir.path().join(SKS_DATA_FILENAME), &proto_bytes).unwrap();
+
+        let store = ProtoSecretKeyStore::open(dir.path(), None);
+
+        assert_eq!(store.get(&key.0), Some(key.1));
+    }
+
     fn proto_key_store() -> TempSecretKeyStore {
         TempSecretKeyStore::new()
     }
@@ -4,7 +4,9 @@
 // Ok, let's duplicate the mock and see what happens.
 
 use crate::secret_key_store::proto_store::ProtoSecretKeyStore;
-use crate::secret_key_store::{scope::ConstScope, Scope, SecretKeyStore, SecretKeyStoreError};
+use crate::secret_key_store::{
+    scope::ConstScope, Scope, SecretKeyStore, SecretKeyStoreError, SecretKeyStorePersistenceError,
+};
 use crate::types::CspSecretKey;
 use ic_crypto_internal_basic_sig_ed25519::types as ed25519_types;
 use ic_crypto_internal_csp_test_utils::files::mk_temp_dir_with_permissions;
@@ -13,16 +15,112 @@ use mockall::predicate::*;
 use mockall::*;
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha20Rng;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
 use tempfile::TempDir;
+use zeroize::Zeroize;
 
 mock! {
-    pub SecretKeyStore {}
+    /// Mocked calls of a `MockSecretKeyStore`
+    pub SecretKeyStoreCalls {
+        pub fn insert(&mut self, id: KeyId, key: CspSecretKey, scope: Option<Scope>) -> Result<(), SecretKeyStoreError>;
+        pub fn get(&self, id: &KeyId) -> Option<CspSecretKey>;
+        pub fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>>;
+        pub fn contains(&self, id: &KeyId) -> bool;
+        pub fn remove(&mut self, id: &KeyId) -> bool;
+        pub fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId>;
+        pub fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize>;
+        pub fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError>;
+    }
+}
+
+/// Mock SecretKeyStore object for testing interactions
+///
+/// Expectations are set on the wrapped `MockSecretKeyStoreCalls`, e.g.
+/// `sks.expect_get()`. The methods taking a filter are generic over
+/// non-`'static` closures, which cannot be mocked, so calling them panics
+/// like any call without a matching expectation.
+pub struct MockSecretKeyStore(MockSecretKeyStoreCalls);
+
+impl MockSecretKeyStore {
+    /// Create a new MockSecretKeyStore without any expectations.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(MockSecretKeyStoreCalls::new())
+    }
+}
+
+impl Deref for MockSecretKeyStore {
+    type Target = MockSecretKeyStoreCalls;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for MockSecretKeyStore {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl SecretKeyStore for MockSecretKeyStore {
+    fn insert(
+        &mut self,
+        id: KeyId,
+        key: CspSecretKey,
+        scope: Option<Scope>,
+    ) -> Result<(), SecretKeyStoreError> {
+        self.0.insert(id, key, scope)
+    }
+
+    fn get(&self, id: &KeyId) -> Option<CspSecretKey> {
+        self.0.get(id)
+    }
+
+    fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>> {
+        self.0.get_scope(id)
+    }
+
+    fn contains(&self, id: &KeyId) -> bool {
+        self.0.contains(id)
+    }
+
+    fn remove(&mut self, id: &KeyId) -> bool {
+        self.0.remove(id)
+    }
+
+    fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId> {
+        self.0.keys_in_scope(scope)
+    }
+
+    fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize> {
+        self.0.count_by_scope()
+    }
+
+    fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError> {
+        self.0.clear_scope(scope)
+    }
+
+    fn try_retain<F>(&mut self, _filter: F, _scope: Scope) -> Result<(), SecretKeyStoreError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        panic!("MockSecretKeyStore::try_retain: No matching expectation found")
+    }
 
-    pub trait SecretKeyStore {
-        fn insert(&mut self, id: KeyId, key: CspSecretKey, scope: Option<Scope>) -> Result<(), SecretKeyStoreError>;
-        fn get(&self, id: &KeyId) -> Option<CspSecretKey>;
-        fn contains(&self, id: &KeyId) -> bool;
-        fn remove(&mut self, id: &KeyId) -> bool;
+    fn retain_then_insert<F>(
+        &mut self,
+        _filter: F,
+        _scope: Scope,
+        _new: (KeyId, CspSecretKey, Option<Scope>),
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        panic!("MockSecretKeyStore::retain_then_insert: No matching expectation found")
     }
 }
 
@@ -55,6 +153,13 @@ impl SecretKeyStore for TempSecretKeyStore {
         self.store.insert(id, key, scope)
     }
 
+    fn insert_batch(
+        &mut self,
+        entries: Vec<(KeyId, CspSecretKey, Option<Scope>)>,
+    ) -> Result<(), SecretKeyStoreError> {
+        self.store.insert_batch(entries)
+    }
+
     fn get(&self, id: &KeyId) -> Option<CspSecretKey> {
         self.store.get(id)
     }
@@ -67,19 +172,173 @@ impl SecretKeyStore for TempSecretKeyStore {
         self.store.remove(id)
     }
 
+    fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>> {
+        self.store.get_scope(id)
+    }
+
+    fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId> {
+        self.store.keys_in_scope(scope)
+    }
+
+    fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize> {
+        self.store.count_by_scope()
+    }
+
+    fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError> {
+        self.store.clear_scope(scope)
+    }
+
+    fn drop_ephemeral(&mut self) {
+        self.store.drop_ephemeral()
+    }
+
     fn retain<F>(&mut self, filter: F, scope: Scope)
     where
         F: Fn(&KeyId, &CspSecretKey) -> bool,
     {
         self.store.retain(filter, scope)
     }
+
+    fn try_retain<F>(&mut self, filter: F, scope: Scope) -> Result<(), SecretKeyStoreError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.store.try_retain(filter, scope)
+    }
+
+    fn retain_then_insert<F>(
+        &mut self,
+        filter: F,
+        scope: Scope,
+        new: (KeyId, CspSecretKey, Option<Scope>),
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.store.retain_then_insert(filter, scope, new)
+    }
+}
+
+/// Wraps a `SecretKeyStore` and, once armed with `fail_next_write_with`,
+/// fails the next persisting call (`clear_scope` or `retain_then_insert`)
+/// with the given error without forwarding it to the wrapped store. All other
+/// calls, and persisting calls while no failure is pending, are forwarded.
+pub struct FaultInjectingSecretKeyStore<S: SecretKeyStore> {
+    store: S,
+    pending_error: Option<SecretKeyStorePersistenceError>,
+}
+
+impl<S: SecretKeyStore> FaultInjectingSecretKeyStore<S> {
+    pub fn new(store: S) -> Self {
+        FaultInjectingSecretKeyStore {
+            store,
+            pending_error: None,
+        }
+    }
+
+    /// Makes the next persisting call fail with `error`.
+    pub fn fail_next_write_with(mut self, error: SecretKeyStorePersistenceError) -> Self {
+        self.pending_error = Some(error);
+        self
+    }
+
+    /// Discards a pending failure, if any.
+    pub fn succeed(mut self) -> Self {
+        self.pending_error = None;
+        self
+    }
+
+    fn take_pending_error(&mut self) -> Result<(), SecretKeyStorePersistenceError> {
+        match self.pending_error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
 }
 
-fn make_key_id(seed: u64) -> KeyId {
+impl<S: SecretKeyStore> SecretKeyStore for FaultInjectingSecretKeyStore<S> {
+    fn insert(
+        &mut self,
+        id: KeyId,
+        key: CspSecretKey,
+        scope: Option<Scope>,
+    ) -> Result<(), SecretKeyStoreError> {
+        self.store.insert(id, key, scope)
+    }
+
+    fn insert_batch(
+        &mut self,
+        entries: Vec<(KeyId, CspSecretKey, Option<Scope>)>,
+    ) -> Result<(), SecretKeyStoreError> {
+        self.store.insert_batch(entries)
+    }
+
+    fn get(&self, id: &KeyId) -> Option<CspSecretKey> {
+        self.store.get(id)
+    }
+
+    fn contains(&self, id: &KeyId) -> bool {
+        self.store.contains(id)
+    }
+
+    fn remove(&mut self, id: &KeyId) -> bool {
+        self.store.remove(id)
+    }
+
+    fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>> {
+        self.store.get_scope(id)
+    }
+
+    fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId> {
+        self.store.keys_in_scope(scope)
+    }
+
+    fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize> {
+        self.store.count_by_scope()
+    }
+
+    fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError> {
+        self.take_pending_error()?;
+        self.store.clear_scope(scope)
+    }
+
+    fn drop_ephemeral(&mut self) {
+        self.store.drop_ephemeral()
+    }
+
+    fn retain<F>(&mut self, filter: F, scope: Scope)
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.store.retain(filter, scope)
+    }
+
+    fn try_retain<F>(&mut self, filter: F, scope: Scope) -> Result<(), SecretKeyStoreError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.store.try_retain(filter, scope)
+    }
+
+    fn retain_then_insert<F>(
+        &mut self,
+        filter: F,
+        scope: Scope,
+        new: (KeyId, CspSecretKey, Option<Scope>),
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.take_pending_error()?;
+        self.store.retain_then_insert(filter, scope, new)
+    }
+}
+
+pub fn make_key_id(seed: u64) -> KeyId {
     KeyId::from(ChaCha20Rng::seed_from_u64(seed).gen::<[u8; 32]>())
 }
 
-fn make_secret_key(seed: u64) -> CspSecretKey {
+pub fn make_secret_key(seed: u64) -> CspSecretKey {
     CspSecretKey::Ed25519(ed25519_types::SecretKeyBytes(
         ChaCha20Rng::seed_from_u64(seed).gen(),
     ))
@@ -223,3 +482,427 @@ pub fn should_retain_expected_keys<T: SecretKeyStore>(mut key_store: T) {
         "Expected to keep key with no scope"
     );
 }
+
+pub fn should_insert_batch_of_keys<T: SecretKeyStore>(seed: u64, mut key_store: T) {
+    let entries: Vec<_> = (0..3)
+        .map(|i| {
+            (
+                make_key_id(seed.wrapping_add(i)),
+                make_secret_key(seed.wrapping_add(i)),
+                None,
+            )
+        })
+        .collect();
+
+    assert!(key_store.insert_batch(entries.clone()).is_ok());
+
+    for (key_id, key, _) in entries {
+        assert_eq!(key_store.get(&key_id), Some(key));
+    }
+}
+
+pub fn insert_batch_should_not_insert_any_key_if_one_exists<T: SecretKeyStore>(
+    seed: u64,
+    mut key_store: T,
+) {
+    let existing_key_id = make_key_id(seed);
+    let existing_key = make_secret_key(seed);
+    let new_key_id = make_key_id(seed.wrapping_add(1));
+    key_store
+        .insert(existing_key_id, existing_key.clone(), None)
+        .unwrap();
+
+    let result = key_store.insert_batch(vec![
+        (new_key_id, make_secret_key(seed.wrapping_add(1)), None),
+        (existing_key_id, make_secret_key(seed.wrapping_add(2)), None),
+    ]);
+
+    match result {
+        Err(SecretKeyStoreError::DuplicateKeyId(key_id)) => assert_eq!(key_id, existing_key_id),
+        _ => panic!("expected DuplicateKeyId error"),
+    }
+    assert!(!key_store.contains(&new_key_id));
+    assert_eq!(key_store.get(&existing_key_id), Some(existing_key));
+}
+
+pub fn insert_batch_should_not_insert_any_key_if_batch_contains_duplicates<T: SecretKeyStore>(
+    seed: u64,
+    mut key_store: T,
+) {
+    let key_id = make_key_id(seed);
+    let other_key_id = make_key_id(seed.wrapping_add(1));
+
+    let result = key_store.insert_batch(vec![
+        (other_key_id, make_secret_key(seed), None),
+        (key_id, make_secret_key(seed.wrapping_add(1)), None),
+        (key_id, make_secret_key(seed.wrapping_add(2)), None),
+    ]);
+
+    match result {
+        Err(SecretKeyStoreError::DuplicateKeyId(duplicate)) => assert_eq!(duplicate, key_id),
+        _ => panic!("expected DuplicateKeyId error"),
+    }
+    assert!(!key_store.contains(&key_id));
+    assert!(!key_store.contains(&other_key_id));
+}
+
+pub fn should_get_scope_of_key<T: SecretKeyStore>(mut key_store: T) {
+    let scope = Scope::Const(ConstScope::Test0);
+    let (scoped_id, unscoped_id) = (make_key_id(0), make_key_id(1));
+    key_store
+        .insert(scoped_id, make_secret_key(0), Some(scope))
+        .unwrap();
+    key_store
+        .insert(unscoped_id, make_secret_key(1), None)
+        .unwrap();
+
+    assert_eq!(key_store.get_scope(&scoped_id), Some(Some(scope)));
+    assert_eq!(key_store.get_scope(&unscoped_id), Some(None));
+    assert_eq!(key_store.get_scope(&make_key_id(2)), None);
+}
+
+pub fn should_list_sorted_keys_in_scope<T: SecretKeyStore>(mut key_store: T) {
+    let selected_scope = Scope::Const(ConstScope::Test0);
+    let different_scope = Scope::Const(ConstScope::Test1);
+    let mut expected_key_ids = Vec::new();
+    for seed in 0..5 {
+        let key_id = make_key_id(seed);
+        key_store
+            .insert(key_id, make_secret_key(seed), Some(selected_scope))
+            .unwrap();
+        expected_key_ids.push(key_id);
+    }
+    key_store
+        .insert(make_key_id(10), make_secret_key(10), Some(different_scope))
+        .unwrap();
+    key_store
+        .insert(make_key_id(11), make_secret_key(11), None)
+        .unwrap();
+    expected_key_ids.sort();
+
+    assert_eq!(key_store.keys_in_scope(selected_scope), expected_key_ids);
+    assert_eq!(
+        key_store.keys_in_scope(different_scope),
+        vec![make_key_id(10)]
+    );
+}
+
+pub fn should_count_keys_by_scope<T: SecretKeyStore>(mut key_store: T) {
+    let threshold_scope = Scope::Const(ConstScope::NiDkgThresholdKeys);
+    for seed in 0..3 {
+        key_store
+            .insert(
+                make_key_id(seed),
+                make_secret_key(seed),
+                Some(threshold_scope),
+            )
+            .unwrap();
+    }
+    for seed in 3..5 {
+        key_store
+            .insert(make_key_id(seed), make_secret_key(seed), None)
+            .unwrap();
+    }
+
+    let counts = key_store.count_by_scope();
+
+    let mut expected_counts = BTreeMap::new();
+    expected_counts.insert(Some(threshold_scope), 3);
+    expected_counts.insert(None, 2);
+    assert_eq!(counts, expected_counts);
+}
+
+pub fn should_clear_only_keys_in_given_scope<T: SecretKeyStore>(mut key_store: T) {
+    let scope_to_clear = Scope::Const(ConstScope::Test0);
+    let other_scope = Scope::Const(ConstScope::Test1);
+    for seed in 0..3 {
+        key_store
+            .insert(
+                make_key_id(seed),
+                make_secret_key(seed),
+                Some(scope_to_clear),
+            )
+            .unwrap();
+    }
+    for seed in 3..5 {
+        key_store
+            .insert(make_key_id(seed), make_secret_key(seed), Some(other_scope))
+            .unwrap();
+    }
+    key_store
+        .insert(make_key_id(5), make_secret_key(5), None)
+        .unwrap();
+
+    assert_eq!(key_store.clear_scope(scope_to_clear).unwrap(), 3);
+
+    assert!(key_store.keys_in_scope(scope_to_clear).is_empty());
+    for seed in 0..3 {
+        assert!(!key_store.contains(&make_key_id(seed)));
+    }
+    for seed in 3..6 {
+        assert_eq!(
+            key_store.get(&make_key_id(seed)),
+            Some(make_secret_key(seed))
+        );
+    }
+    assert_eq!(key_store.clear_scope(scope_to_clear).unwrap(), 0);
+}
+
+pub fn should_drop_only_ephemeral_keys<T: SecretKeyStore>(mut key_store: T) {
+    for seed in 0..2 {
+        key_store
+            .insert(
+                make_key_id(seed),
+                make_secret_key(seed),
+                Some(Scope::Ephemeral),
+            )
+            .unwrap();
+    }
+    key_store
+        .insert(
+            make_key_id(2),
+            make_secret_key(2),
+            Some(Scope::Const(ConstScope::Test0)),
+        )
+        .unwrap();
+    key_store
+        .insert(make_key_id(3), make_secret_key(3), None)
+        .unwrap();
+    assert_eq!(key_store.get(&make_key_id(0)), Some(make_secret_key(0)));
+
+    key_store.drop_ephemeral();
+
+    assert!(key_store.keys_in_scope(Scope::Ephemeral).is_empty());
+    for seed in 2..4 {
+        assert_eq!(
+            key_store.get(&make_key_id(seed)),
+            Some(make_secret_key(seed))
+        );
+    }
+}
+
+pub fn should_try_retain_expected_keys<T: SecretKeyStore>(mut key_store: T) {
+    let selected_scope = Scope::Const(ConstScope::Test0);
+    let other_scope = Scope::Const(ConstScope::Test1);
+    for seed in 0..3 {
+        key_store
+            .insert(
+                make_key_id(seed),
+                make_secret_key(seed),
+                Some(selected_scope),
+            )
+            .unwrap();
+    }
+    key_store
+        .insert(make_key_id(3), make_secret_key(3), Some(other_scope))
+        .unwrap();
+    key_store
+        .insert(make_key_id(4), make_secret_key(4), None)
+        .unwrap();
+
+    let id_to_retain = make_key_id(1);
+    assert!(key_store
+        .try_retain(|id, _| *id == id_to_retain, selected_scope)
+        .is_ok());
+
+    assert_eq!(key_store.keys_in_scope(selected_scope), vec![id_to_retain]);
+    assert!(key_store.contains(&make_key_id(3)));
+    assert!(key_store.contains(&make_key_id(4)));
+}
+
+pub fn should_leave_store_unchanged_if_try_retain_filter_panics<T: SecretKeyStore>(
+    mut key_store: T,
+) {
+    let selected_scope = Scope::Const(ConstScope::Test0);
+    for seed in 0..3 {
+        key_store
+            .insert(
+                make_key_id(seed),
+                make_secret_key(seed),
+                Some(selected_scope),
+            )
+            .unwrap();
+    }
+    key_store
+        .insert(make_key_id(3), make_secret_key(3), None)
+        .unwrap();
+
+    let panicking_id = make_key_id(1);
+    let result = key_store.try_retain(
+        |id, _| {
+            if *id == panicking_id {
+                panic!("filter panicked on key {}", id);
+            }
+            false
+        },
+        selected_scope,
+    );
+
+    assert!(matches!(result, Err(SecretKeyStoreError::FilterPanicked)));
+    for seed in 0..4 {
+        assert_eq!(
+            key_store.get(&make_key_id(seed)),
+            Some(make_secret_key(seed))
+        );
+    }
+}
+
+pub fn should_retain_then_insert<T: SecretKeyStore>(mut key_store: T) {
+    let selected_scope = Scope::Const(ConstScope::Test0);
+    let key_to_retain = (make_key_id(0), make_secret_key(0));
+    let key_to_remove = (make_key_id(1), make_secret_key(1));
+    let new_key = (make_key_id(2), make_secret_key(2));
+    key_store
+        .insert(key_to_retain.0, key_to_retain.1, Some(selected_scope))
+        .unwrap();
+    key_store
+        .insert(key_to_remove.0, key_to_remove.1, Some(selected_scope))
+        .unwrap();
+
+    let id_to_retain = key_to_retain.0;
+    assert!(key_store
+        .retain_then_insert(
+            |id, _| *id == id_to_retain,
+            selected_scope,
+            (new_key.0, new_key.1.clone(), Some(selected_scope)),
+        )
+        .is_ok());
+
+    assert!(key_store.contains(&key_to_retain.0));
+    assert!(!key_store.contains(&key_to_remove.0));
+    assert_eq!(key_store.get(&new_key.0), Some(new_key.1));
+}
+
+pub fn retain_then_insert_should_fail_if_new_key_is_retained<T: SecretKeyStore>(mut key_store: T) {
+    let selected_scope = Scope::Const(ConstScope::Test0);
+    let key_to_remove = (make_key_id(0), make_secret_key(0));
+    let existing_key = (make_key_id(1), make_secret_key(1));
+    key_store
+        .insert(key_to_remove.0, key_to_remove.1, Some(selected_scope))
+        .unwrap();
+    key_store
+        .insert(existing_key.0, existing_key.1.clone(), None)
+        .unwrap();
+
+    let result = key_store.retain_then_insert(
+        |_, _| false,
+        selected_scope,
+        (existing_key.0, make_secret_key(2), None),
+    );
+
+    match result {
+        Err(SecretKeyStorePersistenceError::DuplicateKeyId(key_id)) => {
+            assert_eq!(key_id, existing_key.0)
+        }
+        _ => panic!("expected DuplicateKeyId error"),
+    }
+    assert!(key_store.contains(&key_to_remove.0));
+    assert_eq!(key_store.get(&existing_key.0), Some(existing_key.1));
+}
+
+/// A secret key that records whether it was zeroized when it is dropped.
+///
+/// This allows to check that `remove_scrubbed` zeroizes the key in place,
+/// i.e. before the key is moved out of the map and dropped.
+pub struct ScrubRecordingKey {
+    bytes: [u8; 32],
+    zeroized_when_dropped: Rc<Cell<Option<bool>>>,
+}
+
+impl ScrubRecordingKey {
+    /// Returns a key with non-zero bytes, together with a cell that is set to
+    /// whether the key was zeroized once the key is dropped.
+    pub fn new() -> (Self, Rc<Cell<Option<bool>>>) {
+        let zeroized_when_dropped = Rc::new(Cell::new(None));
+        let key = ScrubRecordingKey {
+            bytes: [42; 32],
+            zeroized_when_dropped: Rc::clone(&zeroized_when_dropped),
+        };
+        (key, zeroized_when_dropped)
+    }
+}
+
+impl Zeroize for ScrubRecordingKey {
+    fn zeroize(&mut self) {
+        self.bytes.zeroize();
+    }
+}
+
+impl Drop for ScrubRecordingKey {
+    fn drop(&mut self) {
+        self.zeroized_when_dropped
+            .set(Some(self.bytes.iter().all(|byte| *byte == 0)));
+    }
+}
+
+pub fn clear_scope_should_return_injected_error_and_leave_store_unchanged<T: SecretKeyStore>(
+    key_store: T,
+) {
+    let scope = Scope::Const(ConstScope::Test0);
+    let mut key_store = FaultInjectingSecretKeyStore::new(key_store).fail_next_write_with(
+        SecretKeyStorePersistenceError::IoError("injected".to_string()),
+    );
+    for seed in 0..2 {
+        key_store
+            .insert(make_key_id(seed), make_secret_key(seed), Some(scope))
+            .unwrap();
+    }
+
+    assert!(matches!(
+        key_store.clear_scope(scope),
+        Err(SecretKeyStorePersistenceError::IoError(msg)) if msg == "injected"
+    ));
+
+    for seed in 0..2 {
+        assert_eq!(
+            key_store.get(&make_key_id(seed)),
+            Some(make_secret_key(seed))
+        );
+    }
+    assert_eq!(key_store.clear_scope(scope).unwrap(), 2);
+}
+
+pub fn retain_then_insert_should_return_injected_error_and_leave_store_unchanged<
+    T: SecretKeyStore,
+>(
+    key_store: T,
+) {
+    let scope = Scope::Const(ConstScope::Test0);
+    let mut key_store = FaultInjectingSecretKeyStore::new(key_store).fail_next_write_with(
+        SecretKeyStorePersistenceError::IoError("injected".to_string()),
+    );
+    key_store
+        .insert(make_key_id(0), make_secret_key(0), Some(scope))
+        .unwrap();
+    let new = (make_key_id(1), make_secret_key(1), Some(scope));
+
+    assert!(matches!(
+        key_store.retain_then_insert(|_, _| false, scope, new.clone()),
+        Err(SecretKeyStorePersistenceError::IoError(msg)) if msg == "injected"
+    ));
+
+    assert_eq!(key_store.get(&make_key_id(0)), Some(make_secret_key(0)));
+    assert!(!key_store.contains(&make_key_id(1)));
+    key_store
+        .retain_then_insert(|_, _| false, scope, new)
+        .unwrap();
+    assert!(!key_store.contains(&make_key_id(0)));
+    assert_eq!(key_store.get(&make_key_id(1)), Some(make_secret_key(1)));
+}
+
+pub fn should_forward_persisting_calls_if_injected_error_is_discarded<T: SecretKeyStore>(
+    key_store: T,
+) {
+    let scope = Scope::Const(ConstScope::Test0);
+    let mut key_store = FaultInjectingSecretKeyStore::new(key_store)
+        .fail_next_write_with(SecretKeyStorePersistenceError::IoError(
+            "injected".to_string(),
+        ))
+        .succeed();
+    key_store
+        .insert(make_key_id(0), make_secret_key(0), Some(scope))
+        .unwrap();
+
+    assert_eq!(key_store.clear_scope(scope).unwrap(), 1);
+    assert!(!key_store.contains(&make_key_id(0)));
+}
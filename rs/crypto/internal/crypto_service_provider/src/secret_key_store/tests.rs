@@ -0,0 +1,26 @@
+use super::*;
+use crate::secret_key_store::test_utils::{make_key_id, ScrubRecordingKey};
+
+#[test]
+fn should_zeroize_key_before_removing_it() {
+    let (key, zeroized_when_dropped) = ScrubRecordingKey::new();
+    let mut keys = HashMap::new();
+    keys.insert(make_key_id(0), (key, None));
+
+    assert!(remove_scrubbed(&mut keys, &make_key_id(0)));
+
+    assert!(keys.is_empty());
+    assert_eq!(zeroized_when_dropped.get(), Some(true));
+}
+
+#[test]
+fn should_leave_other_keys_untouched_when_removing_key() {
+    let (key, zeroized_when_dropped) = ScrubRecordingKey::new();
+    let mut keys = HashMap::new();
+    keys.insert(make_key_id(0), (key, None));
+
+    assert!(!remove_scrubbed(&mut keys, &make_key_id(1)));
+
+    assert!(keys.contains_key(&make_key_id(0)));
+    assert_eq!(zeroized_when_dropped.get(), None);
+}
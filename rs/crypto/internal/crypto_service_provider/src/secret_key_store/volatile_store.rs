@@ -1,8 +1,11 @@
 //! In-memory secret key store (for testing)
-use crate::secret_key_store::{Scope, SecretKeyStore, SecretKeyStoreError};
+use crate::secret_key_store::{
+    remove_scrubbed, Scope, SecretKeyStore, SecretKeyStoreError, SecretKeyStorePersistenceError,
+};
 use crate::types::CspSecretKey;
 use ic_types::crypto::KeyId;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::panic::{self, AssertUnwindSafe};
 
 /// An in-memory, non-thread-safe secret key store for testing purposes.
 #[allow(unused)]
@@ -18,6 +21,13 @@ impl VolatileSecretKeyStore {
             keys: HashMap::new(),
         }
     }
+
+    /// Removes the keys with the given IDs, scrubbing them.
+    fn remove_keys(&mut self, key_ids: &[KeyId]) {
+        for id in key_ids {
+            remove_scrubbed(&mut self.keys, id);
+        }
+    }
 }
 
 impl SecretKeyStore for VolatileSecretKeyStore {
@@ -42,32 +52,92 @@ impl SecretKeyStore for VolatileSecretKeyStore {
             .cloned()
     }
 
+    fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>> {
+        self.keys.get(id).map(|(_secret_key, scope)| *scope)
+    }
+
     fn contains(&self, id: &KeyId) -> bool {
         self.keys.contains_key(id)
     }
 
     fn remove(&mut self, id: &KeyId) -> bool {
-        self.keys.remove(id).is_some()
+        remove_scrubbed(&mut self.keys, id)
+    }
+
+    fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId> {
+        let mut key_ids: Vec<KeyId> = self
+            .keys
+            .iter()
+            .filter(|(_, (_, key_scope))| *key_scope == Some(scope))
+            .map(|(id, _)| *id)
+            .collect();
+        key_ids.sort();
+        key_ids
+    }
+
+    fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize> {
+        let mut counts = BTreeMap::new();
+        for (_, key_scope) in self.keys.values() {
+            *counts.entry(*key_scope).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError> {
+        let keys_to_remove = self.keys_in_scope(scope);
+        self.remove_keys(&keys_to_remove);
+        Ok(keys_to_remove.len())
     }
 
     fn retain<F>(&mut self, filter: F, scope: Scope)
     where
         F: Fn(&KeyId, &CspSecretKey) -> bool,
     {
-        let mut keys = HashMap::new();
-        core::mem::swap(&mut keys, &mut self.keys);
-        self.keys = keys
-            .into_iter()
-            .filter(|(id, (key, key_scope))| {
-                let keep = (*key_scope != Some(scope)) || filter(id, key);
-                if !keep {
-                    // Production code should use a logger.  The volatile key store has none so we
-                    // print instead.
-                    println!("WARNING: Deleting key ID {}", id);
-                }
-                keep
-            })
+        let keys_to_remove: Vec<KeyId> = self
+            .keys
+            .iter()
+            .filter(|(id, (key, key_scope))| *key_scope == Some(scope) && !filter(id, key))
+            .map(|(id, _)| *id)
             .collect();
+        self.remove_keys(&keys_to_remove);
+    }
+
+    fn try_retain<F>(&mut self, filter: F, scope: Scope) -> Result<(), SecretKeyStoreError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        let keys_to_remove = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.keys
+                .iter()
+                .filter(|(id, (key, key_scope))| *key_scope == Some(scope) && !filter(id, key))
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>()
+        }))
+        .map_err(|_| SecretKeyStoreError::FilterPanicked)?;
+        self.remove_keys(&keys_to_remove);
+        Ok(())
+    }
+
+    fn retain_then_insert<F>(
+        &mut self,
+        filter: F,
+        scope: Scope,
+        new: (KeyId, CspSecretKey, Option<Scope>),
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        let (new_id, new_key, new_scope) = new;
+        let retained_new_id = self
+            .keys
+            .get(&new_id)
+            .map(|(key, key_scope)| (*key_scope != Some(scope)) || filter(&new_id, key));
+        if retained_new_id == Some(true) {
+            return Err(SecretKeyStorePersistenceError::DuplicateKeyId(new_id));
+        }
+        self.retain(filter, scope);
+        self.keys.insert(new_id, (new_key, new_scope));
+        Ok(())
     }
 }
 
@@ -113,6 +183,21 @@ mod tests {
             test_utils::no_overwrites(seed1, seed2, seed3, volatile_key_store());
         }
 
+        #[test]
+        fn should_insert_batch_of_keys(seed: u64) {
+            test_utils::should_insert_batch_of_keys(seed, volatile_key_store());
+        }
+
+        #[test]
+        fn insert_batch_should_not_insert_any_key_if_one_exists(seed: u64) {
+            test_utils::insert_batch_should_not_insert_any_key_if_one_exists(seed, volatile_key_store());
+        }
+
+        #[test]
+        fn insert_batch_should_not_insert_any_key_if_batch_contains_duplicates(seed: u64) {
+            test_utils::insert_batch_should_not_insert_any_key_if_batch_contains_duplicates(seed, volatile_key_store());
+        }
+
         ////////////////////////////////////////////////////////////////////////////////////////
         // If you add tests here, remember to also add them for the ProtoSecretKeyStore
         ////////////////////////////////////////////////////////////////////////////////////////
@@ -123,6 +208,72 @@ mod tests {
         test_utils::should_retain_expected_keys(volatile_key_store());
     }
 
+    #[test]
+    fn should_get_scope_of_key() {
+        test_utils::should_get_scope_of_key(volatile_key_store());
+    }
+
+    #[test]
+    fn should_list_sorted_keys_in_scope() {
+        test_utils::should_list_sorted_keys_in_scope(volatile_key_store());
+    }
+
+    #[test]
+    fn should_count_keys_by_scope() {
+        test_utils::should_count_keys_by_scope(volatile_key_store());
+    }
+
+    #[test]
+    fn should_clear_only_keys_in_given_scope() {
+        test_utils::should_clear_only_keys_in_given_scope(volatile_key_store());
+    }
+
+    #[test]
+    fn should_drop_only_ephemeral_keys() {
+        test_utils::should_drop_only_ephemeral_keys(volatile_key_store());
+    }
+
+    #[test]
+    fn should_try_retain_expected_keys() {
+        test_utils::should_try_retain_expected_keys(volatile_key_store());
+    }
+
+    #[test]
+    fn should_leave_store_unchanged_if_try_retain_filter_panics() {
+        test_utils::should_leave_store_unchanged_if_try_retain_filter_panics(volatile_key_store());
+    }
+
+    #[test]
+    fn should_retain_then_insert() {
+        test_utils::should_retain_then_insert(volatile_key_store());
+    }
+
+    #[test]
+    fn retain_then_insert_should_fail_if_new_key_is_retained() {
+        test_utils::retain_then_insert_should_fail_if_new_key_is_retained(volatile_key_store());
+    }
+
+    #[test]
+    fn clear_scope_should_return_injected_error_and_leave_store_unchanged() {
+        test_utils::clear_scope_should_return_injected_error_and_leave_store_unchanged(
+            volatile_key_store(),
+        );
+    }
+
+    #[test]
+    fn retain_then_insert_should_return_injected_error_and_leave_store_unchanged() {
+        test_utils::retain_then_insert_should_return_injected_error_and_leave_store_unchanged(
+            volatile_key_store(),
+        );
+    }
+
+    #[test]
+    fn should_forward_persisting_calls_if_injected_error_is_discarded() {
+        test_utils::should_forward_persisting_calls_if_injected_error_is_discarded(
+            volatile_key_store(),
+        );
+    }
+
     fn volatile_key_store() -> VolatileSecretKeyStore {
         VolatileSecretKeyStore::new()
     }
@@ -85,6 +85,9 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> DistributedKeyGenerationCspClient fo
                         panic!("Could not insert key but it is not present.");
                     }
                 }
+                Err(err) => {
+                    panic!("Could not insert ephemeral key: {}", err)
+                }
             }
         }
     }
@@ -295,6 +298,9 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> DistributedKeyGenerationCspClient fo
                     Err(SecretKeyStoreError::DuplicateKeyId(_key_id)) => {
                         sks_write_lock.remove(&ephemeral_key_id);
                     }
+                    Err(err) => {
+                        panic!("Could not insert threshold key: {}", err)
+                    }
                 }
             }
         }
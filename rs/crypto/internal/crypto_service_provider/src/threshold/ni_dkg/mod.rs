@@ -4,7 +4,7 @@
 //! including the secret key store and random number generator, and the
 //! stateless crypto lib.
 
-use crate::api::{NiDkgCspClient, NodePublicKeyData};
+use crate::api::{NiDkgCspClient, NodePublicKeyData, ThresholdRetentionReport};
 use crate::keygen::forward_secure_key_id;
 use crate::secret_key_store::{SecretKeyStore, SecretKeyStoreError};
 use crate::types::conversions::key_id_from_csp_pub_coeffs;
@@ -78,6 +78,8 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> NiDkgCspClient for Csp<R, S> {
                 panic!(
                     "Could not insert key as the KeyId is already in use.  This suggests an insecure RNG."
                 ),
+              _ =>
+                panic!("Could not insert forward secure key: {}", err),
             };
         };
 
@@ -141,6 +143,9 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> NiDkgCspClient for Csp<R, S> {
         ) {
             match err {
                 SecretKeyStoreError::DuplicateKeyId(_key_id) => unreachable!(),
+                _ => {
+                    panic!("Could not insert forward secure key: {}", err)
+                }
             };
         };
 
@@ -409,6 +414,9 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> NiDkgCspClient for Csp<R, S> {
                 ) {
                     Ok(()) => Ok(()),
                     Err(SecretKeyStoreError::DuplicateKeyId(_key_id)) => Ok(()),
+                    Err(err) => {
+                        panic!("Could not insert threshold key: {}", err)
+                    }
                 }
             }
             other => Err(ni_dkg_errors::CspDkgLoadPrivateKeyError::UnsupportedAlgorithmId(other)),
@@ -424,6 +432,27 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> NiDkgCspClient for Csp<R, S> {
             NIDKG_THRESHOLD_SCOPE,
         )
     }
+
+    fn retain_threshold_keys_if_present_with_report(
+        &self,
+        active_keys: BTreeSet<CspPublicCoefficients>,
+    ) -> ThresholdRetentionReport {
+        debug!(self.logger; crypto.method_name => "retain_threshold_keys_if_present_with_report");
+        let active_key_ids: BTreeSet<KeyId> =
+            active_keys.iter().map(key_id_from_csp_pub_coeffs).collect();
+        // Hold the lock across listing and retaining, so that the report
+        // matches the keys that were actually removed.
+        let mut sks = self.sks_write_lock();
+        let (retained, removed) = sks
+            .keys_in_scope(NIDKG_THRESHOLD_SCOPE)
+            .into_iter()
+            .partition(|key_id| active_key_ids.contains(key_id));
+        sks.retain(
+            |key_id, _| active_key_ids.contains(key_id),
+            NIDKG_THRESHOLD_SCOPE,
+        );
+        ThresholdRetentionReport { retained, removed }
+    }
 }
 
 pub mod static_api {
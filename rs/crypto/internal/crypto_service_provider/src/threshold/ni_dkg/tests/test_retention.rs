@@ -1,13 +1,19 @@
 #![allow(clippy::unwrap_used)]
 use super::fixtures::cache::STATE_WITH_TRANSCRIPT;
-use crate::api::NiDkgCspClient;
+use crate::api::{NiDkgCspClient, ThresholdRetentionReport};
+use crate::secret_key_store::test_utils::{make_key_id, make_secret_key};
 use crate::secret_key_store::volatile_store::VolatileSecretKeyStore;
+use crate::secret_key_store::SecretKeyStore;
 use crate::threshold::ni_dkg::tests::fixtures::StateWithTranscript;
+use crate::threshold::ni_dkg::NIDKG_THRESHOLD_SCOPE;
 use crate::threshold::ThresholdSignatureCspClient;
 use crate::types as csp_types;
+use crate::types::conversions::key_id_from_csp_pub_coeffs;
 use crate::Csp;
 use ic_crypto_internal_types::sign::threshold_sig::ni_dkg::ni_dkg_groth20_bls12_381::PublicCoefficientsBytes;
+use ic_crypto_internal_types::sign::threshold_sig::public_key::bls12_381::PublicKeyBytes;
 use ic_types::crypto::AlgorithmId;
+use rand::SeedableRng;
 use rand_chacha::ChaCha20Rng;
 use std::collections::BTreeSet;
 
@@ -125,3 +131,58 @@ fn test_retention() {
         .expect("The key should be there initially");
     }
 }
+
+/// Verifies that the retention report partitions the threshold keys into
+/// retained and removed keys, and ignores keys in other scopes.
+///
+/// No NiDKG is run: the threshold keys are inserted into the secret key store
+/// directly under the key IDs derived from their public coefficients.
+#[test]
+fn should_report_retained_and_removed_threshold_keys() {
+    fn public_coefficients(num_coefficients: usize) -> csp_types::CspPublicCoefficients {
+        csp_types::CspPublicCoefficients::Bls12_381(PublicCoefficientsBytes {
+            coefficients: (0..num_coefficients)
+                .map(|_| PublicKeyBytes([0; PublicKeyBytes::SIZE]))
+                .collect(),
+        })
+    }
+    let (active_1, active_2, inactive) = (
+        public_coefficients(1),
+        public_coefficients(2),
+        public_coefficients(3),
+    );
+    let mut sks = VolatileSecretKeyStore::new();
+    for (seed, coefficients) in [&active_1, &active_2, &inactive].iter().enumerate() {
+        sks.insert(
+            key_id_from_csp_pub_coeffs(coefficients),
+            make_secret_key(seed as u64),
+            Some(NIDKG_THRESHOLD_SCOPE),
+        )
+        .expect("failed to insert threshold key");
+    }
+    let unscoped_key_id = make_key_id(42);
+    sks.insert(unscoped_key_id, make_secret_key(42), None)
+        .expect("failed to insert unscoped key");
+    let csp = Csp::of(ChaCha20Rng::seed_from_u64(42), sks);
+
+    let active_keys: BTreeSet<_> = vec![active_1.clone(), active_2.clone(), public_coefficients(4)]
+        .into_iter()
+        .collect();
+    let report = csp.retain_threshold_keys_if_present_with_report(active_keys);
+
+    let mut expected_retained = vec![
+        key_id_from_csp_pub_coeffs(&active_1),
+        key_id_from_csp_pub_coeffs(&active_2),
+    ];
+    expected_retained.sort();
+    assert_eq!(
+        report,
+        ThresholdRetentionReport {
+            retained: expected_retained,
+            removed: vec![key_id_from_csp_pub_coeffs(&inactive)],
+        }
+    );
+    let sks = csp.sks_read_lock();
+    assert!(!sks.contains(&key_id_from_csp_pub_coeffs(&inactive)));
+    assert!(sks.contains(&unscoped_key_id));
+}
@@ -7,11 +7,13 @@ use crate::tls_stub::{
 };
 use crate::Csp;
 use async_trait::async_trait;
-use ic_crypto_tls_interfaces::TlsStream;
+use ic_crypto_tls_interfaces::{TlsCipherSuite, TlsStream};
 use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
-use openssl::ssl::SslAcceptor;
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
+use openssl::ssl::{HandshakeError, SslAcceptor};
 use openssl::x509::X509;
 use rand::{CryptoRng, Rng};
+use std::cmp::Ordering;
 use tokio::net::TcpStream;
 
 #[cfg(test)]
@@ -24,18 +26,59 @@ impl<R: Rng + CryptoRng + Send + Sync, S: SecretKeyStore> CspTlsServerHandshake
         tcp_stream: TcpStream,
         self_cert: X509PublicKeyCert,
         trusted_client_certs: Vec<X509PublicKeyCert>,
+        allowed_cipher_suites: Vec<TlsCipherSuite>,
+        alpn_protocol: Option<Vec<u8>>,
     ) -> Result<(TlsStream, Option<CspCertificateChain>), CspTlsServerHandshakeError> {
-        let tls_acceptor = self.tls_acceptor(self_cert, trusted_client_certs.clone())?;
+        let tls_acceptor = self.tls_acceptor(
+            self_cert,
+            trusted_client_certs.clone(),
+            &allowed_cipher_suites,
+            alpn_protocol.as_deref(),
+        )?;
 
         let tls_stream = tokio_openssl::accept(&tls_acceptor, tcp_stream)
             .await
-            .map_err(|e| CspTlsServerHandshakeError::HandshakeError {
-                internal_error: format!("Handshake failed in tokio_openssl:accept: {}", e),
+            .map_err(|e| match &e {
+                HandshakeError::Failure(mid_handshake_stream)
+                    if ic_crypto_internal_tls::no_matching_alpn_protocol(
+                        mid_handshake_stream.ssl(),
+                    ) =>
+                {
+                    CspTlsServerHandshakeError::NoMatchingAlpnProtocol
+                }
+                _ => CspTlsServerHandshakeError::HandshakeError {
+                    internal_error: format!("Handshake failed in tokio_openssl:accept: {}", e),
+                },
             })?;
 
         let peer_cert_chain = peer_cert_chain_from_stream(&tls_stream)?;
+        // The acceptor leaves the expiry of the client certificate to us.
+        if let Some(peer_cert_chain) = &peer_cert_chain {
+            ensure_peer_cert_not_expired(peer_cert_chain.leaf(), &current_time()?)?;
+        }
         Ok((TlsStream::new(tls_stream), peer_cert_chain))
     }
+
+    async fn perform_tls_server_handshake_without_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        self_cert: X509PublicKeyCert,
+        allow_tls12: bool,
+    ) -> Result<TlsStream, CspTlsServerHandshakeError> {
+        let self_cert_x509 = self_cert_x509(&self_cert)?;
+        let tls_acceptor = ic_crypto_internal_tls::tls_acceptor_without_client_auth(
+            &key_from_secret_key_store(&*self.sks_read_lock(), &self_cert)?,
+            &self_cert_x509,
+            allow_tls12,
+        )?;
+
+        let tls_stream = tokio_openssl::accept(&tls_acceptor, tcp_stream)
+            .await
+            .map_err(|e| CspTlsServerHandshakeError::HandshakeError {
+                internal_error: format!("Handshake failed in tokio_openssl:accept: {}", e),
+            })?;
+        Ok(TlsStream::new(tls_stream))
+    }
 }
 
 impl<R: Rng + CryptoRng, S: SecretKeyStore> Csp<R, S> {
@@ -43,20 +86,62 @@ impl<R: Rng + CryptoRng, S: SecretKeyStore> Csp<R, S> {
     /// server. The `self_cert` is used as server certificate and the
     /// corresponding private key must be in the secret key store. The
     /// server will only allow TLS connections from clients that
-    /// authenticate with a client certificate in `trusted_client_certs`.
+    /// authenticate with a client certificate in `trusted_client_certs`, and
+    /// only using one of the `allowed_cipher_suites`. If an `alpn_protocol` is
+    /// given, it is selected via ALPN.
     fn tls_acceptor(
         &self,
         self_cert: X509PublicKeyCert,
         trusted_client_certs: Vec<X509PublicKeyCert>,
+        allowed_cipher_suites: &[TlsCipherSuite],
+        alpn_protocol: Option<&[u8]>,
     ) -> Result<SslAcceptor, CspTlsServerHandshakeError> {
         let self_cert_x509 = self_cert_x509(&self_cert)?;
         let trusted_client_certs_x509 = trusted_client_certs_x509(trusted_client_certs)?;
-        Ok(ic_crypto_internal_tls::tls_acceptor(
-            &key_from_secret_key_store(&*self.sks_read_lock(), &self_cert)?,
-            &self_cert_x509,
-            trusted_client_certs_x509,
-        )?)
+        let allowed_cipher_suite_names: Vec<&str> = allowed_cipher_suites
+            .iter()
+            .map(TlsCipherSuite::name)
+            .collect();
+        Ok(
+            ic_crypto_internal_tls::tls_acceptor_with_cipher_suites_and_alpn(
+                &key_from_secret_key_store(&*self.sks_read_lock(), &self_cert)?,
+                &self_cert_x509,
+                trusted_client_certs_x509,
+                &allowed_cipher_suite_names,
+                alpn_protocol,
+            )?,
+        )
+    }
+}
+
+fn current_time() -> Result<Asn1Time, CspTlsServerHandshakeError> {
+    Asn1Time::days_from_now(0).map_err(|e| CspTlsServerHandshakeError::HandshakeError {
+        internal_error: format!("Failed to determine the current time: {}", e),
+    })
+}
+
+/// Ensures that the peer's certificate has not expired at time `now`. Like
+/// OpenSSL, a certificate is considered expired from its `notAfter` on.
+fn ensure_peer_cert_not_expired(
+    peer_cert: &X509,
+    now: &Asn1TimeRef,
+) -> Result<(), CspTlsServerHandshakeError> {
+    let not_after = peer_cert.not_after();
+    let ordering =
+        not_after
+            .compare(now)
+            .map_err(|e| CspTlsServerHandshakeError::HandshakeError {
+                internal_error: format!(
+                    "Failed to compare the peer certificate's notAfter with the current time: {}",
+                    e
+                ),
+            })?;
+    if ordering != Ordering::Greater {
+        return Err(CspTlsServerHandshakeError::PeerCertificateExpired {
+            not_after: not_after.to_string(),
+        });
     }
+    Ok(())
 }
 
 fn self_cert_x509(self_cert: &X509PublicKeyCert) -> Result<X509, CspTlsServerHandshakeError> {
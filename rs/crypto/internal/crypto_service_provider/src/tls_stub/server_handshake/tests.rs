@@ -1,4 +1,5 @@
 #![allow(clippy::unwrap_used)]
+use super::ensure_peer_cert_not_expired;
 use crate::api::tls_errors::CspTlsServerHandshakeError;
 use crate::api::CspTlsServerHandshake;
 use crate::secret_key_store::test_utils::TempSecretKeyStore;
@@ -9,10 +10,18 @@ use crate::tls_stub::test_utils::{
 use crate::types::CspSecretKey;
 use crate::Csp;
 use ic_crypto_internal_multi_sig_bls12381::types::SecretKeyBytes;
+use ic_crypto_test_utils::tls::custom_client::{CustomClient, CustomClientBuilder};
 use ic_crypto_test_utils::tls::x509_certificates::{
     cert_to_der, generate_ed25519_cert, private_key_to_der, x509_public_key_cert,
+    CertWithPrivateKey,
 };
+use ic_crypto_tls_interfaces::{TlsCipherSuite, DEFAULT_TLS_CIPHER_SUITES};
+use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
+use openssl::asn1::Asn1Time;
+use openssl::ssl::SslVersion;
+use openssl::x509::X509;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
 
 #[test]
 fn should_return_acceptor_from_clib_if_no_error_occurs() {
@@ -25,6 +34,8 @@ fn should_return_acceptor_from_clib_if_no_error_occurs() {
         .tls_acceptor(
             x509_public_key_cert(&self_cert_x509),
             vec![x509_public_key_cert(&trusted_client_cert)],
+            DEFAULT_TLS_CIPHER_SUITES,
+            None,
         )
         .unwrap();
 
@@ -50,6 +61,8 @@ async fn should_return_create_acceptor_error_from_clib() {
             dummy_tcp_stream().await,
             x509_public_key_cert(&self_cert_x509),
             vec![],
+            DEFAULT_TLS_CIPHER_SUITES.to_vec(),
+            None,
         )
         .await;
 
@@ -60,6 +73,206 @@ async fn should_return_create_acceptor_error_from_clib() {
     );
 }
 
+#[tokio::test]
+async fn should_return_create_acceptor_error_if_allowed_cipher_suites_empty() {
+    let (private_key, self_cert_x509) = generate_ed25519_cert();
+    let sks = secret_key_store_with_key(&private_key, &self_cert_x509);
+    let csp = Csp::of(dummy_csprng(), sks);
+    let (_, trusted_client_cert) = generate_ed25519_cert();
+
+    let result = csp
+        .perform_tls_server_handshake(
+            dummy_tcp_stream().await,
+            x509_public_key_cert(&self_cert_x509),
+            vec![x509_public_key_cert(&trusted_client_cert)],
+            vec![],
+            None,
+        )
+        .await;
+
+    assert!(
+        matches!(result, Err(CspTlsServerHandshakeError::CreateAcceptorError { description, .. })
+            if description == "The allowed cipher suites must not be empty."
+        )
+    );
+}
+
+#[tokio::test]
+async fn should_negotiate_only_allowed_cipher_suite() {
+    let setup = setup_server_and_client(
+        Some(CertWithPrivateKey::builder().build_ed25519()),
+        CustomClient::builder()
+            .with_allowed_cipher_suites("TLS_AES_256_GCM_SHA384:TLS_AES_128_GCM_SHA256")
+            .expect_negotiated_cipher_suite("TLS_AES_128_GCM_SHA256"),
+    )
+    .await;
+
+    let result = setup
+        .csp
+        .perform_tls_server_handshake(
+            setup.tcp_stream,
+            setup.self_cert,
+            setup.trusted_client_certs,
+            vec![TlsCipherSuite::Aes128GcmSha256],
+            None,
+        )
+        .await;
+
+    assert!(result.is_ok());
+    setup.client_handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn should_report_negotiated_tls_version_and_cipher_suite() {
+    let setup = setup_server_and_client(
+        Some(CertWithPrivateKey::builder().build_ed25519()),
+        CustomClient::builder(),
+    )
+    .await;
+
+    let (tls_stream, _) = setup
+        .csp
+        .perform_tls_server_handshake(
+            setup.tcp_stream,
+            setup.self_cert,
+            setup.trusted_client_certs,
+            DEFAULT_TLS_CIPHER_SUITES.to_vec(),
+            None,
+        )
+        .await
+        .unwrap();
+
+    let session_info = tls_stream.session_info();
+    assert_eq!(session_info.version, "TLSv1.3");
+    let cipher_suite = session_info.cipher_suite.unwrap();
+    assert!(DEFAULT_TLS_CIPHER_SUITES
+        .iter()
+        .any(|allowed| allowed.name() == cipher_suite));
+    setup.client_handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn should_select_alpn_protocol_offered_by_client() {
+    let setup = setup_server_and_client(
+        Some(CertWithPrivateKey::builder().build_ed25519()),
+        CustomClient::builder().with_alpn_protocols(&[b"other/1", b"ic-node/1"]),
+    )
+    .await;
+
+    let (tls_stream, _) = setup
+        .csp
+        .perform_tls_server_handshake(
+            setup.tcp_stream,
+            setup.self_cert,
+            setup.trusted_client_certs,
+            DEFAULT_TLS_CIPHER_SUITES.to_vec(),
+            Some(b"ic-node/1".to_vec()),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(tls_stream.alpn_protocol(), Some(b"ic-node/1".to_vec()));
+    setup.client_handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn should_return_no_matching_alpn_protocol_error_if_client_offers_only_unsupported_protocols()
+{
+    let setup = setup_server_and_client(
+        Some(CertWithPrivateKey::builder().build_ed25519()),
+        CustomClient::builder()
+            .with_alpn_protocols(&[b"h2"])
+            .expect_error("no application protocol"),
+    )
+    .await;
+
+    let result = setup
+        .csp
+        .perform_tls_server_handshake(
+            setup.tcp_stream,
+            setup.self_cert,
+            setup.trusted_client_certs,
+            DEFAULT_TLS_CIPHER_SUITES.to_vec(),
+            Some(b"ic-node/1".to_vec()),
+        )
+        .await;
+
+    assert_eq!(
+        result.err(),
+        Some(CspTlsServerHandshakeError::NoMatchingAlpnProtocol)
+    );
+    setup.client_handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn should_return_peer_certificate_expired_error_if_client_cert_has_expired() {
+    let setup = setup_server_and_client(
+        Some(
+            CertWithPrivateKey::builder()
+                .not_before(NOT_BEFORE)
+                .not_after(NOT_AFTER)
+                .build_ed25519(),
+        ),
+        CustomClient::builder(),
+    )
+    .await;
+
+    let result = setup
+        .csp
+        .perform_tls_server_handshake(
+            setup.tcp_stream,
+            setup.self_cert,
+            setup.trusted_client_certs,
+            DEFAULT_TLS_CIPHER_SUITES.to_vec(),
+            None,
+        )
+        .await;
+
+    assert_eq!(
+        result.err(),
+        Some(CspTlsServerHandshakeError::PeerCertificateExpired {
+            not_after: "Jan  1 00:00:00 2020 GMT".to_string()
+        })
+    );
+    setup.client_handle.await.unwrap();
+}
+
+#[test]
+fn should_accept_peer_cert_that_has_not_expired() {
+    let now = Asn1Time::from_str_x509("20190601000000Z").unwrap();
+
+    assert_eq!(
+        ensure_peer_cert_not_expired(&cert_valid_from_2019_to_2020(), &now),
+        Ok(())
+    );
+}
+
+#[test]
+fn should_reject_peer_cert_at_not_after() {
+    let cert = cert_valid_from_2019_to_2020();
+    let now = Asn1Time::from_str_x509(NOT_AFTER).unwrap();
+
+    assert_eq!(
+        ensure_peer_cert_not_expired(&cert, &now),
+        Err(CspTlsServerHandshakeError::PeerCertificateExpired {
+            not_after: cert.not_after().to_string()
+        })
+    );
+}
+
+#[test]
+fn should_reject_peer_cert_that_has_expired() {
+    let cert = cert_valid_from_2019_to_2020();
+    let now = Asn1Time::from_str_x509("20210101000000Z").unwrap();
+
+    assert_eq!(
+        ensure_peer_cert_not_expired(&cert, &now),
+        Err(CspTlsServerHandshakeError::PeerCertificateExpired {
+            not_after: cert.not_after().to_string()
+        })
+    );
+}
+
 #[tokio::test]
 async fn should_return_error_if_secret_key_not_found() {
     let (_, self_cert_x509) = generate_ed25519_cert();
@@ -71,6 +284,8 @@ async fn should_return_error_if_secret_key_not_found() {
             dummy_tcp_stream().await,
             x509_public_key_cert(&self_cert_x509),
             vec![],
+            DEFAULT_TLS_CIPHER_SUITES.to_vec(),
+            None,
         )
         .await;
 
@@ -93,6 +308,8 @@ async fn should_return_error_on_wrong_secret_key_type() {
             dummy_tcp_stream().await,
             x509_public_key_cert(&self_cert_x509),
             vec![],
+            DEFAULT_TLS_CIPHER_SUITES.to_vec(),
+            None,
         )
         .await;
 
@@ -114,6 +331,8 @@ async fn should_return_error_on_malformed_secret_key() {
             dummy_tcp_stream().await,
             x509_public_key_cert(&self_cert_x509),
             vec![],
+            DEFAULT_TLS_CIPHER_SUITES.to_vec(),
+            None,
         )
         .await;
 
@@ -131,7 +350,13 @@ async fn should_return_error_on_malformed_self_cert() {
     let malformed_self_cert = malformed_cert();
 
     let error = csp
-        .perform_tls_server_handshake(dummy_tcp_stream().await, malformed_self_cert, vec![])
+        .perform_tls_server_handshake(
+            dummy_tcp_stream().await,
+            malformed_self_cert,
+            vec![],
+            DEFAULT_TLS_CIPHER_SUITES.to_vec(),
+            None,
+        )
         .await
         .err()
         .unwrap();
@@ -155,6 +380,8 @@ async fn should_return_error_on_malformed_client_cert() {
             dummy_tcp_stream().await,
             x509_public_key_cert(&self_cert_x509),
             vec![malformed_client_cert],
+            DEFAULT_TLS_CIPHER_SUITES.to_vec(),
+            None,
         )
         .await
         .err()
@@ -167,6 +394,119 @@ async fn should_return_error_on_malformed_client_cert() {
     }
 }
 
+#[tokio::test]
+async fn should_negotiate_tls_12_without_client_auth_if_allowed() {
+    let setup = setup_server_and_client(
+        None,
+        CustomClient::builder().with_max_protocol_version(SslVersion::TLS1_2),
+    )
+    .await;
+
+    let tls_stream = setup
+        .csp
+        .perform_tls_server_handshake_without_client_auth(setup.tcp_stream, setup.self_cert, true)
+        .await
+        .unwrap();
+
+    assert_eq!(tls_stream.session_info().version, "TLSv1.2");
+    setup.client_handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn should_return_handshake_error_for_tls_12_client_without_client_auth_if_not_allowed() {
+    let setup = setup_server_and_client(
+        None,
+        CustomClient::builder()
+            .with_max_protocol_version(SslVersion::TLS1_2)
+            .expect_error("tlsv1 alert protocol version"),
+    )
+    .await;
+
+    let result = setup
+        .csp
+        .perform_tls_server_handshake_without_client_auth(setup.tcp_stream, setup.self_cert, false)
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(CspTlsServerHandshakeError::HandshakeError { .. })
+    ));
+    setup.client_handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn should_negotiate_tls_13_without_client_auth() {
+    let setup = setup_server_and_client(None, CustomClient::builder()).await;
+
+    let tls_stream = setup
+        .csp
+        .perform_tls_server_handshake_without_client_auth(setup.tcp_stream, setup.self_cert, false)
+        .await
+        .unwrap();
+
+    assert_eq!(tls_stream.session_info().version, "TLSv1.3");
+    setup.client_handle.await.unwrap();
+}
+
+const NOT_BEFORE: &str = "20190101000000Z";
+const NOT_AFTER: &str = "20200101000000Z";
+
+fn cert_valid_from_2019_to_2020() -> X509 {
+    CertWithPrivateKey::builder()
+        .not_before(NOT_BEFORE)
+        .not_after(NOT_AFTER)
+        .build_ed25519()
+        .x509()
+}
+
+/// The server side of a TCP connection from a `CustomClient` to a CSP that
+/// has the TLS secret key for `self_cert`. The client runs concurrently and
+/// performs its part of the TLS handshake once the server starts it.
+struct ServerAndClient<C> {
+    csp: C,
+    self_cert: X509PublicKeyCert,
+    trusted_client_certs: Vec<X509PublicKeyCert>,
+    tcp_stream: TcpStream,
+    client_handle: JoinHandle<()>,
+}
+
+/// Sets up a server CSP with a freshly generated TLS certificate, and
+/// connects a client built from `client_builder` to it. If `client_cert` is
+/// given, the client authenticates with it, and it is the only trusted
+/// client certificate; otherwise, the client does not authenticate.
+async fn setup_server_and_client(
+    client_cert: Option<CertWithPrivateKey>,
+    client_builder: CustomClientBuilder,
+) -> ServerAndClient<impl CspTlsServerHandshake> {
+    let (private_key, self_cert_x509) = generate_ed25519_cert();
+    let sks = secret_key_store_with_key(&private_key, &self_cert_x509);
+    let csp = Csp::of(dummy_csprng(), sks);
+    let self_cert = x509_public_key_cert(&self_cert_x509);
+    let (client_builder, trusted_client_certs) = match client_cert {
+        Some(client_cert) => {
+            let trusted_client_cert = x509_public_key_cert(&client_cert.x509());
+            (
+                client_builder.with_client_auth(client_cert),
+                vec![trusted_client_cert],
+            )
+        }
+        None => (client_builder.without_client_auth(), vec![]),
+    };
+    let client = client_builder.build(self_cert.clone());
+    let mut listener = TcpListener::bind(("0.0.0.0", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let client_handle = tokio::spawn(client.run(port));
+    let (tcp_stream, _) = listener.accept().await.unwrap();
+    ServerAndClient {
+        csp,
+        self_cert,
+        trusted_client_certs,
+        tcp_stream,
+        client_handle,
+    }
+}
+
 async fn dummy_tcp_stream() -> TcpStream {
     let listener = TcpListener::bind(("0.0.0.0", 0)).await.unwrap();
     let port = listener.local_addr().unwrap().port();
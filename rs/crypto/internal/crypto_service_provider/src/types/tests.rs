@@ -42,6 +42,38 @@ fn should_return_no_ed25519_secret_key_bytes_for_non_ed25519_secret_key() {
     assert!(secret_key.ed25519_bytes().is_none())
 }
 
+#[test]
+fn should_zeroize_csp_secret_key_ed25519_bytes() {
+    let secret_key = CspSecretKey::Ed25519(ed25519_types::SecretKeyBytes(
+        [1u8; ed25519_types::SecretKeyBytes::SIZE],
+    ));
+    let mut cloned_secret_key = secret_key.clone();
+
+    cloned_secret_key.zeroize();
+
+    assert_eq!(
+        cloned_secret_key.ed25519_bytes(),
+        Some(&[0u8; ed25519_types::SecretKeyBytes::SIZE])
+    );
+    assert_ne!(cloned_secret_key, secret_key);
+}
+
+#[test]
+fn should_zeroize_csp_secret_key_multi_bytes() {
+    let mut secret_key = CspSecretKey::MultiBls12_381(multi_types::SecretKeyBytes(
+        [1u8; multi_types::SecretKeyBytes::SIZE],
+    ));
+
+    secret_key.zeroize();
+
+    assert_eq!(
+        secret_key,
+        CspSecretKey::MultiBls12_381(multi_types::SecretKeyBytes(
+            [0u8; multi_types::SecretKeyBytes::SIZE]
+        ))
+    );
+}
+
 #[test]
 fn should_redact_csp_secret_key_ed25519_debug() {
     let cspsk_ed25519 = CspSecretKey::Ed25519(ed25519_types::SecretKeyBytes(
@@ -11,18 +11,112 @@ use ic_types::crypto::KeyId;
 use mockall::predicate::*;
 use mockall::*;
 use secret_key_store::proto_store::ProtoSecretKeyStore;
-use secret_key_store::{Scope, SecretKeyStore, SecretKeyStoreError};
+use secret_key_store::{
+    Scope, SecretKeyStore, SecretKeyStoreError, SecretKeyStorePersistenceError,
+};
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
 use tempfile::TempDir;
 
 mock! {
-    /// Mock SecretKeyStore object for testing interactions
-    pub SecretKeyStore {}
+    /// Mocked calls of a `MockSecretKeyStore`
+    pub SecretKeyStoreCalls {
+        pub fn insert(&mut self, id: KeyId, key: CspSecretKey, scope: Option<Scope>) -> Result<(), SecretKeyStoreError>;
+        pub fn get(&self, id: &KeyId) -> Option<CspSecretKey>;
+        pub fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>>;
+        pub fn contains(&self, id: &KeyId) -> bool;
+        pub fn remove(&mut self, id: &KeyId) -> bool;
+        pub fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId>;
+        pub fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize>;
+        pub fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError>;
+    }
+}
+
+/// Mock SecretKeyStore object for testing interactions
+///
+/// Expectations are set on the wrapped `MockSecretKeyStoreCalls`, e.g.
+/// `sks.expect_get()`. The methods taking a filter are generic over
+/// non-`'static` closures, which cannot be mocked, so calling them panics
+/// like any call without a matching expectation.
+pub struct MockSecretKeyStore(MockSecretKeyStoreCalls);
+
+impl MockSecretKeyStore {
+    /// Create a new MockSecretKeyStore without any expectations.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(MockSecretKeyStoreCalls::new())
+    }
+}
+
+impl Deref for MockSecretKeyStore {
+    type Target = MockSecretKeyStoreCalls;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for MockSecretKeyStore {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl SecretKeyStore for MockSecretKeyStore {
+    fn insert(
+        &mut self,
+        id: KeyId,
+        key: CspSecretKey,
+        scope: Option<Scope>,
+    ) -> Result<(), SecretKeyStoreError> {
+        self.0.insert(id, key, scope)
+    }
+
+    fn get(&self, id: &KeyId) -> Option<CspSecretKey> {
+        self.0.get(id)
+    }
+
+    fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>> {
+        self.0.get_scope(id)
+    }
+
+    fn contains(&self, id: &KeyId) -> bool {
+        self.0.contains(id)
+    }
+
+    fn remove(&mut self, id: &KeyId) -> bool {
+        self.0.remove(id)
+    }
+
+    fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId> {
+        self.0.keys_in_scope(scope)
+    }
+
+    fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize> {
+        self.0.count_by_scope()
+    }
+
+    fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError> {
+        self.0.clear_scope(scope)
+    }
 
-    pub trait SecretKeyStore {
-        fn insert(&mut self, id: KeyId, key: CspSecretKey, scope: Option<Scope>) -> Result<(), SecretKeyStoreError>;
-        fn get(&self, id: &KeyId) -> Option<CspSecretKey>;
-        fn contains(&self, id: &KeyId) -> bool;
-        fn remove(&mut self, id: &KeyId) -> bool;
+    fn try_retain<F>(&mut self, _filter: F, _scope: Scope) -> Result<(), SecretKeyStoreError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        panic!("MockSecretKeyStore::try_retain: No matching expectation found")
+    }
+
+    fn retain_then_insert<F>(
+        &mut self,
+        _filter: F,
+        _scope: Scope,
+        _new: (KeyId, CspSecretKey, Option<Scope>),
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        panic!("MockSecretKeyStore::retain_then_insert: No matching expectation found")
     }
 }
 
@@ -56,10 +150,21 @@ impl SecretKeyStore for TempSecretKeyStore {
         self.store.insert(id, key, scope)
     }
 
+    fn insert_batch(
+        &mut self,
+        entries: Vec<(KeyId, CspSecretKey, Option<Scope>)>,
+    ) -> Result<(), SecretKeyStoreError> {
+        self.store.insert_batch(entries)
+    }
+
     fn get(&self, id: &KeyId) -> Option<CspSecretKey> {
         self.store.get(id)
     }
 
+    fn get_scope(&self, id: &KeyId) -> Option<Option<Scope>> {
+        self.store.get_scope(id)
+    }
+
     fn contains(&self, id: &KeyId) -> bool {
         self.store.contains(id)
     }
@@ -68,10 +173,45 @@ impl SecretKeyStore for TempSecretKeyStore {
         self.store.remove(id)
     }
 
+    fn keys_in_scope(&self, scope: Scope) -> Vec<KeyId> {
+        self.store.keys_in_scope(scope)
+    }
+
+    fn count_by_scope(&self) -> BTreeMap<Option<Scope>, usize> {
+        self.store.count_by_scope()
+    }
+
+    fn clear_scope(&mut self, scope: Scope) -> Result<usize, SecretKeyStorePersistenceError> {
+        self.store.clear_scope(scope)
+    }
+
+    fn drop_ephemeral(&mut self) {
+        self.store.drop_ephemeral()
+    }
+
     fn retain<F>(&mut self, _filter: F, _scope: Scope)
     where
         F: Fn(&KeyId, &CspSecretKey) -> bool,
     {
         unimplemented!()
     }
+
+    fn try_retain<F>(&mut self, filter: F, scope: Scope) -> Result<(), SecretKeyStoreError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.store.try_retain(filter, scope)
+    }
+
+    fn retain_then_insert<F>(
+        &mut self,
+        filter: F,
+        scope: Scope,
+        new: (KeyId, CspSecretKey, Option<Scope>),
+    ) -> Result<(), SecretKeyStorePersistenceError>
+    where
+        F: Fn(&KeyId, &CspSecretKey) -> bool,
+    {
+        self.store.retain_then_insert(filter, scope, new)
+    }
 }
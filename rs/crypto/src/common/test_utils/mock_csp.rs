@@ -6,7 +6,7 @@ use ic_crypto_internal_csp::api::tls_errors::{
 use ic_crypto_internal_csp::api::{
     CspKeyGenerator, CspSecretKeyInjector, CspSecretKeyStoreChecker, CspSigner,
     CspThresholdSignError, CspTlsClientHandshake, CspTlsServerHandshake,
-    DistributedKeyGenerationCspClient, NiDkgCspClient, NodePublicKeyData,
+    DistributedKeyGenerationCspClient, NiDkgCspClient, NodePublicKeyData, ThresholdRetentionReport,
     ThresholdSignatureCspClient,
 };
 use ic_crypto_internal_csp::tls_stub::cert_chain::CspCertificateChain;
@@ -29,7 +29,7 @@ use ic_crypto_internal_types::sign::threshold_sig::ni_dkg::{
     CspNiDkgDealing, CspNiDkgTranscript, Epoch,
 };
 use ic_crypto_internal_types::sign::threshold_sig::public_key::CspThresholdSigPublicKey;
-use ic_crypto_tls_interfaces::TlsStream;
+use ic_crypto_tls_interfaces::{TlsCipherSuite, TlsStream};
 use ic_protobuf::crypto::v1::NodePublicKeys;
 use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
 use ic_types::crypto::threshold_sig::ni_dkg::NiDkgId;
@@ -378,6 +378,13 @@ impl NiDkgCspClient for MockCryptoServiceProvider {
     fn retain_threshold_keys_if_present(&self, _active_keys: BTreeSet<CspPublicCoefficients>) {
         unimplemented!()
     }
+
+    fn retain_threshold_keys_if_present_with_report(
+        &self,
+        _active_keys: BTreeSet<CspPublicCoefficients>,
+    ) -> ThresholdRetentionReport {
+        unimplemented!()
+    }
 }
 
 impl DistributedKeyGenerationCspClient for MockCryptoServiceProvider {
@@ -511,9 +518,20 @@ impl CspTlsServerHandshake for MockCryptoServiceProvider {
         _tcp_stream: TcpStream,
         _self_cert: X509PublicKeyCert,
         _trusted_client_certs: Vec<X509PublicKeyCert>,
+        _allowed_cipher_suites: Vec<TlsCipherSuite>,
+        _alpn_protocol: Option<Vec<u8>>,
     ) -> Result<(TlsStream, Option<CspCertificateChain>), CspTlsServerHandshakeError> {
         unimplemented!()
     }
+
+    async fn perform_tls_server_handshake_without_client_auth(
+        &self,
+        _tcp_stream: TcpStream,
+        _self_cert: X509PublicKeyCert,
+        _allow_tls12: bool,
+    ) -> Result<TlsStream, CspTlsServerHandshakeError> {
+        unimplemented!()
+    }
 }
 
 #[async_trait]
@@ -11,7 +11,7 @@ use ic_crypto_internal_csp::api::tls_errors::{
 use ic_crypto_internal_csp::api::{
     CspKeyGenerator, CspSecretKeyInjector, CspSecretKeyStoreChecker, CspSigner,
     CspThresholdSignError, CspTlsClientHandshake, CspTlsServerHandshake,
-    DistributedKeyGenerationCspClient, NiDkgCspClient, NodePublicKeyData,
+    DistributedKeyGenerationCspClient, NiDkgCspClient, NodePublicKeyData, ThresholdRetentionReport,
     ThresholdSignatureCspClient,
 };
 use ic_crypto_internal_csp::tls_stub::cert_chain::CspCertificateChain;
@@ -31,7 +31,7 @@ use ic_crypto_internal_types::sign::threshold_sig::ni_dkg::{
     CspFsEncryptionPop, CspFsEncryptionPublicKey, CspNiDkgDealing, CspNiDkgTranscript, Epoch,
 };
 use ic_crypto_internal_types::sign::threshold_sig::public_key::CspThresholdSigPublicKey;
-use ic_crypto_tls_interfaces::TlsStream;
+use ic_crypto_tls_interfaces::{TlsCipherSuite, TlsStream};
 use ic_protobuf::crypto::v1::NodePublicKeys;
 use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
 use ic_types::crypto::threshold_sig::ni_dkg::NiDkgId;
@@ -241,6 +241,11 @@ mock! {
         ) -> Result<(), CspDkgLoadPrivateKeyError>;
 
         fn retain_threshold_keys_if_present(&self, active_keys: BTreeSet<CspPublicCoefficients>);
+
+        fn retain_threshold_keys_if_present_with_report(
+            &self,
+            active_keys: BTreeSet<CspPublicCoefficients>,
+        ) -> ThresholdRetentionReport;
     }
 
     pub trait DistributedKeyGenerationCspClient {
@@ -345,7 +350,16 @@ mock! {
             tcp_stream: TcpStream,
             self_cert: X509PublicKeyCert,
             trusted_client_certs: Vec<X509PublicKeyCert>,
+            allowed_cipher_suites: Vec<TlsCipherSuite>,
+            alpn_protocol: Option<Vec<u8>>,
         ) -> Result<(TlsStream, Option<CspCertificateChain>), CspTlsServerHandshakeError>;
+
+        async fn perform_tls_server_handshake_without_client_auth(
+            &self,
+            tcp_stream: TcpStream,
+            self_cert: X509PublicKeyCert,
+            allow_tls12: bool,
+        ) -> Result<TlsStream, CspTlsServerHandshakeError>;
     }
 
     #[async_trait]
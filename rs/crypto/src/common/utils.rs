@@ -28,9 +28,11 @@ use std::sync::Arc;
 pub mod dkg;
 pub mod ni_dkg;
 
+mod node_public_keys;
 mod temp_crypto;
 
 pub use crate::sign::utils::combined_threshold_signature_and_public_key;
+pub use node_public_keys::{KeyIdComputationError, NodeKeyIds, NodePublicKeysExt};
 pub use temp_crypto::{NodeKeysToGenerate, TempCryptoComponent};
 
 #[cfg(test)]
@@ -0,0 +1,187 @@
+//! Helpers for working with a node's public keys.
+use ic_crypto_internal_csp::keygen::{
+    forward_secure_key_id, public_key_hash_as_key_id, tls_registry_cert_hash_as_key_id,
+};
+use ic_crypto_internal_csp::types::CspPublicKey;
+use ic_crypto_internal_types::context::{Context, DomainSeparationContext};
+use ic_crypto_internal_types::encrypt::forward_secure::CspFsEncryptionPublicKey;
+use ic_crypto_sha256::Sha256;
+use ic_protobuf::crypto::v1::NodePublicKeys;
+use ic_protobuf::registry::crypto::v1::{PublicKey as PublicKeyProto, X509PublicKeyCert};
+use ic_types::crypto::{KeyId, KeyPurpose};
+use openssl::x509::X509;
+use std::convert::TryFrom;
+use std::fmt;
+
+#[cfg(test)]
+mod tests;
+
+const FINGERPRINT_DOMAIN: &str = "ic-node-public-keys-fingerprint";
+const FINGERPRINT_BYTES: usize = 8;
+
+/// Helper methods on `NodePublicKeys`.
+pub trait NodePublicKeysExt {
+    /// Returns a short fingerprint of the node's public keys, e.g. for logging
+    /// and for comparing keys.
+    ///
+    /// The fingerprint is the hex-encoded prefix of a SHA-256 hash over the
+    /// node signing key, the committee signing key, the DKG dealing
+    /// encryption key, and the TLS certificate, in this order. Only the
+    /// algorithm and the key bytes (resp. the DER-encoded certificate) are
+    /// hashed, so the fingerprint does not depend on how the keys are
+    /// serialized. An absent key is hashed as a marker that differs from any
+    /// present key.
+    fn fingerprint(&self) -> String;
+
+    /// Computes the key IDs of all keys that are present.
+    ///
+    /// The key IDs are the ones under which the corresponding secret keys are
+    /// stored in the secret key store. Absent keys have no key ID.
+    ///
+    /// # Errors
+    /// * `KeyIdComputationError::MalformedPublicKey` if one of the public keys
+    ///   is malformed.
+    /// * `KeyIdComputationError::MalformedTlsCertificate` if the TLS
+    ///   certificate is not a well-formed DER-encoded X.509 certificate.
+    fn key_ids(&self) -> Result<NodeKeyIds, KeyIdComputationError>;
+}
+
+/// The key IDs of a node's keys, see `NodePublicKeysExt::key_ids`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeKeyIds {
+    pub node_signing: Option<KeyId>,
+    pub committee_signing: Option<KeyId>,
+    pub dkg_dealing_encryption: Option<KeyId>,
+    pub tls_certificate: Option<KeyId>,
+}
+
+/// An error computing the key IDs of a node's keys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyIdComputationError {
+    MalformedPublicKey {
+        key_purpose: KeyPurpose,
+        internal_error: String,
+    },
+    MalformedTlsCertificate {
+        internal_error: String,
+    },
+}
+
+impl fmt::Display for KeyIdComputationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyIdComputationError::MalformedPublicKey {
+                key_purpose,
+                internal_error,
+            } => write!(
+                f,
+                "Cannot compute key ID of malformed {:?} public key: {}",
+                key_purpose, internal_error
+            ),
+            KeyIdComputationError::MalformedTlsCertificate { internal_error } => write!(
+                f,
+                "Cannot compute key ID of malformed TLS certificate: {}",
+                internal_error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KeyIdComputationError {}
+
+impl NodePublicKeysExt for NodePublicKeys {
+    fn fingerprint(&self) -> String {
+        let mut hash = Sha256::new();
+        hash.write(DomainSeparationContext::new(FINGERPRINT_DOMAIN).as_bytes());
+        for pk in &[
+            &self.node_signing_pk,
+            &self.committee_signing_pk,
+            &self.dkg_dealing_encryption_pk,
+        ] {
+            match pk {
+                Some(PublicKeyProto {
+                    algorithm,
+                    key_value,
+                    ..
+                }) => {
+                    hash.write(&[1]);
+                    hash.write(&algorithm.to_be_bytes());
+                    write_with_length(&mut hash, key_value);
+                }
+                None => hash.write(&[0]),
+            }
+        }
+        match &self.tls_certificate {
+            Some(tls_certificate) => {
+                hash.write(&[1]);
+                write_with_length(&mut hash, &tls_certificate.certificate_der);
+            }
+            None => hash.write(&[0]),
+        }
+        hex::encode(&hash.finish()[..FINGERPRINT_BYTES])
+    }
+
+    fn key_ids(&self) -> Result<NodeKeyIds, KeyIdComputationError> {
+        Ok(NodeKeyIds {
+            node_signing: self
+                .node_signing_pk
+                .clone()
+                .map(|pk| public_key_id(KeyPurpose::NodeSigning, pk))
+                .transpose()?,
+            committee_signing: self
+                .committee_signing_pk
+                .clone()
+                .map(|pk| public_key_id(KeyPurpose::CommitteeSigning, pk))
+                .transpose()?,
+            dkg_dealing_encryption: self
+                .dkg_dealing_encryption_pk
+                .clone()
+                .map(dkg_dealing_encryption_key_id)
+                .transpose()?,
+            tls_certificate: self
+                .tls_certificate
+                .clone()
+                .map(tls_certificate_key_id)
+                .transpose()?,
+        })
+    }
+}
+
+fn public_key_id(
+    key_purpose: KeyPurpose,
+    pk_proto: PublicKeyProto,
+) -> Result<KeyId, KeyIdComputationError> {
+    let csp_key = CspPublicKey::try_from(pk_proto).map_err(|e| {
+        KeyIdComputationError::MalformedPublicKey {
+            key_purpose,
+            internal_error: format!("{}", e),
+        }
+    })?;
+    Ok(public_key_hash_as_key_id(&csp_key))
+}
+
+fn dkg_dealing_encryption_key_id(pk_proto: PublicKeyProto) -> Result<KeyId, KeyIdComputationError> {
+    let csp_key = CspFsEncryptionPublicKey::try_from(pk_proto).map_err(|e| {
+        KeyIdComputationError::MalformedPublicKey {
+            key_purpose: KeyPurpose::DkgDealingEncryption,
+            internal_error: e.internal_error,
+        }
+    })?;
+    Ok(forward_secure_key_id(&csp_key))
+}
+
+fn tls_certificate_key_id(cert: X509PublicKeyCert) -> Result<KeyId, KeyIdComputationError> {
+    X509::from_der(&cert.certificate_der).map_err(|e| {
+        KeyIdComputationError::MalformedTlsCertificate {
+            internal_error: format!("{}", e),
+        }
+    })?;
+    Ok(tls_registry_cert_hash_as_key_id(cert))
+}
+
+/// Writes `bytes` preceded by their length, so that the boundaries between
+/// consecutive keys are unambiguous.
+fn write_with_length(hash: &mut Sha256, bytes: &[u8]) {
+    hash.write(&(bytes.len() as u64).to_be_bytes());
+    hash.write(bytes);
+}
@@ -0,0 +1,161 @@
+use super::*;
+use crate::utils::get_node_keys_or_generate_if_missing;
+use ic_config::crypto::CryptoConfig;
+use ic_types::crypto::AlgorithmId;
+
+#[test]
+fn should_compute_same_fingerprint_for_equal_keys() {
+    assert_eq!(
+        node_public_keys().fingerprint(),
+        node_public_keys().fingerprint()
+    );
+}
+
+#[test]
+fn should_compute_fingerprint_of_expected_length() {
+    assert_eq!(
+        node_public_keys().fingerprint().len(),
+        2 * FINGERPRINT_BYTES
+    );
+}
+
+#[test]
+fn should_ignore_key_versions_in_fingerprint() {
+    let mut node_pks = node_public_keys();
+    node_pks.version = 1;
+    if let Some(pk) = node_pks.node_signing_pk.as_mut() {
+        pk.version = 1;
+    }
+
+    assert_eq!(node_pks.fingerprint(), node_public_keys().fingerprint());
+}
+
+#[test]
+fn should_compute_different_fingerprint_for_different_keys() {
+    let mut node_pks = node_public_keys();
+    if let Some(pk) = node_pks.committee_signing_pk.as_mut() {
+        pk.key_value[0] ^= 1;
+    }
+
+    assert_ne!(node_pks.fingerprint(), node_public_keys().fingerprint());
+}
+
+#[test]
+fn should_compute_different_fingerprint_if_key_is_absent() {
+    let node_pks = NodePublicKeys {
+        tls_certificate: None,
+        ..node_public_keys()
+    };
+
+    assert_ne!(node_pks.fingerprint(), node_public_keys().fingerprint());
+}
+
+#[test]
+fn should_compute_different_fingerprint_if_key_is_moved_to_other_purpose() {
+    let node_pks = node_public_keys();
+    let moved = NodePublicKeys {
+        node_signing_pk: None,
+        committee_signing_pk: node_pks.node_signing_pk.clone(),
+        ..NodePublicKeys::default()
+    };
+    let original = NodePublicKeys {
+        node_signing_pk: node_pks.node_signing_pk,
+        ..NodePublicKeys::default()
+    };
+
+    assert_ne!(moved.fingerprint(), original.fingerprint());
+}
+
+#[test]
+fn should_compute_key_ids_of_all_keys() {
+    let node_pks = generated_node_public_keys();
+
+    let key_ids = node_pks.key_ids().expect("failed to compute key IDs");
+
+    let expected_key_ids = NodeKeyIds {
+        node_signing: Some(public_key_hash_as_key_id(
+            &CspPublicKey::try_from(node_pks.node_signing_pk.clone().unwrap()).unwrap(),
+        )),
+        committee_signing: Some(public_key_hash_as_key_id(
+            &CspPublicKey::try_from(node_pks.committee_signing_pk.clone().unwrap()).unwrap(),
+        )),
+        dkg_dealing_encryption: Some(forward_secure_key_id(
+            &CspFsEncryptionPublicKey::try_from(
+                node_pks.dkg_dealing_encryption_pk.clone().unwrap(),
+            )
+            .unwrap(),
+        )),
+        tls_certificate: Some(tls_registry_cert_hash_as_key_id(
+            node_pks.tls_certificate.clone().unwrap(),
+        )),
+    };
+    assert_eq!(key_ids, expected_key_ids);
+}
+
+#[test]
+fn should_compute_no_key_ids_if_keys_are_absent() {
+    let key_ids = NodePublicKeys::default()
+        .key_ids()
+        .expect("failed to compute key IDs");
+
+    assert_eq!(key_ids, NodeKeyIds::default());
+}
+
+#[test]
+fn should_fail_to_compute_key_ids_if_tls_certificate_is_malformed() {
+    let node_pks = NodePublicKeys {
+        tls_certificate: Some(X509PublicKeyCert {
+            certificate_der: b"not a certificate".to_vec(),
+        }),
+        ..generated_node_public_keys()
+    };
+
+    let result = node_pks.key_ids();
+
+    assert!(matches!(
+        result,
+        Err(KeyIdComputationError::MalformedTlsCertificate { .. })
+    ));
+}
+
+#[test]
+fn should_fail_to_compute_key_ids_if_public_key_is_malformed() {
+    let mut node_pks = generated_node_public_keys();
+    if let Some(pk) = node_pks.committee_signing_pk.as_mut() {
+        pk.key_value.truncate(1);
+    }
+
+    let result = node_pks.key_ids();
+
+    assert!(matches!(
+        result,
+        Err(KeyIdComputationError::MalformedPublicKey {
+            key_purpose: KeyPurpose::CommitteeSigning,
+            ..
+        })
+    ));
+}
+
+fn generated_node_public_keys() -> NodePublicKeys {
+    CryptoConfig::run_with_temp_config(|config| {
+        get_node_keys_or_generate_if_missing(&config.crypto_root).0
+    })
+}
+
+fn node_public_keys() -> NodePublicKeys {
+    let public_key = |algorithm: AlgorithmId, key_value: u8| PublicKeyProto {
+        version: 0,
+        algorithm: algorithm as i32,
+        key_value: vec![key_value; 32],
+        proof_data: None,
+    };
+    NodePublicKeys {
+        version: 0,
+        node_signing_pk: Some(public_key(AlgorithmId::Ed25519, 1)),
+        committee_signing_pk: Some(public_key(AlgorithmId::MultiBls12_381, 2)),
+        tls_certificate: Some(X509PublicKeyCert {
+            certificate_der: vec![3; 32],
+        }),
+        dkg_dealing_encryption_pk: Some(public_key(AlgorithmId::Groth20_Bls12_381, 4)),
+    }
+}
@@ -306,6 +306,21 @@ impl<C: CryptoServiceProvider + Send + Sync> TlsHandshake for TempCryptoComponen
             .await
     }
 
+    async fn perform_tls_server_handshake_without_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        registry_version: RegistryVersion,
+        allow_tls12: bool,
+    ) -> Result<TlsStream, TlsServerHandshakeError> {
+        self.crypto_component
+            .perform_tls_server_handshake_without_client_auth(
+                tcp_stream,
+                registry_version,
+                allow_tls12,
+            )
+            .await
+    }
+
     async fn perform_tls_server_handshake_temp_with_optional_client_auth(
         &self,
         tcp_stream: TcpStream,
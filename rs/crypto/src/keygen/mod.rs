@@ -1,21 +1,25 @@
 use crate::keygen::keygen_internal::KeyGenInternal;
-use crate::{key_from_registry, CryptoComponentFatClient};
+use crate::CryptoComponentFatClient;
 use ic_crypto_internal_csp::keygen::{forward_secure_key_id, public_key_hash_as_key_id};
 use ic_crypto_internal_csp::types::CspPublicKey;
 use ic_crypto_internal_csp::CryptoServiceProvider;
 use ic_crypto_internal_types::encrypt::forward_secure::{
     CspFsEncryptionPop, CspFsEncryptionPublicKey,
 };
-use ic_interfaces::crypto::{KeyManager, Keygen};
+use ic_interfaces::crypto::{KeyDiagnostic, KeyDiagnostics, KeyManager, Keygen};
 use ic_protobuf::crypto::v1::NodePublicKeys;
+use ic_protobuf::registry::crypto::v1::{PublicKey as PublicKeyProto, X509PublicKeyCert};
 use ic_registry_client::helper::crypto::CryptoRegistry;
+use ic_registry_keys::{make_crypto_node_key, make_crypto_tls_cert_key};
+use ic_registry_transport::insert;
+use ic_registry_transport::pb::v1::RegistryMutation;
 use ic_types::crypto::{
     AlgorithmId, CommitteeMemberPublicKey, CryptoError, CryptoResult, KeyId, KeyPurpose,
     UserPublicKey,
 };
-use ic_types::RegistryVersion;
+use ic_types::{NodeId, RegistryVersion};
+use prost::Message;
 use std::convert::TryFrom;
-use std::sync::Arc;
 
 mod keygen_internal;
 
@@ -38,26 +42,164 @@ impl<C: CryptoServiceProvider> KeyManager for CryptoComponentFatClient<C> {
     }
 
     fn check_keys_with_registry(&self, registry_version: RegistryVersion) -> CryptoResult<()> {
-        self.ensure_node_signing_key_material_is_set_up(registry_version)?;
-        self.ensure_committee_signing_key_material_is_set_up(registry_version)?;
-        self.ensure_dkg_dealing_encryption_key_material_is_set_up(registry_version)?;
-        self.ensure_tls_key_material_is_set_up(registry_version)?;
-        Ok(())
+        match self
+            .diagnose_keys_with_registry(registry_version)?
+            .first_error()
+        {
+            Some(error) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn diagnose_keys_with_registry(
+        &self,
+        registry_version: RegistryVersion,
+    ) -> CryptoResult<KeyDiagnostics> {
+        let node_pks = self.node_public_keys();
+        Ok(KeyDiagnostics {
+            node_signing: self.diagnose_node_key(
+                KeyPurpose::NodeSigning,
+                node_pks.node_signing_pk.as_ref(),
+                registry_version,
+                Self::check_node_signing_key_material,
+            )?,
+            committee_signing: self.diagnose_node_key(
+                KeyPurpose::CommitteeSigning,
+                node_pks.committee_signing_pk.as_ref(),
+                registry_version,
+                Self::check_committee_signing_key_material,
+            )?,
+            dkg_dealing_encryption: self.diagnose_node_key(
+                KeyPurpose::DkgDealingEncryption,
+                node_pks.dkg_dealing_encryption_pk.as_ref(),
+                registry_version,
+                Self::check_dkg_dealing_encryption_key_material,
+            )?,
+            tls_certificate: self
+                .diagnose_tls_certificate(node_pks.tls_certificate.as_ref(), registry_version)?,
+        })
+    }
+
+    fn keys_needing_registration(
+        &self,
+        registry_version: RegistryVersion,
+    ) -> CryptoResult<Vec<KeyPurpose>> {
+        Ok(self
+            .diagnose_keys_with_registry(registry_version)?
+            .key_purposes_needing_registration())
+    }
+
+    fn node_public_keys_as_registry_mutations(
+        &self,
+        node_id: NodeId,
+    ) -> CryptoResult<Vec<RegistryMutation>> {
+        let node_pks = self.node_public_keys();
+        let mut mutations = Vec::new();
+        for (key_purpose, pk) in &[
+            (KeyPurpose::NodeSigning, &node_pks.node_signing_pk),
+            (KeyPurpose::CommitteeSigning, &node_pks.committee_signing_pk),
+            (
+                KeyPurpose::DkgDealingEncryption,
+                &node_pks.dkg_dealing_encryption_pk,
+            ),
+        ] {
+            let pk = pk.as_ref().ok_or_else(|| CryptoError::InvalidArgument {
+                message: format!("Missing public key with purpose {:?}", key_purpose),
+            })?;
+            mutations.push(insert(
+                make_crypto_node_key(node_id, *key_purpose),
+                encode_proto(pk),
+            ));
+        }
+        let tls_certificate =
+            node_pks
+                .tls_certificate
+                .as_ref()
+                .ok_or_else(|| CryptoError::InvalidArgument {
+                    message: "Missing TLS certificate".to_string(),
+                })?;
+        mutations.push(insert(
+            make_crypto_tls_cert_key(node_id),
+            encode_proto(tls_certificate),
+        ));
+        Ok(mutations)
     }
 }
 
+/// Returns the diagnostic for a key held locally as `local_key`, where `error`
+/// is the result of checking the key material of `registry_key`.
+fn key_diagnostic<K: PartialEq>(
+    local_key: Option<&K>,
+    registry_key: Option<&K>,
+    error: Option<CryptoError>,
+) -> KeyDiagnostic {
+    let secret_key_present = registry_key.is_some() && error.is_none();
+    KeyDiagnostic {
+        present_locally: local_key.is_some(),
+        present_in_registry: registry_key.is_some(),
+        secret_key_present,
+        matching: secret_key_present && local_key.is_some() && local_key == registry_key,
+        error,
+    }
+}
+
+fn encode_proto<M: Message>(msg: &M) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(msg.encoded_len());
+    msg.encode(&mut buf)
+        .expect("encoding into a buffer with sufficient capacity cannot fail");
+    buf
+}
+
 // Helpers for implementing `KeyManager`-trait.
 impl<C: CryptoServiceProvider> CryptoComponentFatClient<C> {
-    fn ensure_node_signing_key_material_is_set_up(
+    /// Diagnoses the node key with the given `key_purpose`, using
+    /// `check_key_material` to check the key found in the registry.
+    fn diagnose_node_key(
         &self,
+        key_purpose: KeyPurpose,
+        local_pk: Option<&PublicKeyProto>,
         registry_version: RegistryVersion,
-    ) -> CryptoResult<()> {
-        let pk_proto = key_from_registry(
-            Arc::clone(&self.registry_client),
+        check_key_material: fn(&Self, PublicKeyProto, RegistryVersion) -> CryptoResult<()>,
+    ) -> CryptoResult<KeyDiagnostic> {
+        let registry_pk = self.registry_client.get_crypto_key_for_node(
             self.node_id,
-            KeyPurpose::NodeSigning,
+            key_purpose,
             registry_version,
         )?;
+        let error = match &registry_pk {
+            Some(pk_proto) => check_key_material(self, pk_proto.clone(), registry_version).err(),
+            None => Some(CryptoError::PublicKeyNotFound {
+                node_id: self.node_id,
+                key_purpose,
+                registry_version,
+            }),
+        };
+        Ok(key_diagnostic(local_pk, registry_pk.as_ref(), error))
+    }
+
+    fn diagnose_tls_certificate(
+        &self,
+        local_cert: Option<&X509PublicKeyCert>,
+        registry_version: RegistryVersion,
+    ) -> CryptoResult<KeyDiagnostic> {
+        let registry_cert = self
+            .registry_client
+            .get_tls_certificate(self.node_id, registry_version)?;
+        let error = match &registry_cert {
+            Some(cert) => self.check_tls_key_material(cert).err(),
+            None => Some(CryptoError::TlsCertNotFound {
+                node_id: self.node_id,
+                registry_version,
+            }),
+        };
+        Ok(key_diagnostic(local_cert, registry_cert.as_ref(), error))
+    }
+
+    fn check_node_signing_key_material(
+        &self,
+        pk_proto: PublicKeyProto,
+        registry_version: RegistryVersion,
+    ) -> CryptoResult<()> {
         if AlgorithmId::from(pk_proto.algorithm) != AlgorithmId::Ed25519 {
             return Err(CryptoError::PublicKeyNotFound {
                 node_id: self.node_id,
@@ -76,16 +218,11 @@ impl<C: CryptoServiceProvider> CryptoComponentFatClient<C> {
         Ok(())
     }
 
-    fn ensure_committee_signing_key_material_is_set_up(
+    fn check_committee_signing_key_material(
         &self,
+        pk_proto: PublicKeyProto,
         registry_version: RegistryVersion,
     ) -> CryptoResult<()> {
-        let pk_proto = key_from_registry(
-            Arc::clone(&self.registry_client),
-            self.node_id,
-            KeyPurpose::CommitteeSigning,
-            registry_version,
-        )?;
         if AlgorithmId::from(pk_proto.algorithm) != AlgorithmId::MultiBls12_381 {
             return Err(CryptoError::PublicKeyNotFound {
                 node_id: self.node_id,
@@ -104,16 +241,11 @@ impl<C: CryptoServiceProvider> CryptoComponentFatClient<C> {
         Ok(())
     }
 
-    fn ensure_dkg_dealing_encryption_key_material_is_set_up(
+    fn check_dkg_dealing_encryption_key_material(
         &self,
+        pk_proto: PublicKeyProto,
         registry_version: RegistryVersion,
     ) -> CryptoResult<()> {
-        let pk_proto = key_from_registry(
-            Arc::clone(&self.registry_client),
-            self.node_id,
-            KeyPurpose::DkgDealingEncryption,
-            registry_version,
-        )?;
         if AlgorithmId::from(pk_proto.algorithm) != AlgorithmId::Groth20_Bls12_381 {
             return Err(CryptoError::PublicKeyNotFound {
                 node_id: self.node_id,
@@ -145,20 +277,10 @@ impl<C: CryptoServiceProvider> CryptoComponentFatClient<C> {
         Ok(())
     }
 
-    fn ensure_tls_key_material_is_set_up(
-        &self,
-        registry_version: RegistryVersion,
-    ) -> CryptoResult<()> {
-        let x509_public_key_cert = self
-            .registry_client
-            .get_tls_certificate(self.node_id, registry_version)?
-            .ok_or(CryptoError::TlsCertNotFound {
-                node_id: self.node_id,
-                registry_version,
-            })?;
-        if !self.csp.sks_contains_tls_key(&x509_public_key_cert) {
+    fn check_tls_key_material(&self, x509_public_key_cert: &X509PublicKeyCert) -> CryptoResult<()> {
+        if !self.csp.sks_contains_tls_key(x509_public_key_cert) {
             return Err(CryptoError::TlsSecretKeyNotFound {
-                certificate_der: x509_public_key_cert.certificate_der,
+                certificate_der: x509_public_key_cert.certificate_der.clone(),
             });
         }
         Ok(())
@@ -3,9 +3,20 @@
 use super::*;
 use crate::common::test_utils::crypto_component::crypto_component_with;
 use crate::common::test_utils::hex_to_32_bytes;
+use crate::common::test_utils::mockall_csp::MockAllCryptoServiceProvider;
+use crate::utils::get_node_keys_or_generate_if_missing_for_node_id;
+use ic_config::crypto::CryptoConfig;
 use ic_crypto_internal_csp::secret_key_store::volatile_store::VolatileSecretKeyStore;
 use ic_crypto_internal_csp::secret_key_store::SecretKeyStore;
+use ic_interfaces::registry::RegistryClient;
+use ic_logger::replica_logger::no_op_logger;
+use ic_protobuf::registry::crypto::v1::{PublicKey, X509PublicKeyCert};
+use ic_registry_client::fake::FakeRegistryClient;
+use ic_registry_common::proto_registry_data_provider::ProtoRegistryDataProvider;
+use ic_registry_transport::pb::v1::registry_mutation::Type;
 use ic_test_utilities::crypto::empty_fake_registry;
+use ic_test_utilities::types::ids::node_test_id;
+use std::sync::Arc;
 
 #[test]
 fn should_correctly_generate_ed25519_user_keys() {
@@ -53,6 +64,314 @@ fn should_correctly_generate_committee_member_keys() {
     );
 }
 
+#[test]
+fn should_return_node_public_keys_as_registry_mutations() {
+    let node_id = node_test_id(42);
+    let node_pks = node_public_keys();
+    let crypto = crypto_component_with_node_public_keys(node_pks.clone());
+
+    let mutations = crypto
+        .node_public_keys_as_registry_mutations(node_id)
+        .unwrap();
+
+    assert_eq!(mutations.len(), 4);
+    assert!(mutations
+        .iter()
+        .all(|mutation| mutation.mutation_type == Type::Insert as i32));
+    let value_for_key = |key: String| {
+        mutations
+            .iter()
+            .find(|mutation| mutation.key == key.as_bytes())
+            .map(|mutation| mutation.value.as_slice())
+            .expect("missing mutation for key")
+    };
+    for (key_purpose, expected_pk) in &[
+        (KeyPurpose::NodeSigning, &node_pks.node_signing_pk),
+        (KeyPurpose::CommitteeSigning, &node_pks.committee_signing_pk),
+        (
+            KeyPurpose::DkgDealingEncryption,
+            &node_pks.dkg_dealing_encryption_pk,
+        ),
+    ] {
+        let value = value_for_key(make_crypto_node_key(node_id, *key_purpose));
+        assert_eq!(Some(PublicKey::decode(value).unwrap()), **expected_pk);
+    }
+    let value = value_for_key(make_crypto_tls_cert_key(node_id));
+    assert_eq!(
+        Some(X509PublicKeyCert::decode(value).unwrap()),
+        node_pks.tls_certificate
+    );
+}
+
+#[test]
+fn should_fail_to_return_registry_mutations_if_tls_certificate_is_missing() {
+    let node_pks = NodePublicKeys {
+        tls_certificate: None,
+        ..node_public_keys()
+    };
+    let crypto = crypto_component_with_node_public_keys(node_pks);
+
+    let result = crypto.node_public_keys_as_registry_mutations(node_test_id(42));
+
+    assert!(matches!(result, Err(CryptoError::InvalidArgument { .. })));
+}
+
+#[test]
+fn should_diagnose_all_keys_as_matching_if_registry_contains_them() {
+    let node_pks = well_formed_node_public_keys();
+    let registry = registry_with_node_public_keys(&node_pks);
+    let crypto = crypto_component_with_node_public_keys_and_registry(node_pks, registry);
+
+    let diagnostics = crypto
+        .diagnose_keys_with_registry(REGISTRY_VERSION_1)
+        .unwrap();
+
+    assert!(diagnostics.all_matching());
+}
+
+#[test]
+fn should_diagnose_missing_committee_signing_key_in_registry() {
+    let node_pks = well_formed_node_public_keys();
+    let registry = registry_with_node_public_keys(&NodePublicKeys {
+        committee_signing_pk: None,
+        ..node_pks.clone()
+    });
+    let crypto = crypto_component_with_node_public_keys_and_registry(node_pks, registry);
+
+    let diagnostics = crypto
+        .diagnose_keys_with_registry(REGISTRY_VERSION_1)
+        .unwrap();
+
+    assert_eq!(
+        diagnostics.committee_signing,
+        KeyDiagnostic {
+            present_locally: true,
+            present_in_registry: false,
+            secret_key_present: false,
+            matching: false,
+            error: Some(CryptoError::PublicKeyNotFound {
+                node_id: node_test_id(1),
+                key_purpose: KeyPurpose::CommitteeSigning,
+                registry_version: REGISTRY_VERSION_1,
+            }),
+        }
+    );
+    assert!(diagnostics.node_signing.matching);
+    assert!(diagnostics.dkg_dealing_encryption.matching);
+    assert!(diagnostics.tls_certificate.matching);
+    assert!(matches!(
+        crypto.check_keys_with_registry(REGISTRY_VERSION_1),
+        Err(CryptoError::PublicKeyNotFound {
+            key_purpose: KeyPurpose::CommitteeSigning,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn should_list_unregistered_dkg_dealing_encryption_key_as_needing_registration() {
+    let node_pks = well_formed_node_public_keys();
+    let registry = registry_with_node_public_keys(&NodePublicKeys {
+        dkg_dealing_encryption_pk: None,
+        ..node_pks.clone()
+    });
+    let crypto = crypto_component_with_node_public_keys_and_registry(node_pks, registry);
+
+    let keys = crypto
+        .keys_needing_registration(REGISTRY_VERSION_1)
+        .unwrap();
+
+    assert_eq!(keys, vec![KeyPurpose::DkgDealingEncryption]);
+}
+
+#[test]
+fn should_list_no_keys_as_needing_registration_if_registry_contains_them() {
+    let node_pks = well_formed_node_public_keys();
+    let registry = registry_with_node_public_keys(&node_pks);
+    let crypto = crypto_component_with_node_public_keys_and_registry(node_pks, registry);
+
+    let keys = crypto
+        .keys_needing_registration(REGISTRY_VERSION_1)
+        .unwrap();
+
+    assert!(keys.is_empty());
+}
+
+#[test]
+fn should_diagnose_mismatching_tls_certificate() {
+    let node_pks = well_formed_node_public_keys();
+    let registry = registry_with_node_public_keys(&NodePublicKeys {
+        tls_certificate: Some(X509PublicKeyCert {
+            certificate_der: vec![42; 32],
+        }),
+        ..node_pks.clone()
+    });
+    let crypto = crypto_component_with_node_public_keys_and_registry(node_pks, registry);
+
+    let diagnostics = crypto
+        .diagnose_keys_with_registry(REGISTRY_VERSION_1)
+        .unwrap();
+
+    assert_eq!(
+        diagnostics.tls_certificate,
+        KeyDiagnostic {
+            present_locally: true,
+            present_in_registry: true,
+            secret_key_present: true,
+            matching: false,
+            error: None,
+        }
+    );
+    assert!(!diagnostics.all_matching());
+}
+
+#[test]
+fn should_diagnose_registered_key_whose_secret_key_is_missing() {
+    let node_pks = well_formed_node_public_keys();
+    let node_signing_key_id = public_key_hash_as_key_id(
+        &CspPublicKey::try_from(node_pks.node_signing_pk.clone().unwrap()).unwrap(),
+    );
+    let registry = registry_with_node_public_keys(&node_pks);
+    let crypto = crypto_component_with_node_public_keys_registry_and_secret_keys(
+        node_pks,
+        registry,
+        move |key_id| *key_id != node_signing_key_id,
+    );
+
+    let diagnostics = crypto
+        .diagnose_keys_with_registry(REGISTRY_VERSION_1)
+        .unwrap();
+
+    let secret_key_not_found = CryptoError::SecretKeyNotFound {
+        algorithm: AlgorithmId::Ed25519,
+        key_id: node_signing_key_id,
+    };
+    assert_eq!(
+        diagnostics.node_signing,
+        KeyDiagnostic {
+            present_locally: true,
+            present_in_registry: true,
+            secret_key_present: false,
+            matching: false,
+            error: Some(secret_key_not_found.clone()),
+        }
+    );
+    assert!(diagnostics.committee_signing.matching);
+    assert!(diagnostics.dkg_dealing_encryption.matching);
+    assert!(diagnostics.tls_certificate.matching);
+    assert_eq!(
+        crypto.check_keys_with_registry(REGISTRY_VERSION_1),
+        Err(secret_key_not_found)
+    );
+    assert_eq!(
+        crypto.keys_needing_registration(REGISTRY_VERSION_1),
+        Ok(vec![KeyPurpose::NodeSigning])
+    );
+}
+
+const REGISTRY_VERSION_1: RegistryVersion = RegistryVersion::new(1);
+
+fn crypto_component_with_node_public_keys(
+    node_pks: NodePublicKeys,
+) -> CryptoComponentFatClient<MockAllCryptoServiceProvider> {
+    crypto_component_with_node_public_keys_and_registry(node_pks, empty_fake_registry())
+}
+
+fn crypto_component_with_node_public_keys_and_registry(
+    node_pks: NodePublicKeys,
+    registry_client: Arc<dyn RegistryClient>,
+) -> CryptoComponentFatClient<MockAllCryptoServiceProvider> {
+    crypto_component_with_node_public_keys_registry_and_secret_keys(
+        node_pks,
+        registry_client,
+        |_key_id| true,
+    )
+}
+
+/// Returns a crypto component whose secret key store contains the key with ID
+/// `key_id` iff `sks_contains(key_id)` is true, and that contains all TLS keys.
+fn crypto_component_with_node_public_keys_registry_and_secret_keys(
+    node_pks: NodePublicKeys,
+    registry_client: Arc<dyn RegistryClient>,
+    sks_contains: impl Fn(&KeyId) -> bool + Send + 'static,
+) -> CryptoComponentFatClient<MockAllCryptoServiceProvider> {
+    let mut csp = MockAllCryptoServiceProvider::new();
+    csp.expect_node_public_keys().return_const(node_pks);
+    csp.expect_sks_contains()
+        .returning(move |key_id| sks_contains(key_id));
+    csp.expect_sks_contains_tls_key().return_const(true);
+    CryptoComponentFatClient::new_with_csp_and_fake_node_id(
+        csp,
+        no_op_logger(),
+        registry_client,
+        node_test_id(1),
+    )
+}
+
+/// Returns a registry that contains the given keys for node 1 at version 1.
+fn registry_with_node_public_keys(node_pks: &NodePublicKeys) -> Arc<dyn RegistryClient> {
+    let node_id = node_test_id(1);
+    let data_provider = Arc::new(ProtoRegistryDataProvider::new());
+    for (key_purpose, pk) in &[
+        (KeyPurpose::NodeSigning, &node_pks.node_signing_pk),
+        (KeyPurpose::CommitteeSigning, &node_pks.committee_signing_pk),
+        (
+            KeyPurpose::DkgDealingEncryption,
+            &node_pks.dkg_dealing_encryption_pk,
+        ),
+    ] {
+        if let Some(pk) = pk {
+            data_provider
+                .add(
+                    &make_crypto_node_key(node_id, *key_purpose),
+                    REGISTRY_VERSION_1,
+                    Some(pk.clone()),
+                )
+                .expect("Could not extend registry");
+        }
+    }
+    if let Some(tls_certificate) = &node_pks.tls_certificate {
+        data_provider
+            .add(
+                &make_crypto_tls_cert_key(node_id),
+                REGISTRY_VERSION_1,
+                Some(tls_certificate.clone()),
+            )
+            .expect("Could not extend registry");
+    }
+    let registry_client = Arc::new(FakeRegistryClient::new(data_provider));
+    // Need to poll the data provider at least once to update the cache.
+    registry_client.update_to_latest_version();
+    registry_client
+}
+
+fn node_public_keys() -> NodePublicKeys {
+    let public_key = |algorithm: AlgorithmId, key_value: u8| PublicKey {
+        version: 0,
+        algorithm: algorithm as i32,
+        key_value: vec![key_value; 32],
+        proof_data: None,
+    };
+    NodePublicKeys {
+        version: 0,
+        node_signing_pk: Some(public_key(AlgorithmId::Ed25519, 1)),
+        committee_signing_pk: Some(public_key(AlgorithmId::MultiBls12_381, 2)),
+        tls_certificate: Some(X509PublicKeyCert {
+            certificate_der: vec![3; 32],
+        }),
+        dkg_dealing_encryption_pk: Some(public_key(AlgorithmId::Groth20_Bls12_381, 4)),
+    }
+}
+
+/// Returns well-formed node public keys, i.e. keys that pass the checks of
+/// `check_keys_with_registry` if the secret key store contains their secret
+/// keys.
+fn well_formed_node_public_keys() -> NodePublicKeys {
+    CryptoConfig::run_with_temp_config(|config| {
+        get_node_keys_or_generate_if_missing_for_node_id(&config.crypto_root, node_test_id(1))
+    })
+}
+
 fn empty_secret_key_store() -> impl SecretKeyStore {
     VolatileSecretKeyStore::new()
 }
@@ -27,6 +27,7 @@ pub use sign::utils::{
 
 use crate::common::utils::derive_node_id;
 use crate::sign::ThresholdSigDataStoreImpl;
+use crate::tls_stub::TlsServerHandshakeCounters;
 use ic_config::crypto::CryptoConfig;
 use ic_crypto_internal_csp::api::NodePublicKeyData;
 use ic_crypto_internal_csp::keygen::public_key_hash_as_key_id;
@@ -101,6 +102,8 @@ pub struct CryptoComponentFatClient<C: CryptoServiceProvider> {
     // The node id of the node that instantiated this crypto component.
     node_id: NodeId,
     logger: ReplicaLogger,
+    // Only set if the component was created with a metrics registry.
+    tls_server_handshake_metrics: Option<TlsServerHandshakeCounters>,
 }
 
 /// A `ThresholdSigDataStore` that is wrapped by a `RwLock`.
@@ -163,6 +166,7 @@ impl<C: CryptoServiceProvider> CryptoComponentFatClient<C> {
             registry_client,
             node_id,
             logger,
+            tls_server_handshake_metrics: None,
         }
     }
 }
@@ -236,6 +240,7 @@ impl CryptoComponentFatClient<Csp<OsRng, ProtoSecretKeyStore>> {
             registry_client,
             node_id,
             logger,
+            tls_server_handshake_metrics: metrics_registry.map(TlsServerHandshakeCounters::new),
         }
     }
 
@@ -252,6 +257,7 @@ impl CryptoComponentFatClient<Csp<OsRng, ProtoSecretKeyStore>> {
             registry_client,
             node_id,
             logger,
+            tls_server_handshake_metrics: None,
         }
     }
 
@@ -18,6 +18,8 @@ use tokio::net::TcpStream;
 mod client_handshake;
 mod server_handshake;
 
+pub(crate) use server_handshake::TlsServerHandshakeCounters;
+
 #[async_trait]
 impl<CSP> TlsHandshake for CryptoComponentFatClient<CSP>
 where
@@ -43,6 +45,46 @@ where
             tcp_stream,
             allowed_clients,
             registry_version,
+            None,
+            None,
+            server_handshake::RegistryRetryConfig::default(),
+            self.tls_server_handshake_metrics
+                .as_ref()
+                .map(|metrics| metrics as &dyn server_handshake::TlsServerHandshakeMetrics),
+        )
+        .await
+        .map(|(tls_stream, peer, _alpn_protocol)| (tls_stream, peer));
+        debug!(logger;
+            crypto.description => "end",
+            crypto.is_ok => result.is_ok(),
+            crypto.error => log_err(result.as_ref().err()),
+        );
+        result
+    }
+
+    async fn perform_tls_server_handshake_without_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        registry_version: RegistryVersion,
+        allow_tls12: bool,
+    ) -> Result<TlsStream, TlsServerHandshakeError> {
+        let logger = new_logger!(&self.logger;
+            crypto.trait_name => "TlsHandshake",
+            crypto.method_name => "perform_tls_server_handshake_without_client_auth",
+            crypto.registry_version => registry_version.get(),
+        );
+        debug!(logger; crypto.description => "start",);
+        let result = server_handshake::perform_tls_server_handshake_without_client_auth(
+            &self.csp,
+            self.node_id,
+            &self.registry_client,
+            tcp_stream,
+            registry_version,
+            allow_tls12,
+            server_handshake::RegistryRetryConfig::default(),
+            self.tls_server_handshake_metrics
+                .as_ref()
+                .map(|metrics| metrics as &dyn server_handshake::TlsServerHandshakeMetrics),
         )
         .await;
         debug!(logger;
@@ -73,6 +115,12 @@ where
             tcp_stream,
             allowed_authenticating_clients,
             registry_version,
+            None,
+            None,
+            server_handshake::RegistryRetryConfig::default(),
+            self.tls_server_handshake_metrics
+                .as_ref()
+                .map(|metrics| metrics as &dyn server_handshake::TlsServerHandshakeMetrics),
         )
         .await;
         debug!(logger;
@@ -5,20 +5,121 @@ use crate::tls_stub::{
 use ic_crypto_internal_csp::api::CspTlsServerHandshake;
 use ic_crypto_internal_csp::tls_stub::cert_chain::CspCertificateChain;
 use ic_crypto_tls_interfaces::{
-    AllowedClients, AuthenticatedPeer, Peer, PeerNotAllowedError, SomeOrAllNodes,
-    TlsServerHandshakeError, TlsStream,
+    AllowedClients, AuthenticatedPeer, Peer, PeerNotAllowedError, SomeOrAllNodes, TlsCipherSuite,
+    TlsServerHandshakeError, TlsStream, DEFAULT_TLS_CIPHER_SUITES,
 };
 use ic_interfaces::registry::RegistryClient;
+use ic_metrics::MetricsRegistry;
 use ic_protobuf::registry::crypto::v1::X509PublicKeyCert;
 use ic_registry_client::helper::node::NodeRegistry;
 use ic_types::{NodeId, RegistryVersion};
 use openssl::x509::X509;
+use prometheus::IntCounterVec;
 use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpStream;
 
+#[cfg(test)]
+mod tests;
+
+/// Determines how registry lookups are retried during a handshake.
+///
+/// Only transient registry errors are retried. A certificate that is missing
+/// from the registry is reported right away.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RegistryRetryConfig {
+    /// The total number of lookup attempts, including the first one.
+    pub attempts: usize,
+    /// The time to wait between two attempts.
+    pub delay: Duration,
+}
+
+impl Default for RegistryRetryConfig {
+    fn default() -> Self {
+        RegistryRetryConfig {
+            attempts: 3,
+            delay: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Receives the outcome of every TLS server handshake, e.g. to export it as
+/// a metric. The outcome is one of the `OUTCOME_*` labels.
+pub trait TlsServerHandshakeMetrics: Send + Sync {
+    fn observe_outcome(&self, outcome: &'static str);
+}
+
+pub const OUTCOME_SUCCESS: &str = "success";
+pub const OUTCOME_REGISTRY_ERROR: &str = "registry_error";
+pub const OUTCOME_MALFORMED_SELF_CERT: &str = "malformed_self_cert";
+pub const OUTCOME_PEER_AUTH_FAILED: &str = "peer_auth_failed";
+pub const OUTCOME_HANDSHAKE_ERROR: &str = "handshake_error";
+
+/// Counts TLS server handshakes in the metrics registry, labeled by outcome.
+pub struct TlsServerHandshakeCounters {
+    handshakes: IntCounterVec,
+}
+
+impl TlsServerHandshakeCounters {
+    pub fn new(metrics_registry: &MetricsRegistry) -> Self {
+        Self {
+            handshakes: metrics_registry.int_counter_vec(
+                "ic_crypto_tls_server_handshakes_total",
+                "Number of TLS server handshakes, by outcome",
+                &["outcome"],
+            ),
+        }
+    }
+}
+
+impl TlsServerHandshakeMetrics for TlsServerHandshakeCounters {
+    fn observe_outcome(&self, outcome: &'static str) {
+        self.handshakes.with_label_values(&[outcome]).inc();
+    }
+}
+
+/// Returns the metrics label for the outcome of a handshake.
+fn outcome_label<T>(result: &Result<T, TlsServerHandshakeError>) -> &'static str {
+    match result {
+        Ok(_) => OUTCOME_SUCCESS,
+        Err(TlsServerHandshakeError::RegistryError(_))
+        | Err(TlsServerHandshakeError::CertificateNotInRegistry { .. }) => OUTCOME_REGISTRY_ERROR,
+        Err(TlsServerHandshakeError::MalformedSelfCertificate { .. }) => {
+            OUTCOME_MALFORMED_SELF_CERT
+        }
+        Err(TlsServerHandshakeError::MalformedClientCertificate(_))
+        | Err(TlsServerHandshakeError::ClientNotAllowed(_))
+        | Err(TlsServerHandshakeError::UnauthenticatedClient)
+        | Err(TlsServerHandshakeError::PeerCertificateExpired { .. }) => OUTCOME_PEER_AUTH_FAILED,
+        Err(TlsServerHandshakeError::CreateAcceptorError { .. })
+        | Err(TlsServerHandshakeError::HandshakeError { .. })
+        | Err(TlsServerHandshakeError::NoMatchingAlpnProtocol) => OUTCOME_HANDSHAKE_ERROR,
+    }
+}
+
+fn observe_outcome<T>(
+    metrics: Option<&dyn TlsServerHandshakeMetrics>,
+    result: &Result<T, TlsServerHandshakeError>,
+) {
+    if let Some(metrics) = metrics {
+        metrics.observe_outcome(outcome_label(result));
+    }
+}
+
 // TODO (CRP-772): Simplify handshake code by moving cert equality check to CSP
 // TODO (CRP-773): Use X509 domain object instead of protobuf in API
+//
+// If `allowed_cipher_suites` is `None`, the `DEFAULT_TLS_CIPHER_SUITES` are
+// allowed. An empty list of cipher suites results in a
+// `TlsServerHandshakeError::CreateAcceptorError`.
+//
+// If an `alpn_protocol` is given, the client must offer it via ALPN, otherwise
+// the handshake fails. The negotiated ALPN protocol is returned together with
+// the authenticated peer.
+//
+// If `metrics` are given, the outcome of the handshake is reported to them.
+#[allow(clippy::too_many_arguments)]
 pub async fn perform_tls_server_handshake<C: CspTlsServerHandshake>(
     csp: &C,
     self_node_id: NodeId,
@@ -26,22 +127,62 @@ pub async fn perform_tls_server_handshake<C: CspTlsServerHandshake>(
     tcp_stream: TcpStream,
     allowed_clients: AllowedClients,
     registry_version: RegistryVersion,
-) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError> {
-    let (tls_stream, peer) = perform_tls_server_handshake_temp_with_optional_client_auth(
+    allowed_cipher_suites: Option<Vec<TlsCipherSuite>>,
+    alpn_protocol: Option<Vec<u8>>,
+    registry_retry: RegistryRetryConfig,
+    metrics: Option<&dyn TlsServerHandshakeMetrics>,
+) -> Result<(TlsStream, AuthenticatedPeer, Option<Vec<u8>>), TlsServerHandshakeError> {
+    let result = perform_tls_server_handshake_with_authenticated_client(
         csp,
         self_node_id,
         registry_client,
         tcp_stream,
         allowed_clients,
         registry_version,
+        allowed_cipher_suites,
+        alpn_protocol,
+        registry_retry,
+    )
+    .await;
+    observe_outcome(metrics, &result);
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn perform_tls_server_handshake_with_authenticated_client<C: CspTlsServerHandshake>(
+    csp: &C,
+    self_node_id: NodeId,
+    registry_client: &Arc<dyn RegistryClient>,
+    tcp_stream: TcpStream,
+    allowed_clients: AllowedClients,
+    registry_version: RegistryVersion,
+    allowed_cipher_suites: Option<Vec<TlsCipherSuite>>,
+    alpn_protocol: Option<Vec<u8>>,
+    registry_retry: RegistryRetryConfig,
+) -> Result<(TlsStream, AuthenticatedPeer, Option<Vec<u8>>), TlsServerHandshakeError> {
+    let (tls_stream, peer) = perform_tls_server_handshake_with_optional_client_auth(
+        csp,
+        self_node_id,
+        registry_client,
+        tcp_stream,
+        allowed_clients,
+        registry_version,
+        allowed_cipher_suites,
+        alpn_protocol,
+        registry_retry,
     )
     .await?;
     match peer {
-        Peer::Authenticated(peer) => Ok((tls_stream, peer)),
+        Peer::Authenticated(peer) => {
+            let negotiated_alpn_protocol = tls_stream.alpn_protocol();
+            Ok((tls_stream, peer, negotiated_alpn_protocol))
+        }
         Peer::Unauthenticated => Err(TlsServerHandshakeError::UnauthenticatedClient),
     }
 }
 
+// If `metrics` are given, the outcome of the handshake is reported to them.
+#[allow(clippy::too_many_arguments)]
 pub async fn perform_tls_server_handshake_temp_with_optional_client_auth<
     C: CspTlsServerHandshake,
 >(
@@ -51,20 +192,76 @@ pub async fn perform_tls_server_handshake_temp_with_optional_client_auth<
     tcp_stream: TcpStream,
     allowed_authenticating_clients: AllowedClients,
     registry_version: RegistryVersion,
+    allowed_cipher_suites: Option<Vec<TlsCipherSuite>>,
+    alpn_protocol: Option<Vec<u8>>,
+    registry_retry: RegistryRetryConfig,
+    metrics: Option<&dyn TlsServerHandshakeMetrics>,
 ) -> Result<(TlsStream, Peer), TlsServerHandshakeError> {
-    let self_tls_cert = tls_cert_from_registry(registry_client, self_node_id, registry_version)?;
-    let trusted_node_certs = tls_certs_from_registry(
+    let result = perform_tls_server_handshake_with_optional_client_auth(
+        csp,
+        self_node_id,
         registry_client,
-        &allowed_authenticating_clients.nodes(),
+        tcp_stream,
+        allowed_authenticating_clients,
         registry_version,
-    )?;
+        allowed_cipher_suites,
+        alpn_protocol,
+        registry_retry,
+    )
+    .await;
+    observe_outcome(metrics, &result);
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn perform_tls_server_handshake_with_optional_client_auth<C: CspTlsServerHandshake>(
+    csp: &C,
+    self_node_id: NodeId,
+    registry_client: &Arc<dyn RegistryClient>,
+    tcp_stream: TcpStream,
+    allowed_authenticating_clients: AllowedClients,
+    registry_version: RegistryVersion,
+    allowed_cipher_suites: Option<Vec<TlsCipherSuite>>,
+    alpn_protocol: Option<Vec<u8>>,
+    registry_retry: RegistryRetryConfig,
+) -> Result<(TlsStream, Peer), TlsServerHandshakeError> {
+    let self_tls_cert = retry_on_registry_error(registry_retry, || {
+        tls_cert_from_registry(registry_client, self_node_id, registry_version)
+    })
+    .await?;
+    let trusted_node_certs = retry_on_registry_error(registry_retry, || {
+        tls_certs_from_registry(
+            registry_client,
+            &allowed_authenticating_clients.nodes(),
+            registry_version,
+        )
+    })
+    .await?;
     let trusted_client_certs =
         combine_certs(&trusted_node_certs, allowed_authenticating_clients.certs());
 
+    let allowed_cipher_suites =
+        allowed_cipher_suites.unwrap_or_else(|| DEFAULT_TLS_CIPHER_SUITES.to_vec());
+
+    let alpn_required = alpn_protocol.is_some();
+
     let (tls_stream, peer_cert_chain) = csp
-        .perform_tls_server_handshake(tcp_stream, self_tls_cert, trusted_client_certs)
+        .perform_tls_server_handshake(
+            tcp_stream,
+            self_tls_cert,
+            trusted_client_certs,
+            allowed_cipher_suites,
+            alpn_protocol,
+        )
         .await?;
 
+    // The CSP aborts the handshake with `NoMatchingAlpnProtocol` if the client
+    // offers only other protocols, but a client that offers no protocol at all
+    // is accepted.
+    if alpn_required && tls_stream.alpn_protocol().is_none() {
+        return Err(TlsServerHandshakeError::NoMatchingAlpnProtocol);
+    }
+
     match peer_cert_chain {
         Some(peer_cert_chain) => {
             let peer = authenticated_peer(
@@ -78,6 +275,72 @@ pub async fn perform_tls_server_handshake_temp_with_optional_client_auth<
     }
 }
 
+// If `metrics` are given, the outcome of the handshake is reported to them.
+#[allow(clippy::too_many_arguments)]
+pub async fn perform_tls_server_handshake_without_client_auth<C: CspTlsServerHandshake>(
+    csp: &C,
+    self_node_id: NodeId,
+    registry_client: &Arc<dyn RegistryClient>,
+    tcp_stream: TcpStream,
+    registry_version: RegistryVersion,
+    allow_tls12: bool,
+    registry_retry: RegistryRetryConfig,
+    metrics: Option<&dyn TlsServerHandshakeMetrics>,
+) -> Result<TlsStream, TlsServerHandshakeError> {
+    let result = perform_tls_server_handshake_with_unauthenticated_client(
+        csp,
+        self_node_id,
+        registry_client,
+        tcp_stream,
+        registry_version,
+        allow_tls12,
+        registry_retry,
+    )
+    .await;
+    observe_outcome(metrics, &result);
+    result
+}
+
+async fn perform_tls_server_handshake_with_unauthenticated_client<C: CspTlsServerHandshake>(
+    csp: &C,
+    self_node_id: NodeId,
+    registry_client: &Arc<dyn RegistryClient>,
+    tcp_stream: TcpStream,
+    registry_version: RegistryVersion,
+    allow_tls12: bool,
+    registry_retry: RegistryRetryConfig,
+) -> Result<TlsStream, TlsServerHandshakeError> {
+    let self_tls_cert = retry_on_registry_error(registry_retry, || {
+        tls_cert_from_registry(registry_client, self_node_id, registry_version)
+    })
+    .await?;
+    let tls_stream = csp
+        .perform_tls_server_handshake_without_client_auth(tcp_stream, self_tls_cert, allow_tls12)
+        .await?;
+    Ok(tls_stream)
+}
+
+/// Calls `lookup` until it succeeds, fails with an error other than a
+/// `RegistryError`, or `retry.attempts` attempts have been made.
+async fn retry_on_registry_error<T, F>(
+    retry: RegistryRetryConfig,
+    lookup: F,
+) -> Result<T, TlsCertFromRegistryError>
+where
+    F: Fn() -> Result<T, TlsCertFromRegistryError>,
+{
+    let mut attempt = 1;
+    loop {
+        match lookup() {
+            Err(TlsCertFromRegistryError::RegistryError(_)) if attempt < retry.attempts => {
+                attempt += 1;
+                tokio::time::delay_for(retry.delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
 fn tls_certs_from_registry(
     registry_client: &Arc<dyn RegistryClient>,
     nodes: &SomeOrAllNodes,
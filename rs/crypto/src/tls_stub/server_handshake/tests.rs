@@ -0,0 +1,209 @@
+#![allow(clippy::unwrap_used)]
+use super::*;
+use ic_crypto_internal_csp::secret_key_store::volatile_store::VolatileSecretKeyStore;
+use ic_crypto_internal_csp::Csp;
+use ic_crypto_test_utils::tls::x509_certificates::CertWithPrivateKey;
+use ic_test_utilities::registry::MockRegistryClient;
+use ic_test_utilities::types::ids::node_test_id;
+use ic_types::registry::RegistryClientError;
+use prost::Message;
+use rand_chacha::ChaCha20Rng;
+use rand_core::SeedableRng;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Mutex;
+use tokio::net::TcpListener;
+
+const NOT_BEFORE: &str = "20190101000000Z";
+const NOT_AFTER: &str = "20200101000000Z";
+
+#[tokio::test]
+async fn should_retry_cert_lookup_if_registry_is_temporarily_unavailable() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let registry = registry_failing_then_returning(2, Some(cert_der()), Arc::clone(&calls));
+
+    let result = retry_on_registry_error(retry_config(3), || {
+        tls_cert_from_registry(&registry, node_test_id(1), REGISTRY_VERSION_1)
+    })
+    .await;
+
+    assert_eq!(result.unwrap().certificate_der, cert_der());
+    assert_eq!(calls.load(AtomicOrdering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn should_return_registry_error_if_retries_are_exhausted() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let registry = registry_failing_then_returning(2, Some(cert_der()), Arc::clone(&calls));
+
+    let result = retry_on_registry_error(retry_config(2), || {
+        tls_cert_from_registry(&registry, node_test_id(1), REGISTRY_VERSION_1)
+    })
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(TlsCertFromRegistryError::RegistryError(
+            RegistryClientError::PollLockFailed { .. }
+        ))
+    ));
+    assert_eq!(calls.load(AtomicOrdering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn should_not_retry_if_cert_is_not_in_registry() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let registry = registry_failing_then_returning(0, None, Arc::clone(&calls));
+
+    let result = retry_on_registry_error(retry_config(3), || {
+        tls_cert_from_registry(&registry, node_test_id(1), REGISTRY_VERSION_1)
+    })
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(TlsCertFromRegistryError::CertificateNotInRegistry { .. })
+    ));
+    assert_eq!(calls.load(AtomicOrdering::SeqCst), 1);
+}
+
+#[test]
+fn should_observe_malformed_self_cert_outcome() {
+    let metrics = RecordingMetrics::default();
+    let result: Result<(), _> = Err(TlsServerHandshakeError::MalformedSelfCertificate {
+        internal_error: "malformed".to_string(),
+    });
+
+    observe_outcome(Some(&metrics), &result);
+
+    assert_eq!(
+        *metrics.outcomes.lock().unwrap(),
+        vec!["malformed_self_cert"]
+    );
+}
+
+#[tokio::test]
+async fn should_observe_malformed_self_cert_outcome_of_handshake() {
+    let metrics = RecordingMetrics::default();
+    let malformed_cert_der = vec![42; 10];
+    let registry =
+        registry_failing_then_returning(0, Some(malformed_cert_der), Arc::new(AtomicUsize::new(0)));
+    let csp = Csp::of(
+        ChaCha20Rng::seed_from_u64(42),
+        VolatileSecretKeyStore::new(),
+    );
+    let allowed_clients =
+        AllowedClients::new_with_nodes(vec![node_test_id(2)].into_iter().collect()).unwrap();
+
+    let result = perform_tls_server_handshake(
+        &csp,
+        node_test_id(1),
+        &registry,
+        dummy_tcp_stream().await,
+        allowed_clients,
+        REGISTRY_VERSION_1,
+        None,
+        None,
+        retry_config(1),
+        Some(&metrics),
+    )
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(TlsServerHandshakeError::MalformedSelfCertificate { .. })
+    ));
+    assert_eq!(
+        *metrics.outcomes.lock().unwrap(),
+        vec![OUTCOME_MALFORMED_SELF_CERT]
+    );
+}
+
+#[test]
+fn should_label_handshake_outcomes() {
+    assert_eq!(outcome_label::<()>(&Ok(())), OUTCOME_SUCCESS);
+    assert_eq!(
+        outcome_label::<()>(&Err(TlsServerHandshakeError::RegistryError(
+            RegistryClientError::PollLockFailed {
+                error: "registry is busy".to_string(),
+            }
+        ))),
+        OUTCOME_REGISTRY_ERROR
+    );
+    assert_eq!(
+        outcome_label::<()>(&Err(TlsServerHandshakeError::ClientNotAllowed(
+            PeerNotAllowedError::HandshakeCertificateNodeIdNotAllowed
+        ))),
+        OUTCOME_PEER_AUTH_FAILED
+    );
+    assert_eq!(
+        outcome_label::<()>(&Err(TlsServerHandshakeError::UnauthenticatedClient)),
+        OUTCOME_PEER_AUTH_FAILED
+    );
+    assert_eq!(
+        outcome_label::<()>(&Err(TlsServerHandshakeError::NoMatchingAlpnProtocol)),
+        OUTCOME_HANDSHAKE_ERROR
+    );
+}
+
+#[derive(Default)]
+struct RecordingMetrics {
+    outcomes: Mutex<Vec<&'static str>>,
+}
+
+impl TlsServerHandshakeMetrics for RecordingMetrics {
+    fn observe_outcome(&self, outcome: &'static str) {
+        self.outcomes.lock().unwrap().push(outcome);
+    }
+}
+
+const REGISTRY_VERSION_1: RegistryVersion = RegistryVersion::new(1);
+
+async fn dummy_tcp_stream() -> TcpStream {
+    let listener = TcpListener::bind(("0.0.0.0", 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    TcpStream::connect(("127.0.0.1", port)).await.unwrap()
+}
+
+fn retry_config(attempts: usize) -> RegistryRetryConfig {
+    RegistryRetryConfig {
+        attempts,
+        delay: Duration::from_millis(1),
+    }
+}
+
+/// Returns a registry whose first `failures` lookups fail with a transient
+/// error, and whose subsequent lookups return `cert_der` as TLS certificate.
+fn registry_failing_then_returning(
+    failures: usize,
+    cert_der: Option<Vec<u8>>,
+    calls: Arc<AtomicUsize>,
+) -> Arc<dyn RegistryClient> {
+    let mut registry = MockRegistryClient::new();
+    registry.expect_get_value().returning(move |_, _| {
+        if calls.fetch_add(1, AtomicOrdering::SeqCst) < failures {
+            return Err(RegistryClientError::PollLockFailed {
+                error: "registry is busy".to_string(),
+            });
+        }
+        Ok(cert_der.clone().map(|certificate_der| {
+            let mut bytes = Vec::new();
+            X509PublicKeyCert { certificate_der }
+                .encode(&mut bytes)
+                .unwrap();
+            bytes
+        }))
+    });
+    Arc::new(registry)
+}
+
+fn cert_der() -> Vec<u8> {
+    cert_valid_from_2019_to_2020().to_der().unwrap()
+}
+
+fn cert_valid_from_2019_to_2020() -> X509 {
+    CertWithPrivateKey::builder()
+        .not_before(NOT_BEFORE)
+        .not_after(NOT_AFTER)
+        .build_ed25519()
+        .x509()
+}
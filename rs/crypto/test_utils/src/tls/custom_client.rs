@@ -26,6 +26,8 @@ pub struct CustomClientBuilder {
     client_auth_data: Option<(PKey<Private>, X509)>,
     extra_chain_certs: Option<Vec<X509>>,
     msg_expected_from_server: Option<String>,
+    expected_negotiated_cipher_suite: Option<String>,
+    alpn_protocols: Option<Vec<Vec<u8>>>,
 }
 
 impl CustomClientBuilder {
@@ -78,6 +80,16 @@ impl CustomClientBuilder {
         self
     }
 
+    pub fn expect_negotiated_cipher_suite(mut self, cipher_suite: &str) -> Self {
+        self.expected_negotiated_cipher_suite = Some(cipher_suite.to_string());
+        self
+    }
+
+    pub fn with_alpn_protocols(mut self, alpn_protocols: &[&[u8]]) -> Self {
+        self.alpn_protocols = Some(alpn_protocols.iter().map(|p| p.to_vec()).collect());
+        self
+    }
+
     pub fn build(self, server_cert: X509PublicKeyCert) -> CustomClient {
         let max_proto_version = self.max_proto_version.unwrap_or(DEFAULT_MAX_PROTO_VERSION);
         let allowed_cipher_suites = self
@@ -97,6 +109,8 @@ impl CustomClientBuilder {
             allowed_signature_algorithms,
             expected_error: self.expected_error,
             msg_expected_from_server: self.msg_expected_from_server,
+            expected_negotiated_cipher_suite: self.expected_negotiated_cipher_suite,
+            alpn_protocols: self.alpn_protocols,
         }
     }
 }
@@ -112,6 +126,8 @@ pub struct CustomClient {
     allowed_signature_algorithms: String,
     expected_error: Option<String>,
     msg_expected_from_server: Option<String>,
+    expected_negotiated_cipher_suite: Option<String>,
+    alpn_protocols: Option<Vec<Vec<u8>>>,
 }
 
 #[allow(unused)]
@@ -125,6 +141,8 @@ impl CustomClient {
             client_auth_data: None,
             extra_chain_certs: None,
             msg_expected_from_server: None,
+            expected_negotiated_cipher_suite: None,
+            alpn_protocols: None,
         }
     }
 
@@ -157,6 +175,7 @@ impl CustomClient {
                     error
                 ),
                 Ok(stream) => {
+                    self.expect_negotiated_cipher_suite_if_configured(&stream);
                     let (mut tls_read_half, tls_write_half) = tokio::io::split(stream);
                     self.expect_msg_from_server_if_configured(&mut tls_read_half)
                         .await;
@@ -182,6 +201,16 @@ impl CustomClient {
                 .check_private_key()
                 .expect("Inconsistent private key and certificate.");
         }
+        if let Some(alpn_protocols) = &self.alpn_protocols {
+            let mut wire_format = Vec::new();
+            for protocol in alpn_protocols {
+                wire_format.push(protocol.len() as u8);
+                wire_format.extend_from_slice(protocol);
+            }
+            builder
+                .set_alpn_protos(&wire_format)
+                .expect("Failed to set the ALPN protocols.");
+        }
         if let Some(extra_chain_certs) = &self.extra_chain_certs {
             for extra_chain_cert in extra_chain_certs {
                 builder.add_extra_chain_cert(extra_chain_cert.clone());
@@ -217,6 +246,17 @@ impl CustomClient {
         panic!("no certificate since client auth is disabled")
     }
 
+    fn expect_negotiated_cipher_suite_if_configured(&self, stream: &SslStream<TcpStream>) {
+        if let Some(expected_cipher_suite) = &self.expected_negotiated_cipher_suite {
+            let negotiated_cipher_suite = stream
+                .ssl()
+                .current_cipher()
+                .expect("no cipher suite was negotiated")
+                .name();
+            assert_eq!(negotiated_cipher_suite, expected_cipher_suite);
+        }
+    }
+
     async fn expect_msg_from_server_if_configured(
         &self,
         tls_read_half: &mut ReadHalf<SslStream<TcpStream>>,
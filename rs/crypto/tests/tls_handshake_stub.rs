@@ -822,7 +822,8 @@ mod server {
             .with_client_auth(
                 CertWithPrivateKey::builder()
                     .cn(CLIENT_ID_1.to_string())
-                    .validity_days(0) // current time
+                    .not_before("20190101000000Z")
+                    .not_after("20200101000000Z")
                     .build_ed25519(),
             )
             .build(server.cert());
@@ -833,7 +834,12 @@ mod server {
 
         let (_, server_result) = tokio::join!(client.run(server.port()), server.run());
 
-        assert_handshake_server_error_containing(&server_result, "certificate has expired");
+        assert_eq!(
+            server_result.unwrap_err(),
+            TlsServerHandshakeError::PeerCertificateExpired {
+                not_after: "Jan  1 00:00:00 2020 GMT".to_string()
+            }
+        );
     }
 
     #[tokio::test]
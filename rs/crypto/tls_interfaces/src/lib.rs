@@ -19,6 +19,32 @@ use tokio_openssl::SslStream;
 #[cfg(test)]
 mod tests;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A TLS 1.3 cipher suite that can be allowed for a TLS handshake.
+pub enum TlsCipherSuite {
+    /// TLS_AES_128_GCM_SHA256
+    Aes128GcmSha256,
+    /// TLS_AES_256_GCM_SHA384
+    Aes256GcmSha384,
+}
+
+impl TlsCipherSuite {
+    /// Returns the name of the cipher suite as specified in RFC 8446.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TlsCipherSuite::Aes128GcmSha256 => "TLS_AES_128_GCM_SHA256",
+            TlsCipherSuite::Aes256GcmSha384 => "TLS_AES_256_GCM_SHA384",
+        }
+    }
+}
+
+/// The cipher suites that are allowed for a TLS handshake unless configured
+/// otherwise.
+pub const DEFAULT_TLS_CIPHER_SUITES: &[TlsCipherSuite] = &[
+    TlsCipherSuite::Aes128GcmSha256,
+    TlsCipherSuite::Aes256GcmSha384,
+];
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 /// Errors from a TLS handshake performed as the server. Please refer to the
 /// `TlsHandshake` method for detailed error variant descriptions.
@@ -42,6 +68,10 @@ pub enum TlsServerHandshakeError {
     },
     ClientNotAllowed(PeerNotAllowedError),
     UnauthenticatedClient,
+    PeerCertificateExpired {
+        not_after: String,
+    },
+    NoMatchingAlpnProtocol,
 }
 
 impl Display for TlsServerHandshakeError {
@@ -146,6 +176,24 @@ impl TlsStream {
         Self { ssl_stream }
     }
 
+    /// Returns the protocol negotiated via ALPN during the handshake, if any.
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        self.ssl_stream
+            .ssl()
+            .selected_alpn_protocol()
+            .map(|protocol| protocol.to_vec())
+    }
+
+    /// Returns the protocol version and cipher suite negotiated during the
+    /// handshake.
+    pub fn session_info(&self) -> TlsSessionInfo {
+        let ssl = self.ssl_stream.ssl();
+        TlsSessionInfo {
+            version: ssl.version_str().to_string(),
+            cipher_suite: ssl.current_cipher().map(|cipher| cipher.name().to_string()),
+        }
+    }
+
     /// Use this method to split a `TlsStream`, as it returns `TlsReadHalf`
     /// and `TlsWriteHalf` that are guaranteed to be protected by TLS by the
     /// type system.
@@ -186,6 +234,16 @@ impl AsyncWrite for TlsStream {
     }
 }
 
+/// The parameters negotiated during a TLS handshake.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsSessionInfo {
+    /// The protocol version, e.g. "TLSv1.3"
+    pub version: String,
+    /// The name of the cipher suite, e.g. "TLS_AES_128_GCM_SHA256", or `None`
+    /// if no cipher suite was negotiated
+    pub cipher_suite: Option<String>,
+}
+
 /// The read half of a stream over a secure connection protected by TLS.
 pub struct TlsReadHalf {
     read_half: ReadHalf<SslStream<TcpStream>>,
@@ -302,6 +360,10 @@ pub trait TlsHandshake {
     ///   registry.
     /// * TlsServerHandshakeError::UnauthenticatedClient if the client did not
     ///   authenticate using a client certificate.
+    /// * TlsServerHandshakeError::PeerCertificateExpired if the certificate
+    ///   the client presented in the handshake has expired.
+    /// * TlsServerHandshakeError::NoMatchingAlpnProtocol if an ALPN protocol
+    ///   is required, but the client did not offer it.
     ///
     /// # Panics
     /// * If the secret key corresponding to the server certificate cannot be
@@ -314,8 +376,50 @@ pub trait TlsHandshake {
         registry_version: RegistryVersion,
     ) -> Result<(TlsStream, AuthenticatedPeer), TlsServerHandshakeError>;
 
+    /// Transforms a TCP stream into a TLS stream by performing a TLS server
+    /// handshake with a client that does not authenticate, e.g. an external
+    /// monitoring agent.
+    ///
+    /// For the handshake, the server uses the following configuration:
+    /// * Minimum protocol version: TLS 1.3, or TLS 1.2 if `allow_tls12` is set
+    /// * Supported signature algorithms: ed25519
+    /// * Allowed cipher suites: TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384,
+    ///   and for TLS 1.2 ECDHE-ECDSA-AES128-GCM-SHA256,
+    ///   ECDHE-ECDSA-AES256-GCM-SHA384
+    /// * Client authentication: none
+    ///
+    /// `allow_tls12` is meant for clients that cannot do TLS 1.3. Connections
+    /// between nodes must use `perform_tls_server_handshake` instead, which
+    /// requires client authentication and TLS 1.3.
+    ///
+    /// The given `tcp_stream` is consumed. If an error is returned, the TCP
+    /// connection is therefore dropped.
+    ///
+    /// # Errors
+    /// * TlsServerHandshakeError::RegistryError if the registry cannot be
+    ///   accessed.
+    /// * TlsServerHandshakeError::CertificateNotInRegistry if the node's own
+    ///   server certificate is not found in the registry.
+    /// * TlsServerHandshakeError::MalformedSelfCertificate if the node's own
+    ///   server certificate is malformed.
+    /// * TlsServerHandshakeError::CreateAcceptorError if there is a problem
+    ///   configuring the server for accepting connections from clients.
+    /// * TlsServerHandshakeError::HandshakeError if there is an error during
+    ///   the TLS handshake, or the handshake fails.
+    ///
+    /// # Panics
+    /// * If the secret key corresponding to the server certificate cannot be
+    ///   found or is malformed in the server's secret key store. Note that this
+    ///   is an error in the setup of the node and registry.
+    async fn perform_tls_server_handshake_without_client_auth(
+        &self,
+        tcp_stream: TcpStream,
+        registry_version: RegistryVersion,
+        allow_tls12: bool,
+    ) -> Result<TlsStream, TlsServerHandshakeError>;
+
     /// IMPORTANT NODE: This method is temporary. It will be replaced by
-    /// `perform_tls_server_handshake` and a new method
+    /// `perform_tls_server_handshake` and
     /// `perform_tls_server_handshake_without_client_auth` soon. This method is
     /// currently needed to allow connections without knowing if a client
     /// performs client authentication.
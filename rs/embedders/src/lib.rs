@@ -252,6 +252,17 @@ pub trait Instance {
     /// Returns execution statistics for this instance.  Note that
     /// stats must be available even if this instance trapped.
     fn get_stats(&self) -> InstanceStats;
+
+    /// Releases the physical pages backing the heap above `new_size` bytes to
+    /// lower the RSS of the process. The heap size does not change.
+    ///
+    /// The released pages lose any changes made to them: on their next
+    /// access they read as zeros, or as their initial contents if the heap
+    /// is initialized from a page map or mapped from a file. If the heap is
+    /// tracked, the released pages are reset in the memory tracker as well,
+    /// so that they are reported neither as accessed nor as dirty until they
+    /// are accessed again.
+    fn decommit_heap_above(&mut self, new_size: usize);
 }
 
 pub trait Embedder: Sync {
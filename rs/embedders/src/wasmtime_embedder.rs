@@ -1,7 +1,7 @@
 pub mod host_memory;
 
-use host_memory::MmapMemoryCreator;
 pub use host_memory::WasmtimeMemoryCreator;
+use host_memory::{MmapMemoryCreator, MmapMemoryPool};
 
 mod signal_stack;
 mod system_api;
@@ -60,18 +60,30 @@ fn trap_to_error(err: anyhow::Error) -> HypervisorError {
 pub struct WasmtimeEmbedder {
     log: ReplicaLogger,
     max_wasm_stack_size: usize,
+    use_huge_pages: bool,
+    mmap_memory_pool: Option<Arc<MmapMemoryPool>>,
 }
 
 impl WasmtimeEmbedder {
     pub fn new(config: Config, log: ReplicaLogger) -> Self {
         let Config {
             max_wasm_stack_size,
+            use_huge_pages,
+            mmap_memory_pool_size,
             ..
         } = config;
 
+        let mmap_memory_pool = if mmap_memory_pool_size > 0 {
+            Some(Arc::new(MmapMemoryPool::new(mmap_memory_pool_size)))
+        } else {
+            None
+        };
+
         WasmtimeEmbedder {
             log,
             max_wasm_stack_size,
+            use_huge_pages,
+            mmap_memory_pool,
         }
     }
 }
@@ -85,7 +97,10 @@ impl Embedder for WasmtimeEmbedder {
         let mut config = wasmtime::Config::default();
         let cached_mem_creator = match persistence_type {
             PersistenceType::Sigsegv => {
-                let raw_creator = MmapMemoryCreator {};
+                let mut raw_creator = MmapMemoryCreator::new(self.use_huge_pages);
+                if let Some(pool) = &self.mmap_memory_pool {
+                    raw_creator = raw_creator.with_pool(Arc::clone(pool));
+                }
                 let mem_creator = Arc::new(WasmtimeMemoryCreator::new(raw_creator));
                 config.with_host_memory(mem_creator);
                 None
@@ -500,4 +515,18 @@ impl Instance for WasmtimeInstance {
                 .map_or(0, |tracker| tracker.num_dirty_pages()),
         }
     }
+
+    fn decommit_heap_above(&mut self, new_size: usize) {
+        let memory = match self.memory() {
+            Ok(memory) => memory,
+            Err(_) => return,
+        };
+        let released =
+            unsafe { host_memory::decommit_above(memory.data_ptr(), memory.data_size(), new_size) };
+        if let (Some((start, len)), Some(memory_tracker)) = (released, self.memory_tracker.as_ref())
+        {
+            let page_size = *ic_sys::PAGE_SIZE;
+            memory_tracker.reset_pages(start / page_size, len / page_size);
+        }
+    }
 }
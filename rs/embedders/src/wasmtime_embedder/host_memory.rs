@@ -8,12 +8,14 @@ use ic_sys::PAGE_SIZE;
 
 use libc::c_void;
 use libc::MAP_FAILED;
-use libc::{mmap, munmap};
-use libc::{MAP_ANON, MAP_PRIVATE, PROT_NONE};
+use libc::{madvise, mmap, mprotect, munmap};
+use libc::{MADV_DONTNEED, MAP_ANON, MAP_PRIVATE, PROT_NONE};
 
 use std::cell::RefCell;
 use std::io::Error;
 use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 pub fn round_up_to_page_size(size: usize, page_size: usize) -> usize {
     (size + (page_size - 1)) & !(page_size - 1)
@@ -27,17 +29,64 @@ fn wasm_max_mem_size_in_bytes() -> usize {
     WASM_MAX_PAGES as usize * WASM_PAGE_SIZE as usize
 }
 
+/// Keeps track of the total number of wasm pages used by all live memories
+/// created by one `WasmtimeMemoryCreator`.
+#[derive(Clone, Default)]
+struct MemoryUsage {
+    total_pages: Arc<AtomicU64>,
+    on_grow: Option<Arc<dyn Fn(usize) + Send + Sync>>,
+}
+
+impl MemoryUsage {
+    fn byte_size(&self) -> usize {
+        self.total_pages.load(Ordering::Relaxed) as usize * WASM_PAGE_SIZE as usize
+    }
+
+    fn add_pages(&self, pages: u32) {
+        self.total_pages.fetch_add(pages as u64, Ordering::Relaxed);
+    }
+
+    fn grow(&self, delta: u32) {
+        self.add_pages(delta);
+        if let Some(on_grow) = &self.on_grow {
+            on_grow(self.byte_size());
+        }
+    }
+
+    fn remove_pages(&self, pages: u32) {
+        self.total_pages.fetch_sub(pages as u64, Ordering::Relaxed);
+    }
+}
+
 #[derive(Default)]
 pub struct WasmtimeMemoryCreator<C: ICMemoryCreator>
 where
     <C as ICMemoryCreator>::Mem: 'static,
 {
     raw_creator: C,
+    usage: MemoryUsage,
 }
 
 impl<C: ICMemoryCreator> WasmtimeMemoryCreator<C> {
     pub fn new(raw_creator: C) -> Self {
-        Self { raw_creator }
+        Self {
+            raw_creator,
+            usage: MemoryUsage::default(),
+        }
+    }
+
+    /// Registers a callback that is invoked whenever one of the memories
+    /// created by this creator grows. The callback receives the total size
+    /// in bytes of all live memories after the growth.
+    pub fn with_grow_callback(mut self, on_grow: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.usage.on_grow = Some(Arc::new(on_grow));
+        self
+    }
+
+    /// Returns the total size in bytes of all live memories created by this
+    /// creator, i.e. the sum of their wasm-visible sizes.
+    pub fn total_byte_size(&self) -> usize {
+        self.usage.byte_size()
     }
 }
 
@@ -48,12 +97,21 @@ unsafe impl<C: ICMemoryCreator + Send + Sync> wasmtime::MemoryCreator for Wasmti
         reserved_size_in_bytes: Option<u64>,
         guard_size: u64,
     ) -> Result<Box<dyn wasmtime::LinearMemory>, String> {
+        //Wasmtime 'guarantees' that these values are <= WASM_MAX_PAGES
+        //and has asserts for that in its Memory implementation
+        //but let's not rely on that in case they change something...
+        //A minimum above the limit cannot be satisfied, so it is rejected
+        //rather than clipped: a clipped minimum would only make the module
+        //trap later on, in a way that is hard to diagnose.
+        let min = ty.limits().min();
+        if min > WASM_MAX_PAGES {
+            return Err(format!(
+                "Wasm memory requires a minimum of {} pages, which exceeds the maximum of {} pages",
+                min, WASM_MAX_PAGES
+            ));
+        }
         unsafe {
-            //Wasmtime 'guarantees' that these values are <= WASM_MAX_PAGES
-            //and has asserts for that in its Memory implementation
-            //but let's just clip to that without panicking in case they change
-            // something...
-            let min = std::cmp::min(ty.limits().min(), WASM_MAX_PAGES);
+            //Clipping the maximum is harmless: growing past it fails cleanly.
             let max = ty.limits().max().unwrap_or(WASM_MAX_PAGES);
             let max = std::cmp::min(max, WASM_MAX_PAGES);
 
@@ -65,12 +123,41 @@ unsafe impl<C: ICMemoryCreator + Send + Sync> wasmtime::MemoryCreator for Wasmti
                 .raw_creator
                 .new_memory(mem_size, guard_size, 0, min, Some(max));
 
-            Ok(Box::new(WasmtimeMemory::new(mem, min, max)))
+            Ok(Box::new(WasmtimeMemory::new(
+                mem,
+                min,
+                max,
+                self.usage.clone(),
+            )))
         }
     }
 }
 
-pub(crate) struct MmapMemoryCreator {}
+#[derive(Default)]
+pub(crate) struct MmapMemoryCreator {
+    /// Whether memories should be backed by huge pages if the kernel
+    /// provides them. See `MmapMemory::new_with_huge_pages`.
+    use_huge_pages: bool,
+    /// The pool memories are taken from and returned to, if any. Only used
+    /// if `use_huge_pages` is not set.
+    pool: Option<Arc<MmapMemoryPool>>,
+}
+
+impl MmapMemoryCreator {
+    pub(crate) fn new(use_huge_pages: bool) -> Self {
+        Self {
+            use_huge_pages,
+            pool: None,
+        }
+    }
+
+    /// Reuses the mappings kept in `pool` instead of creating a fresh
+    /// mapping for every memory.
+    pub(crate) fn with_pool(mut self, pool: Arc<MmapMemoryPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+}
 
 impl ICMemoryCreator for MmapMemoryCreator {
     type Mem = MmapMemory;
@@ -82,22 +169,115 @@ impl ICMemoryCreator for MmapMemoryCreator {
         _min_pages: u32,
         _max_pages: Option<u32>,
     ) -> MmapMemory {
-        unsafe { MmapMemory::new(mem_size, guard_size) }
+        unsafe {
+            if self.use_huge_pages {
+                MmapMemory::new_with_huge_pages(mem_size, guard_size)
+            } else if let Some(pool) = &self.pool {
+                MmapMemoryPool::new_memory(pool, mem_size, guard_size)
+            } else {
+                MmapMemory::new(mem_size, guard_size)
+            }
+        }
+    }
+}
+
+/// Keeps up to `capacity` mappings of dropped `MmapMemory`s around, so that
+/// they can be handed out again instead of paying for a fresh `mmap` and a
+/// `munmap` for every memory.
+///
+/// Before a mapping is put back into the pool, its physical pages are
+/// released with `madvise(MADV_DONTNEED)` and the whole mapping is made
+/// inaccessible again, so a reused mapping is indistinguishable from a fresh
+/// one. The address space stays reserved until the pool is dropped.
+pub(crate) struct MmapMemoryPool {
+    capacity: usize,
+    free: Mutex<Vec<MmapMemory>>,
+}
+
+impl MmapMemoryPool {
+    /// Creates a pool that keeps at most `capacity` unused mappings.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            free: Mutex::new(Vec::with_capacity(capacity)),
+        }
+    }
+
+    /// Returns a memory of the given sizes, reusing a mapping of the same
+    /// total size from `pool` if there is one. The memory is returned to
+    /// `pool` when it is dropped.
+    ///
+    /// # Safety
+    /// See `MmapMemory::new`.
+    unsafe fn new_memory(
+        pool: &Arc<Self>,
+        mem_size_in_bytes: usize,
+        guard_size_in_bytes: usize,
+    ) -> MmapMemory {
+        let (usable_size, guard_size) = mapping_sizes(mem_size_in_bytes, guard_size_in_bytes);
+        let mut memory = pool
+            .take(usable_size + guard_size)
+            .unwrap_or_else(|| MmapMemory::new(mem_size_in_bytes, guard_size_in_bytes));
+        memory.pool = Some(Arc::clone(pool));
+        memory
+    }
+
+    fn take(&self, mem_size: usize) -> Option<MmapMemory> {
+        let mut free = self.free.lock().unwrap();
+        let index = free.iter().position(|memory| memory.mem_size == mem_size)?;
+        Some(free.swap_remove(index))
+    }
+
+    /// Puts the mapping back into the pool if there is room. Returns whether
+    /// the pool took ownership of the mapping.
+    fn give_back(&self, mem: *mut c_void, mem_size: usize) -> bool {
+        let mut free = self.free.lock().unwrap();
+        if free.len() >= self.capacity {
+            return false;
+        }
+        unsafe {
+            let result = madvise(mem, mem_size, MADV_DONTNEED);
+            assert_eq!(result, 0, "madvise failed: {}", Error::last_os_error());
+            let result = mprotect(mem, mem_size, PROT_NONE);
+            assert_eq!(result, 0, "mprotect failed: {}", Error::last_os_error());
+        }
+        free.push(MmapMemory::from_raw(mem, mem_size));
+        true
     }
 }
 
 pub struct MmapMemory {
     mem: *mut c_void,
     mem_size: usize,
+    /// The pool the mapping is returned to on drop. If `None`, the mapping
+    /// is unmapped.
+    pool: Option<Arc<MmapMemoryPool>>,
 }
 
 unsafe impl Send for MmapMemory {}
 
+/// Returns the sizes of the usable part and of the guard region of a mapping,
+/// each rounded up to whole OS pages.
+fn mapping_sizes(mem_size_in_bytes: usize, guard_size_in_bytes: usize) -> (usize, usize) {
+    let usable_size = round_up_to_os_page_size(mem_size_in_bytes);
+    let guard_size = round_up_to_os_page_size(guard_size_in_bytes);
+    (usable_size, guard_size)
+}
+
 impl MmapMemory {
+    /// Reserves `mem_size_in_bytes` of memory followed by a guard region of
+    /// `guard_size_in_bytes`, both rounded up to whole OS pages.
+    ///
+    /// The whole mapping is reserved with `PROT_NONE`. The caller may make
+    /// (parts of) the first `mem_size_in_bytes` accessible, but the guard
+    /// region is never made accessible, so that any access past the usable
+    /// memory traps.
+    ///
     /// # Safety
     /// It calls libc::mmap is if arguments don't make sense it can fail
     pub unsafe fn new(mem_size_in_bytes: usize, guard_size_in_bytes: usize) -> Self {
-        let mem_size = round_up_to_os_page_size(mem_size_in_bytes + guard_size_in_bytes);
+        let (usable_size, guard_size) = mapping_sizes(mem_size_in_bytes, guard_size_in_bytes);
+        let mem_size = usable_size + guard_size;
 
         // It is important to reserve the memory with PROT_NONE. Otherwise,
         // depending on the overcommit strategy configured in the kernel, the
@@ -119,14 +299,69 @@ impl MmapMemory {
             Error::last_os_error()
         );
 
-        Self { mem, mem_size }
+        Self::from_raw(mem, mem_size)
+    }
+
+    /// Like `new`, but asks the kernel to back the memory with transparent
+    /// huge pages (`madvise(MADV_HUGEPAGE)`) to reduce TLB pressure for large
+    /// memories. If the kernel rejects this, e.g. because it has no support
+    /// for transparent huge pages, the memory is backed by regular pages.
+    ///
+    /// Note that this deliberately does not map the memory with
+    /// `MAP_HUGETLB`: the kernel only changes the protection of (and releases)
+    /// `MAP_HUGETLB` mappings in whole huge pages, so such a memory could
+    /// neither be tracked page by page by `SigsegvMemoryTracker` nor be
+    /// decommitted with `decommit_above`. Transparent huge pages are split by
+    /// the kernel when the protection of a part of them changes.
+    ///
+    /// # Safety
+    /// See `new`.
+    pub unsafe fn new_with_huge_pages(
+        mem_size_in_bytes: usize,
+        guard_size_in_bytes: usize,
+    ) -> Self {
+        Self::new_with_huge_pages_advised_by(
+            mem_size_in_bytes,
+            guard_size_in_bytes,
+            advise_huge_pages,
+        )
+    }
+
+    /// Like `new_with_huge_pages`, but asks for huge pages with `advise`,
+    /// which returns whether the kernel accepted the advice.
+    unsafe fn new_with_huge_pages_advised_by(
+        mem_size_in_bytes: usize,
+        guard_size_in_bytes: usize,
+        advise: unsafe fn(*mut c_void, usize) -> bool,
+    ) -> Self {
+        let memory = Self::new(mem_size_in_bytes, guard_size_in_bytes);
+        // If the advice is rejected, the mapping simply stays backed by
+        // regular pages.
+        let _huge_pages_advised = advise(memory.mem, memory.mem_size);
+        memory
     }
 
     pub fn from_raw(mem: *mut c_void, mem_size: usize) -> Self {
-        Self { mem, mem_size }
+        Self {
+            mem,
+            mem_size,
+            pool: None,
+        }
     }
 }
 
+/// Asks the kernel to back the given mapping with transparent huge pages.
+/// Returns whether the kernel accepted the advice.
+#[cfg(target_os = "linux")]
+unsafe fn advise_huge_pages(mem: *mut c_void, mem_size: usize) -> bool {
+    madvise(mem, mem_size, libc::MADV_HUGEPAGE) == 0
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn advise_huge_pages(_mem: *mut c_void, _mem_size: usize) -> bool {
+    false
+}
+
 impl LinearMemory for MmapMemory {
     fn as_ptr(&self) -> *mut c_void {
         self.mem
@@ -135,6 +370,11 @@ impl LinearMemory for MmapMemory {
 
 impl Drop for MmapMemory {
     fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            if pool.give_back(self.mem, self.mem_size) {
+                return;
+            }
+        }
         let result = unsafe { munmap(self.mem, self.mem_size) };
         assert_eq!(result, 0, "munmap failed: {}", Error::last_os_error());
     }
@@ -144,18 +384,59 @@ pub struct WasmtimeMemory<M: LinearMemory> {
     pub mem: RefCell<M>,
     maximum: u32,
     used: RefCell<u32>,
+    usage: MemoryUsage,
 }
 
 impl<M: LinearMemory> WasmtimeMemory<M> {
-    unsafe fn new(mem: M, min: u32, maximum: u32) -> Self {
+    unsafe fn new(mem: M, min: u32, maximum: u32, usage: MemoryUsage) -> Self {
+        usage.add_pages(min);
         Self {
             mem: RefCell::new(mem),
             maximum,
             used: RefCell::new(min),
+            usage,
         }
     }
 }
 
+/// Releases the physical pages backing the first `byte_size` bytes at `base`
+/// above `new_size` bytes with `madvise(MADV_DONTNEED)` to lower the RSS of
+/// the process. Returns the offset and length of the released range, if any.
+///
+/// Only whole OS pages within `byte_size` are released, so the guard pages
+/// following a memory are left untouched. A memory tracked by a
+/// `SigsegvMemoryTracker` must have the released pages reset in the tracker,
+/// see `Instance::decommit_heap_above`.
+///
+/// # Safety
+/// `base` must point to a mapping of at least `byte_size` bytes.
+pub(crate) unsafe fn decommit_above(
+    base: *mut u8,
+    byte_size: usize,
+    new_size: usize,
+) -> Option<(usize, usize)> {
+    let (start, len) = decommit_range(new_size, byte_size)?;
+    let result = madvise(base.add(start) as *mut c_void, len, MADV_DONTNEED);
+    assert_eq!(result, 0, "madvise failed: {}", Error::last_os_error());
+    Some((start, len))
+}
+
+/// Returns the offset and length of the range of OS pages that lie entirely
+/// above `new_size` within the first `byte_size` bytes, if any.
+fn decommit_range(new_size: usize, byte_size: usize) -> Option<(usize, usize)> {
+    let start = round_up_to_os_page_size(new_size);
+    if start >= byte_size {
+        return None;
+    }
+    Some((start, byte_size - start))
+}
+
+impl<M: LinearMemory> Drop for WasmtimeMemory<M> {
+    fn drop(&mut self) {
+        self.usage.remove_pages(*self.used.borrow());
+    }
+}
+
 unsafe impl<M: LinearMemory> wasmtime::LinearMemory for WasmtimeMemory<M> {
     /// Returns the number of allocated wasm pages.
     fn size(&self) -> u32 {
@@ -176,6 +457,7 @@ unsafe impl<M: LinearMemory> wasmtime::LinearMemory for WasmtimeMemory<M> {
         self.mem.borrow().grow_mem_to(new_pages);
 
         *self.used.borrow_mut() = new_pages;
+        self.usage.grow(delta);
         Some(prev_pages)
     }
 
@@ -190,3 +472,279 @@ impl<M: LinearMemory> LinearMemory for WasmtimeMemory<M> {
         <Self as wasmtime::LinearMemory>::as_ptr(&self) as *mut c_void
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::{Limits, LinearMemory as _, MemoryCreator};
+
+    #[test]
+    fn should_reject_memory_with_minimum_above_max_pages() {
+        let creator = WasmtimeMemoryCreator::new(MmapMemoryCreator::default());
+        let ty = MemoryType::new(Limits::new(WASM_MAX_PAGES + 1, None));
+
+        let result = creator.new_memory(ty, Some(WASM_PAGE_SIZE as u64), 0);
+
+        match result {
+            Err(e) => assert!(e.contains("exceeds the maximum"), "{}", e),
+            Ok(_) => panic!("memory with too large minimum was created"),
+        }
+        assert_eq!(creator.total_byte_size(), 0);
+    }
+
+    #[test]
+    fn should_create_memory_with_minimum_at_most_max_pages_and_clip_maximum() {
+        let creator = WasmtimeMemoryCreator::new(MmapMemoryCreator::default());
+        let ty = MemoryType::new(Limits::new(1, Some(WASM_MAX_PAGES + 1)));
+
+        let memory = creator
+            .new_memory(ty, Some(2 * WASM_PAGE_SIZE as u64), 0)
+            .expect("failed to create memory");
+
+        assert_eq!(memory.size(), 1);
+        assert_eq!(creator.total_byte_size(), WASM_PAGE_SIZE as usize);
+    }
+
+    #[test]
+    fn should_reuse_pooled_mapping_for_sequential_memories() {
+        let pool = Arc::new(MmapMemoryPool::new(1));
+        let creator =
+            WasmtimeMemoryCreator::new(MmapMemoryCreator::default().with_pool(Arc::clone(&pool)));
+        let ty = MemoryType::new(Limits::new(1, Some(16)));
+        let reserved_size = Some(16 * WASM_PAGE_SIZE as u64);
+
+        let memory = creator.new_memory(ty.clone(), reserved_size, 0).unwrap();
+        let first_ptr = memory.as_ptr();
+        drop(memory);
+        let memory = creator.new_memory(ty, reserved_size, 0).unwrap();
+
+        assert_eq!(memory.as_ptr(), first_ptr);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn should_zero_pooled_mapping_before_reuse() {
+        use libc::{PROT_READ, PROT_WRITE};
+
+        let mem_size = WASM_PAGE_SIZE as usize;
+        let pool = Arc::new(MmapMemoryPool::new(1));
+        let memory = unsafe { MmapMemoryPool::new_memory(&pool, mem_size, 0) };
+        let ptr = memory.as_ptr() as *mut u8;
+        let result = unsafe { mprotect(memory.as_ptr(), mem_size, PROT_READ | PROT_WRITE) };
+        assert_eq!(result, 0, "mprotect failed: {}", Error::last_os_error());
+        unsafe { ptr.write_bytes(42, mem_size) };
+        drop(memory);
+
+        let memory = unsafe { MmapMemoryPool::new_memory(&pool, mem_size, 0) };
+        assert_eq!(memory.as_ptr() as *mut u8, ptr);
+        let result = unsafe { mprotect(memory.as_ptr(), mem_size, PROT_READ | PROT_WRITE) };
+        assert_eq!(result, 0, "mprotect failed: {}", Error::last_os_error());
+        unsafe {
+            assert_eq!(*ptr, 0);
+            assert_eq!(*ptr.add(mem_size - 1), 0);
+        }
+    }
+
+    #[test]
+    fn should_round_usable_size_and_guard_size_up_to_whole_pages() {
+        let os_page = *PAGE_SIZE;
+
+        assert_eq!(mapping_sizes(0, 0), (0, 0));
+        assert_eq!(mapping_sizes(1, 1), (os_page, os_page));
+        assert_eq!(mapping_sizes(os_page, os_page), (os_page, os_page));
+        assert_eq!(
+            mapping_sizes(os_page + 1, 2 * os_page + 1),
+            (2 * os_page, 3 * os_page)
+        );
+    }
+
+    #[test]
+    fn should_map_usable_memory_followed_by_guard_region() {
+        let os_page = *PAGE_SIZE;
+
+        let memory = unsafe { MmapMemory::new(os_page + 1, 1) };
+
+        assert_eq!(memory.mem_size, 3 * os_page);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn should_trap_on_access_to_guard_region() {
+        use libc::{_exit, fork, mprotect, waitpid, PROT_READ, SIGSEGV, WIFSIGNALED, WTERMSIG};
+
+        let usable_size = WASM_PAGE_SIZE as usize;
+        let memory = unsafe { MmapMemory::new(usable_size, *PAGE_SIZE) };
+        let result = unsafe { mprotect(memory.as_ptr(), usable_size, PROT_READ) };
+        assert_eq!(result, 0, "mprotect failed: {}", Error::last_os_error());
+        let ptr = memory.as_ptr() as *const u8;
+
+        // The access to the guard region happens in a child process so that
+        // the fault does not bring down the test runner.
+        let pid = unsafe { fork() };
+        assert!(pid >= 0, "fork failed: {}", Error::last_os_error());
+        if pid == 0 {
+            unsafe {
+                ptr.add(usable_size - 1).read_volatile();
+                ptr.add(usable_size).read_volatile();
+                _exit(0);
+            }
+        }
+        let mut status = 0;
+        let result = unsafe { waitpid(pid, &mut status, 0) };
+        assert_eq!(result, pid, "waitpid failed: {}", Error::last_os_error());
+        assert!(WIFSIGNALED(status));
+        assert_eq!(WTERMSIG(status), SIGSEGV);
+    }
+
+    #[test]
+    fn should_create_mapping_with_or_without_huge_pages() {
+        let mem_size = 3 * WASM_PAGE_SIZE as usize;
+
+        let memory = unsafe { MmapMemory::new_with_huge_pages(mem_size, *PAGE_SIZE) };
+
+        assert_ne!(memory.as_ptr(), MAP_FAILED);
+        assert_eq!(
+            memory.mem_size,
+            round_up_to_os_page_size(mem_size) + *PAGE_SIZE
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn should_create_usable_mapping_if_huge_pages_are_rejected() {
+        use libc::{PROT_READ, PROT_WRITE};
+
+        unsafe fn reject_huge_pages(_mem: *mut c_void, _mem_size: usize) -> bool {
+            false
+        }
+        let mem_size = 3 * WASM_PAGE_SIZE as usize;
+
+        let memory = unsafe {
+            MmapMemory::new_with_huge_pages_advised_by(mem_size, *PAGE_SIZE, reject_huge_pages)
+        };
+
+        assert_ne!(memory.as_ptr(), MAP_FAILED);
+        assert_eq!(
+            memory.mem_size,
+            round_up_to_os_page_size(mem_size) + *PAGE_SIZE
+        );
+        let result = unsafe { mprotect(memory.as_ptr(), mem_size, PROT_READ | PROT_WRITE) };
+        assert_eq!(result, 0, "mprotect failed: {}", Error::last_os_error());
+        let ptr = memory.as_ptr() as *mut u8;
+        unsafe { ptr.add(mem_size - 1).write(42) };
+        assert_eq!(unsafe { *ptr }, 0);
+        assert_eq!(unsafe { *ptr.add(mem_size - 1) }, 42);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn should_track_huge_page_memory_page_by_page() {
+        use ic_logger::replica_logger::no_op_logger;
+        use memory_tracker::SigsegvMemoryTracker;
+
+        // Two huge pages, so that the kernel may back at least one aligned
+        // huge page region of the mapping.
+        let mem_size = 4 << 20;
+        let memory = unsafe { MmapMemory::new_with_huge_pages(mem_size, 0) };
+        let tracker = SigsegvMemoryTracker::new(memory.as_ptr(), mem_size, no_op_logger())
+            .expect("failed to track huge page memory");
+        let page = *PAGE_SIZE;
+        let contents = vec![42; page];
+
+        // The first fault on a page makes it readable, the second writable.
+        for page_num in &[0, 1, mem_size / page - 1] {
+            let addr = unsafe { (memory.as_ptr() as *mut u8).add(page_num * page) };
+            assert!(tracker.handle_sigsegv(|_| Some(contents.as_slice()), addr as *mut c_void));
+            assert_eq!(unsafe { *addr }, 42);
+            assert!(tracker.handle_sigsegv(|_| None, addr as *mut c_void));
+            unsafe { *addr = 7 };
+            assert_eq!(unsafe { *addr }, 7);
+        }
+
+        assert_eq!(tracker.num_accessed_pages(), 3);
+        assert_eq!(tracker.num_dirty_pages(), 3);
+    }
+
+    #[test]
+    fn should_decommit_whole_os_pages_above_new_size() {
+        let os_page = *PAGE_SIZE;
+        let wasm_page = WASM_PAGE_SIZE as usize;
+
+        assert_eq!(decommit_range(0, 2 * wasm_page), Some((0, 2 * wasm_page)));
+        assert_eq!(
+            decommit_range(1, 2 * wasm_page),
+            Some((os_page, 2 * wasm_page - os_page))
+        );
+        assert_eq!(
+            decommit_range(wasm_page, 2 * wasm_page),
+            Some((wasm_page, wasm_page))
+        );
+    }
+
+    #[test]
+    fn should_not_decommit_anything_at_or_above_memory_size() {
+        let wasm_page = WASM_PAGE_SIZE as usize;
+
+        assert_eq!(decommit_range(2 * wasm_page, 2 * wasm_page), None);
+        assert_eq!(decommit_range(2 * wasm_page - 1, 2 * wasm_page), None);
+        assert_eq!(decommit_range(3 * wasm_page, 2 * wasm_page), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn should_zero_decommitted_pages() {
+        use libc::{mprotect, PROT_READ, PROT_WRITE};
+
+        let wasm_page = WASM_PAGE_SIZE as usize;
+        let memory = unsafe { MmapMemory::new(2 * wasm_page, 0) };
+        let ptr = memory.as_ptr() as *mut u8;
+        let result = unsafe { mprotect(memory.as_ptr(), 2 * wasm_page, PROT_READ | PROT_WRITE) };
+        assert_eq!(result, 0, "mprotect failed: {}", Error::last_os_error());
+        unsafe { ptr.write_bytes(42, 2 * wasm_page) };
+
+        let released = unsafe { decommit_above(ptr, 2 * wasm_page, wasm_page) };
+
+        assert_eq!(released, Some((wasm_page, wasm_page)));
+        unsafe {
+            assert_eq!(*ptr, 42);
+            assert_eq!(*ptr.add(wasm_page - 1), 42);
+            assert_eq!(*ptr.add(wasm_page), 0);
+            assert_eq!(*ptr.add(2 * wasm_page - 1), 0);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn should_reset_decommitted_pages_in_memory_tracker() {
+        use ic_logger::replica_logger::no_op_logger;
+        use memory_tracker::SigsegvMemoryTracker;
+
+        let wasm_page = WASM_PAGE_SIZE as usize;
+        let page = *PAGE_SIZE;
+        let memory = unsafe { MmapMemory::new(2 * wasm_page, 0) };
+        let ptr = memory.as_ptr() as *mut u8;
+        let tracker = SigsegvMemoryTracker::new(memory.as_ptr(), 2 * wasm_page, no_op_logger())
+            .expect("failed to track memory");
+        let contents = vec![42; page];
+        let last_page = 2 * wasm_page / page - 1;
+        for page_num in &[0, last_page] {
+            let addr = unsafe { ptr.add(page_num * page) };
+            assert!(tracker.handle_sigsegv(|_| None, addr as *mut c_void));
+            assert!(tracker.handle_sigsegv(|_| None, addr as *mut c_void));
+            unsafe { *addr = 7 };
+        }
+
+        let (start, len) = unsafe { decommit_above(ptr, 2 * wasm_page, wasm_page) }.unwrap();
+        tracker.reset_pages(start / page, len / page);
+
+        assert_eq!(tracker.num_accessed_pages(), 1);
+        assert_eq!(tracker.dirty_pages(), vec![ptr as *const c_void]);
+        // The next access of a reset page is tracked like the first one and
+        // initializes the page again.
+        let addr = unsafe { ptr.add(last_page * page) };
+        assert!(tracker.handle_sigsegv(|_| Some(contents.as_slice()), addr as *mut c_void));
+        assert_eq!(unsafe { *addr }, 42);
+        assert_eq!(tracker.num_accessed_pages(), 2);
+        assert_eq!(tracker.num_dirty_pages(), 1);
+    }
+}
@@ -1,3 +1,4 @@
+use super::host_memory::{MmapMemoryCreator, WasmtimeMemoryCreator};
 use super::system_api;
 use ic_interfaces::execution_environment::SubnetAvailableMemory;
 use ic_replicated_state::SystemState;
@@ -9,8 +10,10 @@ use ic_types::{ComputeAllocation, NumBytes, NumInstructions};
 use lazy_static::lazy_static;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use wasmtime::{Config, Engine, Module, Store};
+use wasmtime::{Config, Engine, Limits, MemoryCreator, MemoryType, Module, Store};
+use wasmtime_environ::WASM_PAGE_SIZE;
 
 lazy_static! {
     static ref MAX_SUBNET_AVAILABLE_MEMORY: SubnetAvailableMemory =
@@ -70,3 +73,28 @@ fn test_wasmtime_system_api() {
         .call(&[])
         .expect("call failed");
 }
+
+#[test]
+fn test_wasmtime_memory_creator_reports_total_memory_size() {
+    let last_reported_size = Arc::new(AtomicUsize::new(0));
+    let reported_size = Arc::clone(&last_reported_size);
+    let creator = WasmtimeMemoryCreator::new(MmapMemoryCreator::default())
+        .with_grow_callback(move |total| reported_size.store(total, Ordering::SeqCst));
+    let page_size = WASM_PAGE_SIZE as usize;
+    let reserved_size = Some(16 * WASM_PAGE_SIZE as u64);
+
+    let memory_1 = creator
+        .new_memory(MemoryType::new(Limits::new(1, Some(16))), reserved_size, 0)
+        .unwrap();
+    let _memory_2 = creator
+        .new_memory(MemoryType::new(Limits::new(2, Some(16))), reserved_size, 0)
+        .unwrap();
+    assert_eq!(creator.total_byte_size(), 3 * page_size);
+
+    assert_eq!(memory_1.grow(4), Some(1));
+    assert_eq!(creator.total_byte_size(), 7 * page_size);
+    assert_eq!(last_reported_size.load(Ordering::SeqCst), 7 * page_size);
+
+    drop(memory_1);
+    assert_eq!(creator.total_byte_size(), 2 * page_size);
+}
@@ -1,8 +1,8 @@
 //! The crypto public interface.
 mod keygen;
 
-pub use keygen::KeyManager;
 pub use keygen::Keygen;
+pub use keygen::{KeyDiagnostic, KeyDiagnostics, KeyManager};
 
 mod hash;
 
@@ -1,6 +1,9 @@
 use ic_protobuf::crypto::v1::NodePublicKeys;
-use ic_types::crypto::{CommitteeMemberPublicKey, CryptoError, CryptoResult, KeyId, UserPublicKey};
-use ic_types::RegistryVersion;
+use ic_registry_transport::pb::v1::RegistryMutation;
+use ic_types::crypto::{
+    CommitteeMemberPublicKey, CryptoError, CryptoResult, KeyId, KeyPurpose, UserPublicKey,
+};
+use ic_types::{NodeId, RegistryVersion};
 
 /// Functions to generate key material.
 pub trait Keygen {
@@ -22,16 +25,136 @@ pub trait Keygen {
     ) -> Result<(KeyId, CommitteeMemberPublicKey), CryptoError>;
 }
 
+/// The state of one of the node's public keys with respect to the registry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyDiagnostic {
+    /// Whether the crypto component holds the public key.
+    pub present_locally: bool,
+    /// Whether the registry contains a public key for the node.
+    pub present_in_registry: bool,
+    /// Whether the secret key store contains the secret key corresponding to
+    /// the public key in the registry.
+    pub secret_key_present: bool,
+    /// Whether the key held by the crypto component equals the one in the
+    /// registry, and the corresponding secret key is in the secret key store.
+    /// This implies that all of them are present.
+    pub matching: bool,
+    /// The error that `KeyManager::check_keys_with_registry` reports for this
+    /// key, if the key in the registry is missing or malformed, or its secret
+    /// key is missing from the secret key store.
+    pub error: Option<CryptoError>,
+}
+
+impl KeyDiagnostic {
+    /// Returns true if the key is held locally, but the registry contains no
+    /// key or a different one, or the secret key of the registered key is
+    /// missing from the secret key store.
+    pub fn needs_registration(&self) -> bool {
+        self.present_locally && !self.matching
+    }
+}
+
+/// The state of all of the node's public keys with respect to the registry,
+/// as returned by `KeyManager::diagnose_keys_with_registry`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyDiagnostics {
+    pub node_signing: KeyDiagnostic,
+    pub committee_signing: KeyDiagnostic,
+    pub dkg_dealing_encryption: KeyDiagnostic,
+    pub tls_certificate: KeyDiagnostic,
+}
+
+impl KeyDiagnostics {
+    /// Returns true if all keys match the registry.
+    pub fn all_matching(&self) -> bool {
+        self.node_signing.matching
+            && self.committee_signing.matching
+            && self.dkg_dealing_encryption.matching
+            && self.tls_certificate.matching
+    }
+
+    /// Returns the error that `KeyManager::check_keys_with_registry` reports,
+    /// i.e. the first key error in the order node signing, committee signing,
+    /// DKG dealing encryption, TLS certificate.
+    pub fn first_error(&self) -> Option<&CryptoError> {
+        [
+            &self.node_signing,
+            &self.committee_signing,
+            &self.dkg_dealing_encryption,
+            &self.tls_certificate,
+        ]
+        .iter()
+        .find_map(|diagnostic| diagnostic.error.as_ref())
+    }
+
+    /// Returns the purposes of the node keys that need to be registered, in
+    /// the order node signing, committee signing, DKG dealing encryption.
+    ///
+    /// The TLS certificate has no key purpose and is thus not included, see
+    /// `tls_certificate.needs_registration()` instead.
+    pub fn key_purposes_needing_registration(&self) -> Vec<KeyPurpose> {
+        [
+            (KeyPurpose::NodeSigning, &self.node_signing),
+            (KeyPurpose::CommitteeSigning, &self.committee_signing),
+            (
+                KeyPurpose::DkgDealingEncryption,
+                &self.dkg_dealing_encryption,
+            ),
+        ]
+        .iter()
+        .filter(|(_, diagnostic)| diagnostic.needs_registration())
+        .map(|(key_purpose, _)| *key_purpose)
+        .collect()
+    }
+}
+
 /// Methods for checking and retrieving key material.
 pub trait KeyManager {
     /// Checks whether this crypto component is properly set up, i.e.
     /// whether the registry contains the required public keys,
     /// and whether the crypto component's secret key store
     /// contains the corresponding secret keys.
+    ///
+    /// Returns the first error reported by `diagnose_keys_with_registry`, see
+    /// `KeyDiagnostics::first_error`.
     fn check_keys_with_registry(&self, registry_version: RegistryVersion) -> CryptoResult<()>;
 
+    /// Compares each of this crypto component's node public keys with the
+    /// corresponding key in the registry at `registry_version`.
+    ///
+    /// Unlike `check_keys_with_registry`, this does not stop at the first
+    /// problem, but reports the state of all keys. Returns an error only if
+    /// the registry cannot be queried.
+    fn diagnose_keys_with_registry(
+        &self,
+        registry_version: RegistryVersion,
+    ) -> CryptoResult<KeyDiagnostics>;
+
+    /// Returns the purposes of the node keys that this crypto component holds,
+    /// but that are missing from or differ in the registry at
+    /// `registry_version`, i.e. the keys that need to be (re-)registered.
+    ///
+    /// The TLS certificate is not covered, as it has no key purpose. Use
+    /// `diagnose_keys_with_registry` to also check it.
+    fn keys_needing_registration(
+        &self,
+        registry_version: RegistryVersion,
+    ) -> CryptoResult<Vec<KeyPurpose>>;
+
     /// Returns node public keys that were read when this crypto component was
     /// created. Node public keys stay the same throughout the lifetime of
     /// the component.
     fn node_public_keys(&self) -> NodePublicKeys;
+
+    /// Returns registry mutations that insert this component's node public
+    /// keys for the node with the given `node_id`, i.e. the node signing key,
+    /// the committee signing key, the DKG dealing encryption key, and the TLS
+    /// certificate, each under its respective registry key.
+    ///
+    /// Returns an `InvalidArgument` error if any of these keys is missing
+    /// from the node public keys.
+    fn node_public_keys_as_registry_mutations(
+        &self,
+        node_id: NodeId,
+    ) -> CryptoResult<Vec<RegistryMutation>>;
 }
@@ -68,6 +68,17 @@ pub trait Transport: Send + Sync {
         peer_id: &NodeId,
         flow_tag: FlowTag,
     );
+
+    /// Stop the connection for the given peer and flow tag, without affecting
+    /// the peer's other flows. Messages in the flow's TxQ are sent before the
+    /// connection's tasks exit, and sending on the flow fails until then.
+    /// The flow is then reported down and reconnected.
+    fn stop_connection(
+        &self,
+        client_type: TransportClientType,
+        peer_id: &NodeId,
+        flow_tag: FlowTag,
+    ) -> Result<(), TransportErrorCode>;
 }
 
 #[derive(Debug)]
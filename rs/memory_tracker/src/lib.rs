@@ -111,6 +111,36 @@ impl SigsegvMemoryTracker {
     pub fn num_dirty_pages(&self) -> usize {
         self.dirty_pages.borrow().len()
     }
+
+    // Makes `num_pages` pages starting at `first_page` inaccessible again and
+    // forgets that they were accessed or dirtied, so that their next access
+    // is handled like the first one. This must be called for pages whose
+    // contents are discarded behind the tracker's back, e.g. with
+    // `madvise(MADV_DONTNEED)`.
+    pub fn reset_pages(&self, first_page: PageNum, num_pages: usize) {
+        if num_pages == 0 {
+            return;
+        }
+        let start = self.memory_area.page_addr(first_page) as usize;
+        // Checks that the last page is within the memory area.
+        self.memory_area.page_addr(first_page + num_pages - 1);
+        let end = start + num_pages * *PAGE_SIZE;
+        unsafe {
+            mprotect(
+                start as *mut libc::c_void,
+                num_pages * *PAGE_SIZE,
+                ProtFlags::PROT_NONE,
+            )
+            .unwrap()
+        };
+        let mut accessed_pages = self.accessed_pages.borrow_mut();
+        for page_num in first_page..first_page + num_pages {
+            accessed_pages.set(page_num, false);
+        }
+        self.dirty_pages
+            .borrow_mut()
+            .retain(|&addr| (addr as usize) < start || (addr as usize) >= end);
+    }
 }
 
 // It is not possible to use a logger from within the signal handler. Hence, for
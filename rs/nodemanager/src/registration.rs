@@ -442,11 +442,13 @@ mod tests {
                 flow_tag: 1337,
                 server_port: 23,
                 queue_size: 1,
+                ..Default::default()
             },
             TransportFlowConfig {
                 flow_tag: 1338,
                 server_port: 24,
                 queue_size: 1,
+                ..Default::default()
             },
         ];
 
@@ -9,7 +9,8 @@
 //! [`TransportImpl`](../types/struct.TransportImpl.html).
 
 use crate::types::{
-    ClientState, ConnectionState, FlowState, PeerState, QueueSize, ServerPort, TransportImpl,
+    ClientState, ConnectionState, FlowState, HeartbeatConfig, PeerState, QueueSize, ServerPort,
+    SocketIoConfig, TransportImpl, DEFAULT_MAX_PAYLOAD_LENGTH,
 };
 use crate::utils::{get_flow_ips, get_flow_label, SendQueueImpl};
 use futures::future::{self, Either, FutureExt};
@@ -132,10 +133,21 @@ impl TransportImpl {
 
         // TODO: P2P-514
         let mut queue_size_map = HashMap::new();
+        let mut heartbeat_config_map = HashMap::new();
+        let mut max_payload_length_map = HashMap::new();
+        let mut socket_io_config_map = HashMap::new();
         let flow_ips = get_flow_ips(peer_record)?;
         for flow_config in &self.config.p2p_flows {
             let flow_tag = FlowTag::from(flow_config.flow_tag);
+            let heartbeat_config = HeartbeatConfig::from_flow_config(flow_config)?;
+            let socket_io_config = SocketIoConfig::from_flow_config(flow_config)?;
             queue_size_map.insert(flow_tag, QueueSize::from(flow_config.queue_size));
+            heartbeat_config_map.insert(flow_tag, heartbeat_config);
+            let max_payload_length = flow_config
+                .max_payload_length
+                .unwrap_or(DEFAULT_MAX_PAYLOAD_LENGTH);
+            max_payload_length_map.insert(flow_tag, max_payload_length);
+            socket_io_config_map.insert(flow_tag, socket_io_config);
             if !is_peer_server {
                 let peer_ip = flow_ips
                     .get(&flow_tag)
@@ -152,12 +164,17 @@ impl TransportImpl {
                     flow_label: flow_label.clone(),
                     connection_state: ConnectionState::Listening,
                     abort_handles: Option::None,
+                    stop_signals: Option::None,
+                    stop_requested: false,
                     send_queue: Box::new(SendQueueImpl::new(
                         flow_label,
                         &flow_tag,
                         QueueSize::from(flow_config.queue_size),
                         self.send_queue_metrics.clone(),
                     )),
+                    heartbeat_config,
+                    max_payload_length,
+                    socket_io_config,
                 };
                 self.report_connection_state(&flow_state);
                 peer_state.flow_map.insert(flow_tag, flow_state);
@@ -183,9 +200,24 @@ impl TransportImpl {
             };
 
             let flow_tag = FlowTag::from(flow_endpoint.flow_tag);
-            let queue_size = match queue_size_map.get(&flow_tag) {
-                Some(queue_size) => queue_size,
-                None => {
+            let (queue_size, heartbeat_config, max_payload_length, socket_io_config) = match (
+                queue_size_map.get(&flow_tag),
+                heartbeat_config_map.get(&flow_tag),
+                max_payload_length_map.get(&flow_tag),
+                socket_io_config_map.get(&flow_tag),
+            ) {
+                (
+                    Some(queue_size),
+                    Some(heartbeat_config),
+                    Some(max_payload_length),
+                    Some(socket_io_config),
+                ) => (
+                    queue_size,
+                    heartbeat_config,
+                    max_payload_length,
+                    socket_io_config,
+                ),
+                _ => {
                     error!(
                         self.log,
                         "ControlPlane::start_peer(): TransportConfig NodeRecord mismatch = {:?}",
@@ -221,12 +253,17 @@ impl TransportImpl {
                     server_port,
                 )),
                 abort_handles: Option::None,
+                stop_signals: Option::None,
+                stop_requested: false,
                 send_queue: Box::new(SendQueueImpl::new(
                     flow_label.clone(),
                     &flow_tag,
                     *queue_size,
                     self.send_queue_metrics.clone(),
                 )),
+                heartbeat_config: *heartbeat_config,
+                max_payload_length: *max_payload_length,
+                socket_io_config: *socket_io_config,
             };
             self.report_connection_state(&flow_state);
             peer_state
@@ -245,6 +282,10 @@ impl TransportImpl {
         for canceler in &peer_state.connect_cancelers {
             canceler.store(true, Ordering::SeqCst);
         }
+        // Let the send/receive tasks of the peer's flows exit cleanly
+        for flow_state in peer_state.flow_map.values() {
+            Self::signal_flow_stop(flow_state);
+        }
     }
 
     /// Starts the async task to accept the incoming TcpStreams in server mode.
@@ -508,6 +549,8 @@ impl TransportImpl {
                 send_handle.abort();
                 receive_handle.abort();
             }
+            flow_state.stop_signals = None;
+            flow_state.stop_requested = false;
 
             if !Self::is_peer_server(&self.node_id, &flow_id.peer_id) {
                 // We are the server, wait for the peer to connect
@@ -1047,6 +1090,7 @@ mod tests {
                 flow_tag: FLOW_TAG_1,
                 server_port: PORT_1,
                 queue_size: 10,
+                ..Default::default()
             };
             client_config_1.p2p_flows.push(flow_internal_1);
             let control_plane_1 = create_transport(
@@ -1067,6 +1111,7 @@ mod tests {
                 flow_tag: FLOW_TAG_2,
                 server_port: PORT_2,
                 queue_size: 10,
+                ..Default::default()
             };
             client_config_2.p2p_flows.push(flow_internal_2);
             let control_plane_2 = create_transport(
@@ -12,71 +12,61 @@
 //! the control plane. The data plane itself is composed of two async tasks per
 //! connection: one each for send and receive. The connections established by
 //! the control plane are split into read and write halves and given to these
-//! two tasks.
+//! two tasks. Both tasks can be stopped cleanly via per-flow stop signals,
+//! in which case the send task first writes out the messages that are still
+//! in the send queue, and then passes the flow back to the control plane like
+//! after a disconnect.
 //!
 //! The data plane module implements data plane functionality for
 //! [`TransportImpl`](../types/struct.TransportImpl.html).
 
 use crate::metrics::DataPlaneMetrics;
 use crate::types::{
-    SendQueueReader, TransportHeader, TransportImpl, TRANSPORT_FLAGS_IS_HEARTBEAT,
-    TRANSPORT_FLAGS_SENDER_ERROR, TRANSPORT_HEADER_SIZE,
+    DequeuedMessage, FlowState, HeartbeatConfig, SendQueueReader, SocketIoConfig, TransportHeader,
+    TransportImpl, HEADER_CHECKSUM_VERSION, MAX_SUPPORTED_VERSION, TRANSPORT_FLAGS_IS_HEARTBEAT,
+    TRANSPORT_FLAGS_SENDER_ERROR, TRANSPORT_HEADER_SIZE, TRANSPORT_HEADER_VERSION,
 };
 use ic_crypto_tls_interfaces::{TlsReadHalf, TlsWriteHalf};
 use ic_interfaces::transport::AsyncTransportEventHandler;
-use ic_logger::warn;
+use ic_logger::{info, warn};
 use ic_types::transport::{
-    FlowId, TransportErrorCode, TransportFlowInfo, TransportPayload, TransportStateChange,
+    ConnectionMode, FlowId, TransportErrorCode, TransportFlowInfo, TransportPayload,
+    TransportStateChange,
 };
 
 use futures::future::{AbortHandle, Abortable, Aborted};
+use futures::FutureExt;
 use std::convert::TryInto;
 use std::sync::{Arc, Weak};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Notify;
 use tokio::time::{Duration, Instant};
 
-// DEQUEUE_BYTES is the number of bytes which we will attempt to dequeue and
-// aggregate before sending to the network via write_all(). Tokio currently
-// does not support writev so aggregation is performed manually. This is
-// necessary because we are setting TCP_NODELAY which causes each write to be
-// pushed to the network. Without aggregation, we would have many small writes
-// and thus many small packets. A value of ~800K here works well with a queue
-// size of 1K. Values down to 8K work with queue size >= 4K. Smaller sizes make
-// the system more responsive in clearing the queues at the cost of increased
-// CPU usage. Larger sizes effectively add to the queue size but make the system
-// less responsive to queue clearing. A good compromise size might be 32K with a
-// larger queue size.
-/// The number of bytes which will be attempted to dequeue and aggregate before
-/// sending to the network
-const DEQUEUE_BYTES: usize = 100 * 4 * 1490;
-
-// Payloads are received/collected in units of SOCKET_READ_CHUNK_SIZE
-/// Size of read chunks
-const SOCKET_READ_CHUNK_SIZE: usize = 32 * 1024;
-
-/// Heartbeat send interval (timeout on sender side)
-const TRANSPORT_HEARTBEAT_SEND_INTERVAL_MS: u64 = 200;
-/// Heartbeat wait interval (timeout on receiver side)
-const TRANSPORT_HEARTBEAT_WAIT_INTERVAL_MS: u64 = 5000;
-
 /// Error type for read errors
 #[derive(Debug)]
 enum ReadError {
+    ConnectionClosed,
     SocketReadFailed(std::io::Error),
     SocketReadTimeOut,
+    PayloadTooLarge { declared: u32, max: u32 },
+    UnsupportedVersion(u8),
+    HeaderChecksumMismatch { expected: u16, received: u16 },
+    MessageOnKeepaliveOnlyFlow,
+    DataIdleTimeOut,
 }
 
 /// Implementation for the transport data plane
 impl TransportImpl {
     /// Create header bytes to send with payload.
     fn pack_header(
+        version: u8,
         payload: Option<&TransportPayload>,
         sender_err: bool,
         heartbeat: bool,
     ) -> Vec<u8> {
         let mut result = Vec::<u8>::new();
         let mut header = TransportHeader {
-            version: 0,
+            version,
             flags: 0,
             reserved: 0,
             payload_length: match payload {
@@ -90,6 +80,9 @@ impl TransportImpl {
         if heartbeat {
             header.flags |= TRANSPORT_FLAGS_IS_HEARTBEAT;
         }
+        if version >= HEADER_CHECKSUM_VERSION {
+            header.reserved = Self::header_checksum(&header);
+        }
         result.append(&mut header.version.to_le_bytes().to_vec());
         result.append(&mut header.flags.to_le_bytes().to_vec());
         result.append(&mut header.reserved.to_le_bytes().to_vec());
@@ -120,18 +113,45 @@ impl TransportImpl {
         header
     }
 
+    /// Computes the checksum carried in the `reserved` field of headers with
+    /// a version of at least `HEADER_CHECKSUM_VERSION`: the lower 16 bits of
+    /// the CRC32 over the serialized version, flags and payload length.
+    fn header_checksum(header: &TransportHeader) -> u16 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&[header.version, header.flags]);
+        hasher.update(&header.payload_length.to_le_bytes());
+        hasher.finalize() as u16
+    }
+
     /// Per-flow send task. Reads the requests from the send queue and writes to
-    /// the socket.
-    async fn flow_write_task(
+    /// the socket. A socket write that does not complete within the configured
+    /// write timeout is treated as a disconnect. If a write rate limit is
+    /// configured, the task waits before writing messages (but not heartbeats)
+    /// that exceed it. When `stop` is notified, the
+    /// messages remaining in the send queue are written out before the task
+    /// exits. On keepalive-only flows, the task never reads the send queue and
+    /// only writes a heartbeat every heartbeat send interval.
+    #[allow(clippy::too_many_arguments)]
+    async fn flow_write_task<W: AsyncWrite + Unpin>(
         flow_id: FlowId,
         flow_label: String,
         mut send_queue_reader: Box<dyn SendQueueReader + Send + Sync>,
-        mut writer: Box<TlsWriteHalf>,
+        mut writer: W,
+        header_version: u8,
+        heartbeat_config: HeartbeatConfig,
+        socket_io_config: SocketIoConfig,
         metrics: DataPlaneMetrics,
         state: Weak<TransportImpl>,
+        stop: Arc<Notify>,
     ) {
+        let dequeue_bytes = socket_io_config.dequeue_bytes;
+        let mut rate_limiter = socket_io_config
+            .max_write_bytes_per_sec
+            .map(WriteRateLimiter::new);
         let _updater = MetricsUpdater::new(metrics.clone(), true);
         let flow_tag = flow_id.flow_tag.to_string();
+        let stop_requested = stop.notified();
+        tokio::pin!(stop_requested);
         loop {
             let loop_start_time = Instant::now();
             // If the TransportImpl has been deleted, abort.
@@ -139,82 +159,303 @@ impl TransportImpl {
                 Some(transport) => transport,
                 _ => return,
             };
-            // Wait for the send requests
-            let dequeued = send_queue_reader
-                .dequeue(
-                    DEQUEUE_BYTES,
-                    Duration::from_millis(TRANSPORT_HEARTBEAT_SEND_INTERVAL_MS),
+            // The stop signal is checked between iterations instead of racing
+            // it against the dequeue, as dropping an in-progress dequeue would
+            // lose the messages it already took from the send queue.
+            if stop_requested.as_mut().now_or_never().is_some() {
+                if socket_io_config.connection_mode == ConnectionMode::KeepaliveOnly {
+                    state.on_flow_stopped(flow_id).await;
+                    return;
+                }
+                if let Err(e) = Self::flush_send_queue(
+                    &mut send_queue_reader,
+                    &mut writer,
+                    header_version,
+                    dequeue_bytes,
+                    socket_io_config.write_timeout,
+                    rate_limiter.as_mut(),
+                    &metrics,
+                    &flow_label,
+                    &flow_tag,
                 )
-                .await;
-
-            let mut to_send = Vec::<u8>::new();
-            if dequeued.is_empty() {
-                // There is nothing to send, so issue a heartbeat message
-                to_send.append(&mut Self::pack_header(None, false, true));
-                state
-                    .data_plane_metrics
-                    .heart_beats_sent
-                    .with_label_values(&[&flow_label, &flow_tag])
-                    .inc();
-            } else {
-                for mut msg in dequeued {
-                    to_send.append(&mut Self::pack_header(
-                        Some(&msg.payload),
-                        msg.sender_error,
-                        false,
-                    ));
-                    to_send.append(&mut msg.payload.0);
+                .await
+                {
+                    warn!(
+                        state.log,
+                        "DataPlane::flow_write_task(): failed to flush send queue: flow: {:?}, {:?}",
+                        flow_id,
+                        e,
+                    );
+                    state.on_disconnect(flow_id).await;
+                    return;
                 }
+                state.on_flow_stopped(flow_id).await;
+                return;
             }
+            // Wait for the send requests, or just for the next heartbeat on
+            // keepalive-only flows
+            let (to_send, message_count) = match socket_io_config.connection_mode {
+                ConnectionMode::Data => {
+                    Self::dequeue_and_coalesce(
+                        &mut send_queue_reader,
+                        header_version,
+                        heartbeat_config,
+                        dequeue_bytes,
+                        &metrics,
+                        &flow_label,
+                        &flow_tag,
+                    )
+                    .await
+                }
+                ConnectionMode::KeepaliveOnly => {
+                    tokio::select! {
+                        _ = tokio::time::delay_for(heartbeat_config.send_interval) => (),
+                        _ = &mut stop_requested => {
+                            state.on_flow_stopped(flow_id).await;
+                            return;
+                        }
+                    }
+                    let heartbeat =
+                        Self::pack_heartbeat(header_version, &metrics, &flow_label, &flow_tag);
+                    (heartbeat, 0)
+                }
+            };
+            let is_heartbeat = message_count == 0;
             state
                 .data_plane_metrics
                 .write_task_overhead_time_msec
                 .with_label_values(&[&flow_label, &flow_tag])
                 .observe(loop_start_time.elapsed().as_millis() as f64);
 
+            if !is_heartbeat {
+                if let Some(rate_limiter) = rate_limiter.as_mut() {
+                    rate_limiter.acquire(to_send.len()).await;
+                }
+            }
+
             // Send the payload
             let start_time = Instant::now();
-            if let Err(e) = writer.write_all(&to_send).await {
-                warn!(
-                    state.log,
-                    "DataPlane::flow_write_task(): failed to write payload: flow: {:?}, {:?}",
-                    flow_id,
-                    e,
-                );
-                state.on_disconnect(flow_id).await;
-                return;
+            match tokio::time::timeout(socket_io_config.write_timeout, writer.write_all(&to_send))
+                .await
+            {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => {
+                    warn!(
+                        state.log,
+                        "DataPlane::flow_write_task(): failed to write payload: flow: {:?}, {:?}",
+                        flow_id,
+                        e,
+                    );
+                    state.on_disconnect(flow_id).await;
+                    return;
+                }
+                Err(_) => {
+                    warn!(
+                        state.log,
+                        "DataPlane::flow_write_task(): payload write timed out after {:?}: flow: {:?}",
+                        socket_io_config.write_timeout,
+                        flow_id,
+                    );
+                    state
+                        .data_plane_metrics
+                        .socket_write_timeouts
+                        .with_label_values(&[&flow_label, &flow_tag])
+                        .inc();
+                    state.on_disconnect(flow_id).await;
+                    return;
+                }
             }
             state
                 .data_plane_metrics
                 .socket_write_time_msec
                 .with_label_values(&[&flow_label, &flow_tag])
                 .observe(start_time.elapsed().as_millis() as f64);
-            state
-                .data_plane_metrics
-                .socket_write_bytes
-                .with_label_values(&[&flow_label, &flow_tag])
-                .inc_by(to_send.len() as i64);
-            state
-                .data_plane_metrics
-                .socket_write_size
-                .with_label_values(&[&flow_label, &flow_tag])
+            Self::observe_socket_write(
+                &state.data_plane_metrics,
+                &flow_label,
+                &flow_tag,
+                to_send.len(),
+                message_count,
+            );
+        }
+    }
+
+    /// Counts the bytes of a socket write that carried `message_count`
+    /// messages, or a heartbeat if `message_count` is zero.
+    fn observe_socket_write(
+        metrics: &DataPlaneMetrics,
+        flow_label: &str,
+        flow_tag: &str,
+        bytes_written: usize,
+        message_count: usize,
+    ) {
+        metrics
+            .socket_write_bytes
+            .with_label_values(&[flow_label, flow_tag])
+            .inc_by(bytes_written as i64);
+        // A heartbeat is a single header without payload
+        let header_bytes = TRANSPORT_HEADER_SIZE * message_count.max(1);
+        metrics
+            .socket_write_header_bytes
+            .with_label_values(&[flow_label, flow_tag])
+            .inc_by(header_bytes as i64);
+        metrics
+            .socket_write_payload_bytes
+            .with_label_values(&[flow_label, flow_tag])
+            .inc_by((bytes_written - header_bytes) as i64);
+        metrics
+            .socket_write_size
+            .with_label_values(&[flow_label, flow_tag])
+            .observe(bytes_written as f64);
+    }
+
+    /// Dequeues the next batch of messages from the send queue and aggregates
+    /// them into a single buffer to be written to the socket. If there is
+    /// nothing to send within the heartbeat send interval, the buffer contains
+    /// a heartbeat message. Also returns the number of messages in the
+    /// buffer, which is zero for a heartbeat.
+    async fn dequeue_and_coalesce(
+        send_queue_reader: &mut Box<dyn SendQueueReader + Send + Sync>,
+        header_version: u8,
+        heartbeat_config: HeartbeatConfig,
+        dequeue_bytes: usize,
+        metrics: &DataPlaneMetrics,
+        flow_label: &str,
+        flow_tag: &str,
+    ) -> (Vec<u8>, usize) {
+        let dequeued = send_queue_reader
+            .dequeue(dequeue_bytes, heartbeat_config.send_interval)
+            .await;
+        metrics
+            .send_queue_depth
+            .with_label_values(&[flow_label, flow_tag])
+            .set(send_queue_reader.len() as i64);
+
+        let to_send;
+        let message_count = dequeued.len();
+        if message_count == 0 {
+            // There is nothing to send, so issue a heartbeat message
+            to_send = Self::pack_heartbeat(header_version, metrics, flow_label, flow_tag);
+        } else {
+            to_send = Self::pack_messages(header_version, dequeued);
+            metrics
+                .socket_write_coalesced_messages
+                .with_label_values(&[flow_label, flow_tag])
+                .observe(message_count as f64);
+            metrics
+                .socket_write_coalesced_bytes
+                .with_label_values(&[flow_label, flow_tag])
                 .observe(to_send.len() as f64);
         }
+        (to_send, message_count)
+    }
+
+    /// Creates a heartbeat message and counts it as sent.
+    fn pack_heartbeat(
+        header_version: u8,
+        metrics: &DataPlaneMetrics,
+        flow_label: &str,
+        flow_tag: &str,
+    ) -> Vec<u8> {
+        metrics
+            .heart_beats_sent
+            .with_label_values(&[flow_label, flow_tag])
+            .inc();
+        Self::pack_header(header_version, None, false, true)
+    }
+
+    /// Writes the messages that are currently in the send queue to the
+    /// socket, without waiting for further messages, and flushes the socket.
+    /// As on the regular write path, the writes are subject to the rate limiter
+    /// and each socket operation to `write_timeout`, so a peer that stopped
+    /// reading cannot block the stop forever. A timeout is reported as an
+    /// error of kind `TimedOut`.
+    #[allow(clippy::too_many_arguments)]
+    async fn flush_send_queue<W: AsyncWrite + Unpin>(
+        send_queue_reader: &mut Box<dyn SendQueueReader + Send + Sync>,
+        writer: &mut W,
+        header_version: u8,
+        dequeue_bytes: usize,
+        write_timeout: Duration,
+        mut rate_limiter: Option<&mut WriteRateLimiter>,
+        metrics: &DataPlaneMetrics,
+        flow_label: &str,
+        flow_tag: &str,
+    ) -> std::io::Result<()> {
+        let timed_out = || {
+            metrics
+                .socket_write_timeouts
+                .with_label_values(&[flow_label, flow_tag])
+                .inc();
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("write timed out after {:?}", write_timeout),
+            )
+        };
+        loop {
+            let dequeued = send_queue_reader
+                .dequeue(dequeue_bytes, Duration::from_millis(0))
+                .await;
+            if dequeued.is_empty() {
+                return tokio::time::timeout(write_timeout, writer.flush())
+                    .await
+                    .map_err(|_| timed_out())?;
+            }
+            let message_count = dequeued.len();
+            let to_send = Self::pack_messages(header_version, dequeued);
+            if let Some(rate_limiter) = rate_limiter.as_mut() {
+                rate_limiter.acquire(to_send.len()).await;
+            }
+            tokio::time::timeout(write_timeout, writer.write_all(&to_send))
+                .await
+                .map_err(|_| timed_out())??;
+            Self::observe_socket_write(metrics, flow_label, flow_tag, to_send.len(), message_count);
+        }
+    }
+
+    /// Packs the dequeued messages, each preceded by its header, into a single
+    /// buffer.
+    fn pack_messages(header_version: u8, dequeued: Vec<DequeuedMessage>) -> Vec<u8> {
+        let mut packed = Vec::<u8>::new();
+        for mut msg in dequeued {
+            packed.append(&mut Self::pack_header(
+                header_version,
+                Some(&msg.payload),
+                msg.sender_error,
+                false,
+            ));
+            packed.append(&mut msg.payload.0);
+        }
+        packed
     }
 
     /// Per-flow receive task. Reads the messages from the socket and passes to
-    /// the client.
-    async fn flow_read_task(
+    /// the client. When `stop` is notified, the task exits without reporting a
+    /// disconnect. On keepalive-only flows, receiving anything but heartbeats
+    /// is treated as a disconnect. If the event handler does not accept a
+    /// message within the configured timeout, this is reported, and the task
+    /// either keeps waiting or treats it as a disconnect. If a data idle
+    /// timeout is configured, not receiving any message other than heartbeats
+    /// for that long is treated as a disconnect.
+    #[allow(clippy::too_many_arguments)]
+    async fn flow_read_task<R: AsyncRead + Unpin>(
         flow_id: FlowId,
         flow_label: String,
         event_handler: Arc<dyn AsyncTransportEventHandler>,
-        mut reader: Box<TlsReadHalf>,
+        mut reader: R,
+        heartbeat_config: HeartbeatConfig,
+        max_payload_length: u32,
+        socket_io_config: SocketIoConfig,
         metrics: DataPlaneMetrics,
         state: Weak<TransportImpl>,
+        stop: Arc<Notify>,
     ) {
-        let heartbeat_timeout = Duration::from_millis(TRANSPORT_HEARTBEAT_WAIT_INTERVAL_MS);
+        let heartbeat_timeout = heartbeat_config.wait_interval;
         let _updater = MetricsUpdater::new(metrics.clone(), false);
         let flow_tag = flow_id.flow_tag.to_string();
+        let stop_requested = stop.notified();
+        tokio::pin!(stop_requested);
+        let mut last_data_received = Instant::now();
         loop {
             // If the TransportImpl has been deleted, abort.
             let state = match state.upgrade() {
@@ -222,8 +463,52 @@ impl TransportImpl {
                 _ => return,
             };
 
-            // Read the next message from the socket
-            let ret = Self::read_one_message(&mut reader, heartbeat_timeout).await;
+            // Read the next message from the socket, unless the task is stopped
+            let read_message = Self::read_one_message(
+                &mut reader,
+                heartbeat_timeout,
+                max_payload_length,
+                socket_io_config.read_chunk_size,
+            );
+            // Heartbeats do not count as data, so this deadline is only moved
+            // when a message is received
+            let data_idle = async {
+                match socket_io_config.data_idle_timeout {
+                    Some(timeout) => tokio::time::delay_until(last_data_received + timeout).await,
+                    None => futures::future::pending::<()>().await,
+                }
+            };
+            let ret = tokio::select! {
+                ret = read_message => ret,
+                _ = data_idle => Err(ReadError::DataIdleTimeOut),
+                _ = &mut stop_requested => return,
+            };
+            let ret = ret.and_then(|(header, payload)| {
+                if socket_io_config.connection_mode == ConnectionMode::KeepaliveOnly
+                    && header.flags & TRANSPORT_FLAGS_IS_HEARTBEAT == 0
+                {
+                    return Err(ReadError::MessageOnKeepaliveOnlyFlow);
+                }
+                Ok((header, payload))
+            });
+            if let Err(ReadError::ConnectionClosed) = ret {
+                info!(
+                    state.log,
+                    "DataPlane::flow_read_task(): connection closed by peer: flow: {:?}", flow_id,
+                );
+                state.on_disconnect(flow_id).await;
+                return;
+            }
+            if let Err(ReadError::DataIdleTimeOut) = ret {
+                info!(
+                    state.log,
+                    "DataPlane::flow_read_task(): no data received within {:?}: flow: {:?}",
+                    socket_io_config.data_idle_timeout,
+                    flow_id,
+                );
+                state.on_disconnect(flow_id).await;
+                return;
+            }
             if ret.is_err() {
                 warn!(
                     state.log,
@@ -245,6 +530,15 @@ impl TransportImpl {
 
             // Process the received message
             let (header, payload) = ret.unwrap();
+            let payload_length = payload.as_ref().map_or(0, |payload| payload.0.len());
+            metrics
+                .socket_read_bytes
+                .with_label_values(&[&flow_label, &flow_tag])
+                .inc_by((TRANSPORT_HEADER_SIZE + payload_length) as i64);
+            metrics
+                .socket_read_header_bytes
+                .with_label_values(&[&flow_label, &flow_tag])
+                .inc_by(TRANSPORT_HEADER_SIZE as i64);
             if header.flags & TRANSPORT_FLAGS_IS_HEARTBEAT != 0 {
                 // It's an empty heartbeat message -- do nothing
                 metrics
@@ -254,6 +548,8 @@ impl TransportImpl {
                 continue;
             }
 
+            last_data_received = Instant::now();
+
             // Pass up sender indicated error
             if header.flags & TRANSPORT_FLAGS_SENDER_ERROR != 0 {
                 event_handler
@@ -270,11 +566,33 @@ impl TransportImpl {
             // shutdowns.
             let payload = payload.unwrap();
             metrics
-                .socket_read_bytes
+                .socket_read_payload_bytes
                 .with_label_values(&[&flow_label, &flow_tag])
                 .inc_by(payload.0.len() as i64);
             let start_time = Instant::now();
-            let _ = event_handler.send_message(flow_id, payload).await;
+            let send_message = event_handler.send_message(flow_id, payload);
+            tokio::pin!(send_message);
+            let timeout = socket_io_config.event_handler_timeout;
+            if tokio::time::timeout(timeout, &mut send_message)
+                .await
+                .is_err()
+            {
+                metrics
+                    .event_handler_timeouts
+                    .with_label_values(&[&flow_label, &flow_tag])
+                    .inc();
+                warn!(
+                    state.log,
+                    "DataPlane::flow_read_task(): event handler did not accept message within {:?}: flow: {:?}",
+                    timeout,
+                    flow_id,
+                );
+                if socket_io_config.disconnect_on_event_handler_timeout {
+                    state.on_disconnect(flow_id).await;
+                    return;
+                }
+                let _ = send_message.await;
+            }
             metrics
                 .client_send_time_msec
                 .with_label_values(&[&flow_label, &flow_tag])
@@ -284,26 +602,50 @@ impl TransportImpl {
 
     /// Reads and returns the next <message hdr, message payload> from the
     /// socket. The timeout is for each socket read (header, payload chunks)
-    /// and not the full message.
-    async fn read_one_message(
-        reader: &mut Box<TlsReadHalf>,
+    /// and not the full message. Messages with a payload longer than
+    /// `max_payload_length` are rejected before the payload is read. The
+    /// payload is read in chunks of at most `read_chunk_size` bytes, which
+    /// must be positive.
+    async fn read_one_message<R: AsyncRead + Unpin>(
+        reader: &mut R,
         timeout: Duration,
+        max_payload_length: u32,
+        read_chunk_size: usize,
     ) -> Result<(TransportHeader, Option<TransportPayload>), ReadError> {
+        assert!(read_chunk_size > 0);
         // Read the hdr
         let mut header_buffer = vec![0u8; TRANSPORT_HEADER_SIZE];
         Self::read_from_socket(reader, &mut header_buffer, timeout).await?;
 
         let header = Self::unpack_header(header_buffer);
+        if header.version > MAX_SUPPORTED_VERSION {
+            return Err(ReadError::UnsupportedVersion(header.version));
+        }
+        if header.version >= HEADER_CHECKSUM_VERSION {
+            let expected = Self::header_checksum(&header);
+            if header.reserved != expected {
+                return Err(ReadError::HeaderChecksumMismatch {
+                    expected,
+                    received: header.reserved,
+                });
+            }
+        }
         if header.flags & TRANSPORT_FLAGS_IS_HEARTBEAT != 0 {
             return Ok((header, None));
         }
+        if header.payload_length > max_payload_length {
+            return Err(ReadError::PayloadTooLarge {
+                declared: header.payload_length,
+                max: max_payload_length,
+            });
+        }
 
         // Read the payload in chunks
         let mut payload_buffer = vec![0u8; header.payload_length as usize];
         let mut remaining = header.payload_length as usize;
         let mut cur_offset = 0;
         while remaining > 0 {
-            let cur_chunk_size = std::cmp::min(remaining, SOCKET_READ_CHUNK_SIZE);
+            let cur_chunk_size = std::cmp::min(remaining, read_chunk_size);
             assert!(cur_chunk_size <= remaining);
             Self::read_from_socket(
                 reader,
@@ -320,9 +662,11 @@ impl TransportImpl {
         Ok((header, Some(payload)))
     }
 
-    /// Reads the requested bytes from the socket with a timeout
-    async fn read_from_socket(
-        reader: &mut Box<TlsReadHalf>,
+    /// Reads the requested bytes from the socket with a timeout. Reaching the
+    /// end of the stream before all bytes are read means that the peer closed
+    /// the connection.
+    async fn read_from_socket<R: AsyncRead + Unpin>(
+        reader: &mut R,
         buf: &mut [u8],
         timeout: Duration,
     ) -> Result<(), ReadError> {
@@ -334,6 +678,9 @@ impl TransportImpl {
 
         match ret.unwrap() {
             Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                Err(ReadError::ConnectionClosed)
+            }
             Err(e) => Err(ReadError::SocketReadFailed(e)),
         }
     }
@@ -346,6 +693,41 @@ impl TransportImpl {
                 "DataPlane::on_disconnect(): retry_connection error {:?}: flow: {:?}", flow_id, e
             );
         }
+        self.report_flow_down(flow_id).await;
+    }
+
+    /// Handle the exit of the send task of a flow stopped by `stop_flow()`.
+    ///
+    /// The flow is then handled like after a peer disconnect: it is moved to
+    /// the disconnected state, a reconnect is scheduled and the client is
+    /// notified that the flow is down. Flows of peers that were removed in
+    /// the meantime are not reported.
+    pub(crate) async fn on_flow_stopped(&self, flow_id: FlowId) {
+        {
+            let mut client_map = self.client_map.write().unwrap();
+            let flow_state = match client_map
+                .get_mut(&flow_id.client_type)
+                .and_then(|client_state| client_state.peer_map.get_mut(&flow_id.peer_id))
+                .and_then(|peer_state| peer_state.flow_map.get_mut(&flow_id.flow_tag))
+            {
+                Some(flow_state) => flow_state,
+                None => return,
+            };
+            // The tasks exit on their own and must not be aborted by
+            // `retry_connection()`, as the send task is still running this.
+            flow_state.abort_handles = None;
+        }
+        if let Err(e) = self.retry_connection(&flow_id) {
+            warn!(
+                self.log,
+                "DataPlane::on_flow_stopped(): retry_connection error {:?}: flow: {:?}", flow_id, e
+            );
+        }
+        self.report_flow_down(flow_id).await;
+    }
+
+    /// Notifies the client that the given flow is down.
+    async fn report_flow_down(&self, flow_id: FlowId) {
         let event_handler = {
             let mut cl_map = self.client_map.write().unwrap();
             let client_state = match cl_map.get_mut(&flow_id.client_type) {
@@ -362,6 +744,37 @@ impl TransportImpl {
             .await;
     }
 
+    /// Stops the send/receive tasks of the given flow cleanly. Pending messages
+    /// in the flow's send queue are written to the socket before the send task
+    /// exits. Until then, sending on the flow fails with `FlowStopped`. Once
+    /// the send task has exited, the flow is reconnected, see
+    /// `on_flow_stopped()`. The other flows are not affected.
+    pub fn stop_flow(&self, flow_id: &FlowId) -> Result<(), TransportErrorCode> {
+        let mut client_map = self.client_map.write().unwrap();
+        let flow_state = client_map
+            .get_mut(&flow_id.client_type)
+            .ok_or(TransportErrorCode::TransportClientNotFound)?
+            .peer_map
+            .get_mut(&flow_id.peer_id)
+            .ok_or(TransportErrorCode::PeerNotFound)?
+            .flow_map
+            .get_mut(&flow_id.flow_tag)
+            .ok_or(TransportErrorCode::FlowNotFound)?;
+        if flow_state.stop_signals.is_some() {
+            flow_state.stop_requested = true;
+        }
+        Self::signal_flow_stop(flow_state);
+        Ok(())
+    }
+
+    /// Notifies the send/receive tasks of the flow, if any, to exit.
+    pub(crate) fn signal_flow_stop(flow_state: &FlowState) {
+        if let Some((send_stop, receive_stop)) = &flow_state.stop_signals {
+            send_stop.notify();
+            receive_stop.notify();
+        }
+    }
+
     /// Handle connection setup. Starts flow read and write tasks.
     fn on_connect_setup(
         &self,
@@ -385,20 +798,31 @@ impl TransportImpl {
             None => return Err(TransportErrorCode::FlowNotFound),
         };
 
+        let send_stop = Arc::new(Notify::new());
+        let receive_stop = Arc::new(Notify::new());
+
         // Spawn write task
         let flow_id_cl = flow_state.flow_id;
         let flow_label_cl = flow_state.flow_label.clone();
         let send_queue_reader = flow_state.send_queue.get_reader();
+        let heartbeat_config = flow_state.heartbeat_config;
+        let max_payload_length = flow_state.max_payload_length;
+        let socket_io_config = flow_state.socket_io_config;
         let metrics_cl = self.data_plane_metrics.clone();
         let weak_self = self.weak_self.read().unwrap().clone();
+        let send_stop_cl = send_stop.clone();
         let send_task = async move {
             Self::flow_write_task(
                 flow_id_cl,
                 flow_label_cl,
                 send_queue_reader,
                 writer,
+                TRANSPORT_HEADER_VERSION,
+                heartbeat_config,
+                socket_io_config,
                 metrics_cl,
                 weak_self,
+                send_stop_cl,
             )
             .await;
         };
@@ -408,14 +832,19 @@ impl TransportImpl {
         let event_handler_cl = event_handler.clone();
         let metrics_cl = self.data_plane_metrics.clone();
         let weak_self = self.weak_self.read().unwrap().clone();
+        let receive_stop_cl = receive_stop.clone();
         let receive_task = async move {
             Self::flow_read_task(
                 flow_id_cl,
                 flow_label_cl,
                 event_handler_cl,
                 reader,
+                heartbeat_config,
+                max_payload_length,
+                socket_io_config,
                 metrics_cl,
                 weak_self,
+                receive_stop_cl,
             )
             .await;
         };
@@ -446,6 +875,7 @@ impl TransportImpl {
         });
 
         flow_state.abort_handles = Some((send_abort_handle, receive_abort_handle));
+        flow_state.stop_signals = Some((send_stop, receive_stop));
         Ok(event_handler)
     }
 
@@ -467,6 +897,47 @@ impl TransportImpl {
     }
 }
 
+/// Token bucket that limits the rate at which a write task writes to its
+/// socket.
+///
+/// The bucket holds at most one second worth of bytes and starts empty, so a
+/// flow cannot exceed the limit by reconnecting: even the first write waits
+/// for its tokens to be replenished. A write larger than the available tokens
+/// is let through after waiting for the missing tokens to be replenished.
+struct WriteRateLimiter {
+    bytes_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl WriteRateLimiter {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec as f64,
+            tokens: 0.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes `bytes` tokens from the bucket, waiting until enough tokens are
+    /// available.
+    async fn acquire(&mut self, bytes: usize) {
+        self.refill();
+        self.tokens -= bytes as f64;
+        if self.tokens < 0.0 {
+            tokio::time::delay_for(Duration::from_secs_f64(-self.tokens / self.bytes_per_sec))
+                .await;
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_sec).min(self.bytes_per_sec);
+        self.last_refill = now;
+    }
+}
+
 /// Wrapper to update the metrics on destruction. This is needed as the async
 /// tasks can get cancelled, and the metrics may not be updated on exit
 struct MetricsUpdater {
@@ -498,3 +969,1436 @@ impl Drop for MetricsUpdater {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        ClientState, ConnectionState, PeerState, QueueSize, DEFAULT_DEQUEUE_BYTES,
+        DEFAULT_MAX_PAYLOAD_LENGTH, DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        DEFAULT_SOCKET_WRITE_TIMEOUT_MS,
+    };
+    use crate::utils::SendQueueImpl;
+    use async_trait::async_trait;
+    use ic_crypto::utils::TempCryptoComponent;
+    use ic_interfaces::transport::{SendError, Transport};
+    use ic_logger::replica_logger::no_op_logger;
+    use ic_metrics::MetricsRegistry;
+    use ic_registry_client::fake::FakeRegistryClient;
+    use ic_registry_common::proto_registry_data_provider::ProtoRegistryDataProvider;
+    use ic_test_utilities::types::ids::NODE_1;
+    use ic_types::transport::{FlowTag, TransportClientType, TransportConfig, TransportFlowConfig};
+    use ic_types::RegistryVersion;
+    use std::collections::HashMap;
+    use std::pin::Pin;
+    use std::sync::Mutex;
+    use std::task::{Context, Poll};
+    use tokio::task::JoinHandle;
+
+    /// A reader that never returns any data
+    struct PendingReader;
+
+    impl AsyncRead for PendingReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Poll::Pending
+        }
+    }
+
+    /// A reader that fails every read
+    struct FailingReader;
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Poll::Ready(Err(std::io::Error::from(
+                std::io::ErrorKind::ConnectionReset,
+            )))
+        }
+    }
+
+    /// A writer that never accepts any data
+    struct PendingWriter;
+
+    impl AsyncWrite for PendingWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Poll::Pending
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Pending
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Pending
+        }
+    }
+
+    /// A send queue reader that returns a fixed number of messages
+    struct FixedSendQueueReader {
+        message_count: usize,
+    }
+
+    #[async_trait]
+    impl SendQueueReader for FixedSendQueueReader {
+        async fn dequeue(
+            &mut self,
+            _bytes_limit: usize,
+            _timeout: Duration,
+        ) -> Vec<DequeuedMessage> {
+            (0..self.message_count)
+                .map(|_| DequeuedMessage {
+                    payload: TransportPayload(vec![0u8; 100]),
+                    sender_error: false,
+                })
+                .collect()
+        }
+
+        fn len(&self) -> usize {
+            0
+        }
+    }
+
+    /// A send queue reader that holds a backlog of messages and returns at
+    /// most `batch_size` of them per dequeue
+    struct BacklogSendQueueReader {
+        backlog: usize,
+        batch_size: usize,
+    }
+
+    #[async_trait]
+    impl SendQueueReader for BacklogSendQueueReader {
+        async fn dequeue(
+            &mut self,
+            _bytes_limit: usize,
+            _timeout: Duration,
+        ) -> Vec<DequeuedMessage> {
+            let count = self.backlog.min(self.batch_size);
+            self.backlog -= count;
+            (0..count)
+                .map(|_| DequeuedMessage {
+                    payload: TransportPayload(vec![0u8; 100]),
+                    sender_error: false,
+                })
+                .collect()
+        }
+
+        fn len(&self) -> usize {
+            self.backlog
+        }
+    }
+
+    /// A send queue reader that returns the given messages once, and nothing
+    /// after that
+    struct OnceSendQueueReader {
+        messages: Vec<DequeuedMessage>,
+    }
+
+    #[async_trait]
+    impl SendQueueReader for OnceSendQueueReader {
+        async fn dequeue(
+            &mut self,
+            _bytes_limit: usize,
+            timeout: Duration,
+        ) -> Vec<DequeuedMessage> {
+            if self.messages.is_empty() {
+                tokio::time::delay_for(timeout).await;
+            }
+            std::mem::take(&mut self.messages)
+        }
+
+        fn len(&self) -> usize {
+            self.messages.len()
+        }
+    }
+
+    /// A writer that appends all written bytes to a shared buffer
+    #[derive(Clone, Default)]
+    struct SharedBufferWriter {
+        buffer: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl AsyncWrite for SharedBufferWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.buffer.lock().unwrap().extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    struct NoOpEventHandler;
+
+    #[async_trait]
+    impl AsyncTransportEventHandler for NoOpEventHandler {
+        async fn send_message(
+            &self,
+            _flow: FlowId,
+            _message: TransportPayload,
+        ) -> Result<(), SendError> {
+            Ok(())
+        }
+
+        async fn state_changed(&self, _state_change: TransportStateChange) {}
+
+        async fn error(&self, _flow: FlowId, _error: TransportErrorCode) {}
+    }
+
+    /// Records the reported state changes.
+    #[derive(Default)]
+    struct RecordingEventHandler {
+        state_changes: Mutex<Vec<TransportStateChange>>,
+    }
+
+    #[async_trait]
+    impl AsyncTransportEventHandler for RecordingEventHandler {
+        async fn send_message(
+            &self,
+            _flow: FlowId,
+            _message: TransportPayload,
+        ) -> Result<(), SendError> {
+            Ok(())
+        }
+
+        async fn state_changed(&self, state_change: TransportStateChange) {
+            self.state_changes.lock().unwrap().push(state_change);
+        }
+
+        async fn error(&self, _flow: FlowId, _error: TransportErrorCode) {}
+    }
+
+    /// Accepts messages only after a fixed delay.
+    struct SlowEventHandler {
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl AsyncTransportEventHandler for SlowEventHandler {
+        async fn send_message(
+            &self,
+            _flow: FlowId,
+            _message: TransportPayload,
+        ) -> Result<(), SendError> {
+            tokio::time::delay_for(self.delay).await;
+            Ok(())
+        }
+
+        async fn state_changed(&self, _state_change: TransportStateChange) {}
+
+        async fn error(&self, _flow: FlowId, _error: TransportErrorCode) {}
+    }
+
+    fn transport() -> Arc<TransportImpl> {
+        let data_provider = Arc::new(ProtoRegistryDataProvider::new());
+        let registry = Arc::new(FakeRegistryClient::new(data_provider));
+        TransportImpl::new(
+            NODE_1,
+            TransportConfig {
+                node_ip: "0.0.0.0".to_string(),
+                p2p_flows: Vec::new(),
+            },
+            RegistryVersion::from(1),
+            MetricsRegistry::new(),
+            Arc::new(TempCryptoComponent::new(registry, NODE_1)),
+            tokio::runtime::Handle::current(),
+            no_op_logger(),
+        )
+    }
+
+    fn default_socket_io_config() -> SocketIoConfig {
+        SocketIoConfig::new(
+            DEFAULT_DEQUEUE_BYTES,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+            Duration::from_millis(DEFAULT_SOCKET_WRITE_TIMEOUT_MS),
+        )
+        .unwrap()
+    }
+
+    fn flow_id(flow_tag: u32) -> FlowId {
+        FlowId {
+            client_type: TransportClientType::P2P,
+            peer_id: NODE_1,
+            flow_tag: FlowTag::from(flow_tag),
+        }
+    }
+
+    fn spawn_read_task_on_idle_socket(
+        transport: &Arc<TransportImpl>,
+        flow_tag: u32,
+    ) -> (Arc<Notify>, JoinHandle<()>) {
+        spawn_read_task(transport, flow_tag, PendingReader, ConnectionMode::Data)
+    }
+
+    fn spawn_read_task<R: AsyncRead + Unpin + Send + 'static>(
+        transport: &Arc<TransportImpl>,
+        flow_tag: u32,
+        reader: R,
+        connection_mode: ConnectionMode,
+    ) -> (Arc<Notify>, JoinHandle<()>) {
+        spawn_read_task_with_event_handler(
+            transport,
+            flow_tag,
+            reader,
+            default_socket_io_config().with_connection_mode(connection_mode),
+            Arc::new(NoOpEventHandler),
+        )
+    }
+
+    fn spawn_read_task_with_event_handler<R: AsyncRead + Unpin + Send + 'static>(
+        transport: &Arc<TransportImpl>,
+        flow_tag: u32,
+        reader: R,
+        socket_io_config: SocketIoConfig,
+        event_handler: Arc<dyn AsyncTransportEventHandler>,
+    ) -> (Arc<Notify>, JoinHandle<()>) {
+        let stop = Arc::new(Notify::new());
+        let heartbeat_config =
+            HeartbeatConfig::new(Duration::from_secs(30), Duration::from_secs(60)).unwrap();
+        let task = tokio::spawn(TransportImpl::flow_read_task(
+            flow_id(flow_tag),
+            "flow_label".to_string(),
+            event_handler,
+            reader,
+            heartbeat_config,
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            socket_io_config,
+            transport.data_plane_metrics.clone(),
+            Arc::downgrade(transport),
+            stop.clone(),
+        ));
+        (stop, task)
+    }
+
+    #[tokio::test]
+    async fn should_stop_read_task_of_stopped_flow_only() {
+        let transport = transport();
+        let (stop_1, task_1) = spawn_read_task_on_idle_socket(&transport, 1);
+        let (stop_2, mut task_2) = spawn_read_task_on_idle_socket(&transport, 2);
+
+        stop_1.notify();
+
+        tokio::time::timeout(Duration::from_secs(10), task_1)
+            .await
+            .expect("stopped read task did not exit")
+            .unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), &mut task_2)
+                .await
+                .is_err(),
+            "read task of other flow exited"
+        );
+
+        stop_2.notify();
+        tokio::time::timeout(Duration::from_secs(10), task_2)
+            .await
+            .expect("stopped read task did not exit")
+            .unwrap();
+    }
+
+    /// Registers a client with a single connected flow, whose tasks are
+    /// represented by the returned stop signals.
+    fn add_connected_flow(
+        transport: &Arc<TransportImpl>,
+        flow_id: FlowId,
+        event_handler: Arc<dyn AsyncTransportEventHandler>,
+    ) -> (Arc<Notify>, Arc<Notify>) {
+        let flow_label = "flow_label".to_string();
+        let stop_signals = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        let flow_state = FlowState {
+            flow_id,
+            flow_tag_label: flow_id.flow_tag.to_string(),
+            flow_label: flow_label.clone(),
+            connection_state: ConnectionState::Connected("127.0.0.1:4100".parse().unwrap()),
+            abort_handles: None,
+            stop_signals: Some(stop_signals.clone()),
+            stop_requested: false,
+            send_queue: Box::new(SendQueueImpl::new(
+                flow_label,
+                &flow_id.flow_tag,
+                QueueSize::from(16),
+                transport.send_queue_metrics.clone(),
+            )),
+            heartbeat_config: HeartbeatConfig::new(
+                Duration::from_secs(30),
+                Duration::from_secs(60),
+            )
+            .unwrap(),
+            max_payload_length: DEFAULT_MAX_PAYLOAD_LENGTH,
+            socket_io_config: default_socket_io_config(),
+        };
+        let mut peer_state = PeerState {
+            flow_map: HashMap::new(),
+            connect_cancelers: Vec::new(),
+        };
+        peer_state.flow_map.insert(flow_id.flow_tag, flow_state);
+        let mut client_state = ClientState {
+            accept_ports: HashMap::new(),
+            accept_cancelers: Vec::new(),
+            peer_map: HashMap::new(),
+            event_handler,
+        };
+        client_state.peer_map.insert(flow_id.peer_id, peer_state);
+        transport
+            .client_map
+            .write()
+            .unwrap()
+            .insert(flow_id.client_type, client_state);
+        stop_signals
+    }
+
+    #[tokio::test]
+    async fn should_reject_send_on_stopped_flow_until_tasks_exit() {
+        let transport = transport();
+        let event_handler = Arc::new(RecordingEventHandler::default());
+        let flow_id = flow_id(1);
+        add_connected_flow(&transport, flow_id, event_handler.clone());
+        let send = || {
+            transport.send(
+                flow_id.client_type,
+                &flow_id.peer_id,
+                flow_id.flow_tag,
+                TransportPayload(vec![1, 2, 3]),
+            )
+        };
+        assert_eq!(send(), Ok(()));
+
+        transport
+            .stop_connection(flow_id.client_type, &flow_id.peer_id, flow_id.flow_tag)
+            .unwrap();
+
+        assert_eq!(send(), Err(TransportErrorCode::FlowStopped));
+        assert!(event_handler.state_changes.lock().unwrap().is_empty());
+
+        transport.on_flow_stopped(flow_id).await;
+
+        {
+            let client_map = transport.client_map.read().unwrap();
+            let flow_state = &client_map[&flow_id.client_type].peer_map[&flow_id.peer_id].flow_map
+                [&flow_id.flow_tag];
+            assert!(flow_state.stop_signals.is_none());
+            assert!(flow_state.abort_handles.is_none());
+            assert!(!flow_state.stop_requested);
+            assert!(!matches!(
+                flow_state.connection_state,
+                ConnectionState::Connected(_)
+            ));
+        }
+        assert_eq!(
+            *event_handler.state_changes.lock().unwrap(),
+            vec![TransportStateChange::PeerFlowDown(TransportFlowInfo {
+                peer_id: flow_id.peer_id,
+                flow_tag: flow_id.flow_tag,
+            })]
+        );
+        // Messages sent while the flow is down are queued for the reconnect
+        assert_eq!(send(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn should_disconnect_keepalive_only_flow_on_message() {
+        let transport = transport();
+        let payload = TransportPayload(vec![1, 2, 3]);
+        let mut message =
+            TransportImpl::pack_header(TRANSPORT_HEADER_VERSION, Some(&payload), false, false);
+        message.extend_from_slice(&payload.0);
+        let reader = std::io::Cursor::new(message).chain(PendingReader);
+
+        let (_stop, task) = spawn_read_task(&transport, 1, reader, ConnectionMode::KeepaliveOnly);
+
+        tokio::time::timeout(Duration::from_secs(10), task)
+            .await
+            .expect("read task did not exit after receiving a message")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_keep_keepalive_only_flow_up_on_heartbeat() {
+        let transport = transport();
+        let heartbeat = TransportImpl::pack_header(TRANSPORT_HEADER_VERSION, None, false, true);
+        let reader = std::io::Cursor::new(heartbeat).chain(PendingReader);
+
+        let (stop, mut task) =
+            spawn_read_task(&transport, 1, reader, ConnectionMode::KeepaliveOnly);
+
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), &mut task)
+                .await
+                .is_err(),
+            "read task exited after receiving a heartbeat"
+        );
+        assert_eq!(
+            transport
+                .data_plane_metrics
+                .heart_beats_received
+                .with_label_values(&["flow_label", &FlowTag::from(1).to_string()])
+                .get(),
+            1
+        );
+        stop.notify();
+        tokio::time::timeout(Duration::from_secs(10), task)
+            .await
+            .expect("stopped read task did not exit")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_only_write_heartbeats_on_keepalive_only_flow() {
+        let transport = transport();
+        let send_queue_reader = OnceSendQueueReader {
+            messages: vec![DequeuedMessage {
+                payload: TransportPayload(vec![0u8; 100]),
+                sender_error: false,
+            }],
+        };
+        let writer = SharedBufferWriter::default();
+        let heartbeat_config =
+            HeartbeatConfig::new(Duration::from_millis(10), Duration::from_millis(100)).unwrap();
+        let socket_io_config =
+            default_socket_io_config().with_connection_mode(ConnectionMode::KeepaliveOnly);
+        let stop = Arc::new(Notify::new());
+        let stop_cl = stop.clone();
+        tokio::spawn(async move {
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+            stop_cl.notify();
+        });
+
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            TransportImpl::flow_write_task(
+                flow_id(1),
+                "flow_label".to_string(),
+                Box::new(send_queue_reader),
+                writer.clone(),
+                TRANSPORT_HEADER_VERSION,
+                heartbeat_config,
+                socket_io_config,
+                transport.data_plane_metrics.clone(),
+                Arc::downgrade(&transport),
+                stop,
+            ),
+        )
+        .await
+        .expect("stopped write task did not exit");
+
+        let written = writer.buffer.lock().unwrap().clone();
+        let heartbeat = TransportImpl::pack_header(TRANSPORT_HEADER_VERSION, None, false, true);
+        assert!(!written.is_empty());
+        for chunk in written.chunks(TRANSPORT_HEADER_SIZE) {
+            assert_eq!(chunk, &heartbeat[..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_flush_queued_messages_when_write_task_is_stopped() {
+        let transport = transport();
+        let send_queue_reader = OnceSendQueueReader {
+            messages: (0..3)
+                .map(|_| DequeuedMessage {
+                    payload: TransportPayload(vec![0u8; 100]),
+                    sender_error: false,
+                })
+                .collect(),
+        };
+        let writer = SharedBufferWriter::default();
+        let heartbeat_config =
+            HeartbeatConfig::new(Duration::from_secs(30), Duration::from_secs(60)).unwrap();
+        let stop = Arc::new(Notify::new());
+        stop.notify();
+
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            TransportImpl::flow_write_task(
+                flow_id(1),
+                "flow_label".to_string(),
+                Box::new(send_queue_reader),
+                writer.clone(),
+                TRANSPORT_HEADER_VERSION,
+                heartbeat_config,
+                default_socket_io_config(),
+                transport.data_plane_metrics.clone(),
+                Arc::downgrade(&transport),
+                stop,
+            ),
+        )
+        .await
+        .expect("stopped write task did not exit");
+
+        assert_eq!(
+            writer.buffer.lock().unwrap().len(),
+            3 * (TRANSPORT_HEADER_SIZE + 100)
+        );
+        let flow_tag = FlowTag::from(1).to_string();
+        let labels = ["flow_label", flow_tag.as_str()];
+        let metrics = &transport.data_plane_metrics;
+        assert_eq!(
+            metrics.socket_write_bytes.with_label_values(&labels).get(),
+            3 * (TRANSPORT_HEADER_SIZE + 100) as i64
+        );
+        assert_eq!(
+            metrics
+                .socket_write_payload_bytes
+                .with_label_values(&labels)
+                .get(),
+            300
+        );
+    }
+
+    #[tokio::test]
+    async fn should_stop_write_task_when_flush_to_peer_that_never_reads_times_out() {
+        let transport = transport();
+        let send_queue_reader = OnceSendQueueReader {
+            messages: vec![DequeuedMessage {
+                payload: TransportPayload(vec![0u8; 100]),
+                sender_error: false,
+            }],
+        };
+        let heartbeat_config =
+            HeartbeatConfig::new(Duration::from_secs(30), Duration::from_secs(60)).unwrap();
+        let write_timeout = Duration::from_millis(100);
+        let socket_io_config = SocketIoConfig::new(
+            DEFAULT_DEQUEUE_BYTES,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+            write_timeout,
+        )
+        .unwrap();
+        let stop = Arc::new(Notify::new());
+        stop.notify();
+
+        let start_time = Instant::now();
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            TransportImpl::flow_write_task(
+                flow_id(1),
+                "flow_label".to_string(),
+                Box::new(send_queue_reader),
+                PendingWriter,
+                TRANSPORT_HEADER_VERSION,
+                heartbeat_config,
+                socket_io_config,
+                transport.data_plane_metrics.clone(),
+                Arc::downgrade(&transport),
+                stop,
+            ),
+        )
+        .await
+        .expect("stopped write task did not exit after the flush timed out");
+
+        assert!(start_time.elapsed() >= write_timeout);
+        assert_eq!(
+            transport
+                .data_plane_metrics
+                .socket_write_timeouts
+                .with_label_values(&["flow_label", &FlowTag::from(1).to_string()])
+                .get(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn should_disconnect_when_write_times_out() {
+        let transport = transport();
+        let send_queue_reader = OnceSendQueueReader {
+            messages: vec![DequeuedMessage {
+                payload: TransportPayload(vec![0u8; 100]),
+                sender_error: false,
+            }],
+        };
+        let heartbeat_config =
+            HeartbeatConfig::new(Duration::from_secs(30), Duration::from_secs(60)).unwrap();
+        let write_timeout = Duration::from_millis(100);
+        let socket_io_config = SocketIoConfig::new(
+            DEFAULT_DEQUEUE_BYTES,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+            write_timeout,
+        )
+        .unwrap();
+
+        let start_time = Instant::now();
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            TransportImpl::flow_write_task(
+                flow_id(1),
+                "flow_label".to_string(),
+                Box::new(send_queue_reader),
+                PendingWriter,
+                TRANSPORT_HEADER_VERSION,
+                heartbeat_config,
+                socket_io_config,
+                transport.data_plane_metrics.clone(),
+                Arc::downgrade(&transport),
+                Arc::new(Notify::new()),
+            ),
+        )
+        .await
+        .expect("write task did not exit after the write timed out");
+
+        assert!(start_time.elapsed() >= write_timeout);
+        assert_eq!(
+            transport
+                .data_plane_metrics
+                .socket_write_timeouts
+                .with_label_values(&["flow_label", &FlowTag::from(1).to_string()])
+                .get(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn should_cap_write_throughput_at_configured_rate() {
+        let transport = transport();
+        let writer = SharedBufferWriter::default();
+        let heartbeat_config =
+            HeartbeatConfig::new(Duration::from_secs(30), Duration::from_secs(60)).unwrap();
+        let max_write_bytes_per_sec = 20_000;
+        let socket_io_config = default_socket_io_config()
+            .with_max_write_bytes_per_sec(max_write_bytes_per_sec)
+            .unwrap();
+        let window = Duration::from_millis(500);
+
+        let result = tokio::time::timeout(
+            window,
+            TransportImpl::flow_write_task(
+                flow_id(1),
+                "flow_label".to_string(),
+                Box::new(FixedSendQueueReader { message_count: 10 }),
+                writer.clone(),
+                TRANSPORT_HEADER_VERSION,
+                heartbeat_config,
+                socket_io_config,
+                transport.data_plane_metrics.clone(),
+                Arc::downgrade(&transport),
+                Arc::new(Notify::new()),
+            ),
+        )
+        .await;
+
+        assert!(result.is_err(), "write task exited");
+        // The bucket starts empty, so every batch, including the first one, is
+        // only written once the tokens for it have been replenished.
+        let expected = max_write_bytes_per_sec as f64 * window.as_secs_f64();
+        let written = writer.buffer.lock().unwrap().len() as f64;
+        assert!(
+            written <= expected * 1.1,
+            "wrote {} bytes, expected at most about {}",
+            written,
+            expected
+        );
+        assert!(
+            written >= expected * 0.5,
+            "wrote {} bytes, expected at least about {}",
+            written,
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn should_delay_first_write_until_tokens_are_replenished() {
+        let transport = transport();
+        let writer = SharedBufferWriter::default();
+        let heartbeat_config =
+            HeartbeatConfig::new(Duration::from_secs(30), Duration::from_secs(60)).unwrap();
+        let max_write_bytes_per_sec = 20_000;
+        let socket_io_config = default_socket_io_config()
+            .with_max_write_bytes_per_sec(max_write_bytes_per_sec)
+            .unwrap();
+        let batch_size = 10 * (TRANSPORT_HEADER_SIZE + 100);
+        let first_batch_delay =
+            Duration::from_secs_f64(batch_size as f64 / max_write_bytes_per_sec as f64);
+
+        let result = tokio::time::timeout(
+            first_batch_delay / 2,
+            TransportImpl::flow_write_task(
+                flow_id(1),
+                "flow_label".to_string(),
+                Box::new(FixedSendQueueReader { message_count: 10 }),
+                writer.clone(),
+                TRANSPORT_HEADER_VERSION,
+                heartbeat_config,
+                socket_io_config,
+                transport.data_plane_metrics.clone(),
+                Arc::downgrade(&transport),
+                Arc::new(Notify::new()),
+            ),
+        )
+        .await;
+
+        assert!(result.is_err(), "write task exited");
+        assert!(writer.buffer.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_count_written_header_and_payload_bytes_separately() {
+        let transport = transport();
+        let send_queue_reader = OnceSendQueueReader {
+            messages: (0..2)
+                .map(|_| DequeuedMessage {
+                    payload: TransportPayload(vec![0u8; 100]),
+                    sender_error: false,
+                })
+                .collect(),
+        };
+        let heartbeat_config =
+            HeartbeatConfig::new(Duration::from_millis(10), Duration::from_millis(100)).unwrap();
+        let stop = Arc::new(Notify::new());
+        let stop_cl = stop.clone();
+        tokio::spawn(async move {
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+            stop_cl.notify();
+        });
+
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            TransportImpl::flow_write_task(
+                flow_id(1),
+                "flow_label".to_string(),
+                Box::new(send_queue_reader),
+                SharedBufferWriter::default(),
+                TRANSPORT_HEADER_VERSION,
+                heartbeat_config,
+                default_socket_io_config(),
+                transport.data_plane_metrics.clone(),
+                Arc::downgrade(&transport),
+                stop,
+            ),
+        )
+        .await
+        .expect("stopped write task did not exit");
+
+        let flow_tag = FlowTag::from(1).to_string();
+        let labels = ["flow_label", flow_tag.as_str()];
+        let metrics = &transport.data_plane_metrics;
+        let heartbeats = metrics.heart_beats_sent.with_label_values(&labels).get();
+        assert!(heartbeats > 0);
+        assert_eq!(
+            metrics
+                .socket_write_header_bytes
+                .with_label_values(&labels)
+                .get(),
+            (2 + heartbeats) * TRANSPORT_HEADER_SIZE as i64
+        );
+        assert_eq!(
+            metrics
+                .socket_write_payload_bytes
+                .with_label_values(&labels)
+                .get(),
+            200
+        );
+    }
+
+    #[tokio::test]
+    async fn should_count_read_header_and_payload_bytes_separately() {
+        let transport = transport();
+        let heartbeat = TransportImpl::pack_header(TRANSPORT_HEADER_VERSION, None, false, true);
+        let payload = TransportPayload(vec![1, 2, 3]);
+        let mut data = heartbeat.clone();
+        data.extend(TransportImpl::pack_header(
+            TRANSPORT_HEADER_VERSION,
+            Some(&payload),
+            false,
+            false,
+        ));
+        data.extend_from_slice(&payload.0);
+        data.extend(heartbeat);
+        let reader = std::io::Cursor::new(data).chain(PendingReader);
+
+        let (stop, task) = spawn_read_task(&transport, 1, reader, ConnectionMode::Data);
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+        stop.notify();
+        tokio::time::timeout(Duration::from_secs(10), task)
+            .await
+            .expect("stopped read task did not exit")
+            .unwrap();
+
+        let flow_tag = FlowTag::from(1).to_string();
+        let labels = ["flow_label", flow_tag.as_str()];
+        let metrics = &transport.data_plane_metrics;
+        assert_eq!(
+            metrics
+                .socket_read_header_bytes
+                .with_label_values(&labels)
+                .get(),
+            3 * TRANSPORT_HEADER_SIZE as i64
+        );
+        assert_eq!(
+            metrics
+                .socket_read_payload_bytes
+                .with_label_values(&labels)
+                .get(),
+            3
+        );
+        assert_eq!(
+            metrics.socket_read_bytes.with_label_values(&labels).get(),
+            3 * TRANSPORT_HEADER_SIZE as i64 + 3
+        );
+    }
+
+    fn spawn_read_task_with_slow_event_handler(
+        transport: &Arc<TransportImpl>,
+        disconnect_on_event_handler_timeout: bool,
+    ) -> (Arc<Notify>, JoinHandle<()>) {
+        let payload = TransportPayload(vec![1, 2, 3]);
+        let mut data =
+            TransportImpl::pack_header(TRANSPORT_HEADER_VERSION, Some(&payload), false, false);
+        data.extend_from_slice(&payload.0);
+        let reader = std::io::Cursor::new(data).chain(PendingReader);
+        let socket_io_config = default_socket_io_config()
+            .with_event_handler_timeout(
+                Duration::from_millis(50),
+                disconnect_on_event_handler_timeout,
+            )
+            .unwrap();
+        spawn_read_task_with_event_handler(
+            transport,
+            1,
+            reader,
+            socket_io_config,
+            Arc::new(SlowEventHandler {
+                delay: Duration::from_millis(200),
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn should_report_slow_event_handler_and_keep_connection() {
+        let transport = transport();
+
+        let (stop, mut task) = spawn_read_task_with_slow_event_handler(&transport, false);
+
+        assert!(
+            tokio::time::timeout(Duration::from_millis(500), &mut task)
+                .await
+                .is_err(),
+            "read task exited after a slow event handler"
+        );
+        assert_eq!(
+            transport
+                .data_plane_metrics
+                .event_handler_timeouts
+                .with_label_values(&["flow_label", &FlowTag::from(1).to_string()])
+                .get(),
+            1
+        );
+        stop.notify();
+        tokio::time::timeout(Duration::from_secs(10), task)
+            .await
+            .expect("stopped read task did not exit")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_disconnect_on_slow_event_handler_if_configured() {
+        let transport = transport();
+
+        let (_stop, task) = spawn_read_task_with_slow_event_handler(&transport, true);
+
+        tokio::time::timeout(Duration::from_secs(10), task)
+            .await
+            .expect("read task did not exit after a slow event handler")
+            .unwrap();
+        assert_eq!(
+            transport
+                .data_plane_metrics
+                .event_handler_timeouts
+                .with_label_values(&["flow_label", &FlowTag::from(1).to_string()])
+                .get(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn should_disconnect_when_only_heartbeats_are_received_for_data_idle_timeout() {
+        let transport = transport();
+        let mut listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut peer = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (reader, _) = listener.accept().await.unwrap();
+        let heartbeat = TransportImpl::pack_header(TRANSPORT_HEADER_VERSION, None, false, true);
+        tokio::spawn(async move {
+            while peer.write_all(&heartbeat).await.is_ok() {
+                tokio::time::delay_for(Duration::from_millis(20)).await;
+            }
+        });
+        let data_idle_timeout = Duration::from_millis(200);
+        let socket_io_config = default_socket_io_config()
+            .with_data_idle_timeout(data_idle_timeout)
+            .unwrap();
+
+        let start_time = Instant::now();
+        let (_stop, task) = spawn_read_task_with_event_handler(
+            &transport,
+            1,
+            reader,
+            socket_io_config,
+            Arc::new(NoOpEventHandler),
+        );
+
+        tokio::time::timeout(Duration::from_secs(10), task)
+            .await
+            .expect("read task did not exit after the data idle timeout")
+            .unwrap();
+        assert!(start_time.elapsed() >= data_idle_timeout);
+        assert!(
+            transport
+                .data_plane_metrics
+                .heart_beats_received
+                .with_label_values(&["flow_label", &FlowTag::from(1).to_string()])
+                .get()
+                > 1
+        );
+    }
+
+    #[test]
+    fn should_reject_zero_data_idle_timeout() {
+        assert_eq!(
+            default_socket_io_config().with_data_idle_timeout(Duration::from_millis(0)),
+            Err(TransportErrorCode::InvalidSocketIoConfig)
+        );
+    }
+
+    #[test]
+    fn should_reject_zero_event_handler_timeout() {
+        assert_eq!(
+            default_socket_io_config().with_event_handler_timeout(Duration::from_millis(0), false),
+            Err(TransportErrorCode::InvalidSocketIoConfig)
+        );
+    }
+
+    async fn coalesce_messages(message_count: usize, metrics: &DataPlaneMetrics) -> Vec<u8> {
+        let mut send_queue_reader: Box<dyn SendQueueReader + Send + Sync> =
+            Box::new(FixedSendQueueReader { message_count });
+        let heartbeat_config =
+            HeartbeatConfig::new(Duration::from_millis(50), Duration::from_millis(500)).unwrap();
+        TransportImpl::dequeue_and_coalesce(
+            &mut send_queue_reader,
+            TRANSPORT_HEADER_VERSION,
+            heartbeat_config,
+            DEFAULT_DEQUEUE_BYTES,
+            metrics,
+            "flow_label",
+            "1",
+        )
+        .await
+        .0
+    }
+
+    #[tokio::test]
+    async fn should_observe_number_of_coalesced_messages() {
+        let metrics = DataPlaneMetrics::new(MetricsRegistry::new());
+
+        let to_send = coalesce_messages(7, &metrics).await;
+
+        assert_eq!(to_send.len(), 7 * (TRANSPORT_HEADER_SIZE + 100));
+        let coalesced_messages = metrics
+            .socket_write_coalesced_messages
+            .with_label_values(&["flow_label", "1"]);
+        assert_eq!(coalesced_messages.get_sample_count(), 1);
+        assert_eq!(coalesced_messages.get_sample_sum(), 7.0);
+        let coalesced_bytes = metrics
+            .socket_write_coalesced_bytes
+            .with_label_values(&["flow_label", "1"]);
+        assert_eq!(coalesced_bytes.get_sample_count(), 1);
+        assert_eq!(coalesced_bytes.get_sample_sum(), to_send.len() as f64);
+    }
+
+    #[tokio::test]
+    async fn should_publish_send_queue_depth_after_dequeue() {
+        let metrics = DataPlaneMetrics::new(MetricsRegistry::new());
+        let mut send_queue_reader: Box<dyn SendQueueReader + Send + Sync> =
+            Box::new(BacklogSendQueueReader {
+                backlog: 25,
+                batch_size: 10,
+            });
+        let heartbeat_config =
+            HeartbeatConfig::new(Duration::from_millis(50), Duration::from_millis(500)).unwrap();
+        let send_queue_depth = || {
+            metrics
+                .send_queue_depth
+                .with_label_values(&["flow_label", "1"])
+                .get()
+        };
+
+        for expected_depth in &[15, 5, 0] {
+            TransportImpl::dequeue_and_coalesce(
+                &mut send_queue_reader,
+                TRANSPORT_HEADER_VERSION,
+                heartbeat_config,
+                DEFAULT_DEQUEUE_BYTES,
+                &metrics,
+                "flow_label",
+                "1",
+            )
+            .await;
+            assert_eq!(send_queue_depth(), *expected_depth);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_not_observe_coalesced_messages_for_heartbeats() {
+        let metrics = DataPlaneMetrics::new(MetricsRegistry::new());
+
+        let to_send = coalesce_messages(0, &metrics).await;
+
+        assert_eq!(to_send.len(), TRANSPORT_HEADER_SIZE);
+        assert_eq!(
+            metrics
+                .socket_write_coalesced_messages
+                .with_label_values(&["flow_label", "1"])
+                .get_sample_count(),
+            0
+        );
+        assert_eq!(
+            metrics
+                .heart_beats_sent
+                .with_label_values(&["flow_label", "1"])
+                .get(),
+            1
+        );
+    }
+
+    #[test]
+    fn should_reject_heartbeat_send_interval_not_less_than_wait_interval() {
+        assert_eq!(
+            HeartbeatConfig::new(Duration::from_millis(500), Duration::from_millis(500)),
+            Err(TransportErrorCode::InvalidHeartbeatConfig)
+        );
+        assert_eq!(
+            HeartbeatConfig::new(Duration::from_millis(600), Duration::from_millis(500)),
+            Err(TransportErrorCode::InvalidHeartbeatConfig)
+        );
+    }
+
+    #[test]
+    fn should_reject_zero_heartbeat_send_interval() {
+        assert_eq!(
+            HeartbeatConfig::new(Duration::from_millis(0), Duration::from_millis(500)),
+            Err(TransportErrorCode::InvalidHeartbeatConfig)
+        );
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "Invalid heartbeat config for flow 0")]
+    async fn should_panic_on_creating_transport_with_invalid_flow_config() {
+        let data_provider = Arc::new(ProtoRegistryDataProvider::new());
+        let registry = Arc::new(FakeRegistryClient::new(data_provider));
+        let flow_config = TransportFlowConfig {
+            flow_tag: 0,
+            server_port: 0,
+            queue_size: 1,
+            heartbeat_send_interval_ms: Some(0),
+            ..Default::default()
+        };
+        TransportImpl::new(
+            NODE_1,
+            TransportConfig {
+                node_ip: "0.0.0.0".to_string(),
+                p2p_flows: vec![flow_config],
+            },
+            RegistryVersion::from(1),
+            MetricsRegistry::new(),
+            Arc::new(TempCryptoComponent::new(registry, NODE_1)),
+            tokio::runtime::Handle::current(),
+            no_op_logger(),
+        );
+    }
+
+    #[tokio::test]
+    async fn should_time_out_read_after_configured_heartbeat_wait_interval() {
+        let flow_config = TransportFlowConfig {
+            flow_tag: 0,
+            server_port: 0,
+            queue_size: 1,
+            heartbeat_send_interval_ms: Some(50),
+            heartbeat_wait_interval_ms: Some(500),
+            ..Default::default()
+        };
+        let heartbeat_config = HeartbeatConfig::from_flow_config(&flow_config).unwrap();
+        assert_eq!(heartbeat_config.send_interval, Duration::from_millis(50));
+        assert_eq!(heartbeat_config.wait_interval, Duration::from_millis(500));
+
+        let start_time = Instant::now();
+        let result = TransportImpl::read_one_message(
+            &mut PendingReader,
+            heartbeat_config.wait_interval,
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        )
+        .await;
+
+        assert!(matches!(result, Err(ReadError::SocketReadTimeOut)));
+        let elapsed = start_time.elapsed();
+        assert!(elapsed >= Duration::from_millis(500));
+        assert!(elapsed < Duration::from_millis(5000));
+    }
+
+    #[tokio::test]
+    async fn should_report_connection_closed_when_stream_ends_mid_read() {
+        let payload = TransportPayload(vec![1, 2, 3]);
+        let mut message =
+            TransportImpl::pack_header(TRANSPORT_HEADER_VERSION, Some(&payload), false, false);
+        message.extend_from_slice(&payload.0[..1]);
+        let mut reader: &[u8] = &message;
+
+        let result = TransportImpl::read_one_message(
+            &mut reader,
+            Duration::from_secs(1),
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        )
+        .await;
+
+        assert!(matches!(result, Err(ReadError::ConnectionClosed)));
+    }
+
+    #[tokio::test]
+    async fn should_report_socket_read_failure_other_than_end_of_stream() {
+        let result = TransportImpl::read_one_message(
+            &mut FailingReader,
+            Duration::from_secs(1),
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        )
+        .await;
+
+        assert!(matches!(result, Err(ReadError::SocketReadFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn should_reject_payload_longer_than_max_payload_length() {
+        let header = TransportImpl::pack_header(TRANSPORT_HEADER_VERSION, None, false, false);
+        let mut oversized_header = header[..TRANSPORT_HEADER_SIZE - 4].to_vec();
+        oversized_header.extend_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader: &[u8] = &oversized_header;
+
+        let result = TransportImpl::read_one_message(
+            &mut reader,
+            Duration::from_secs(1),
+            1024,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(ReadError::PayloadTooLarge {
+                declared: u32::MAX,
+                max: 1024
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_deliver_empty_message_as_empty_payload() {
+        let payload = TransportPayload(vec![]);
+        let message = TransportImpl::pack_header(0, Some(&payload), false, false);
+        let mut reader: &[u8] = &message;
+
+        let (header, read_payload) = TransportImpl::read_one_message(
+            &mut reader,
+            Duration::from_secs(1),
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(header.payload_length, 0);
+        assert_eq!(read_payload, Some(payload));
+    }
+
+    #[test]
+    fn should_reject_empty_message_only_if_configured() {
+        let empty = TransportPayload(vec![]);
+        let non_empty = TransportPayload(vec![1]);
+        let accepting = default_socket_io_config();
+        let rejecting = default_socket_io_config().with_reject_empty_messages(true);
+
+        assert_eq!(accepting.check_message(&empty), Ok(()));
+        assert_eq!(
+            rejecting.check_message(&empty),
+            Err(TransportErrorCode::EmptyMessage)
+        );
+        assert_eq!(rejecting.check_message(&non_empty), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn should_read_message_with_supported_version() {
+        let payload = TransportPayload(vec![1, 2, 3]);
+        let mut message = TransportImpl::pack_header(0, Some(&payload), false, false);
+        message.extend_from_slice(&payload.0);
+        let mut reader: &[u8] = &message;
+
+        let (header, read_payload) = TransportImpl::read_one_message(
+            &mut reader,
+            Duration::from_secs(1),
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(header.version, 0);
+        assert_eq!(read_payload, Some(payload));
+    }
+
+    #[tokio::test]
+    async fn should_reject_message_with_unsupported_version() {
+        let payload = TransportPayload(vec![1, 2, 3]);
+        let mut message = TransportImpl::pack_header(255, Some(&payload), false, false);
+        message.extend_from_slice(&payload.0);
+        let mut reader: &[u8] = &message;
+
+        let result = TransportImpl::read_one_message(
+            &mut reader,
+            Duration::from_secs(1),
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        )
+        .await;
+
+        assert!(matches!(result, Err(ReadError::UnsupportedVersion(255))));
+    }
+
+    #[tokio::test]
+    async fn should_read_message_with_header_checksum() {
+        let payload = TransportPayload(vec![1, 2, 3]);
+        let mut message =
+            TransportImpl::pack_header(HEADER_CHECKSUM_VERSION, Some(&payload), false, false);
+        message.extend_from_slice(&payload.0);
+        let mut reader: &[u8] = &message;
+
+        let (header, read_payload) = TransportImpl::read_one_message(
+            &mut reader,
+            Duration::from_secs(1),
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(header.version, HEADER_CHECKSUM_VERSION);
+        assert_eq!(header.reserved, TransportImpl::header_checksum(&header));
+        assert_eq!(read_payload, Some(payload));
+    }
+
+    #[tokio::test]
+    async fn should_reject_header_with_corrupted_payload_length() {
+        let payload = TransportPayload(vec![1, 2, 3]);
+        let mut message =
+            TransportImpl::pack_header(HEADER_CHECKSUM_VERSION, Some(&payload), false, false);
+        let checksum = u16::from_le_bytes([message[2], message[3]]);
+        // Flip a bit in the payload length
+        message[4] ^= 0x04;
+        message.extend_from_slice(&[0u8; 7]);
+        let mut reader: &[u8] = &message;
+
+        let result = TransportImpl::read_one_message(
+            &mut reader,
+            Duration::from_secs(1),
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        )
+        .await;
+
+        match result {
+            Err(ReadError::HeaderChecksumMismatch { expected, received }) => {
+                assert_eq!(received, checksum);
+                assert_ne!(expected, checksum);
+            }
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("corrupted header was accepted"),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_not_check_reserved_field_of_version_0_header() {
+        let payload = TransportPayload(vec![1, 2, 3]);
+        let mut message = TransportImpl::pack_header(0, Some(&payload), false, false);
+        assert_eq!(&message[2..4], &[0, 0]);
+        message[2] = 0xff;
+        message.extend_from_slice(&payload.0);
+        let mut reader: &[u8] = &message;
+
+        let (_header, read_payload) = TransportImpl::read_one_message(
+            &mut reader,
+            Duration::from_secs(1),
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            DEFAULT_SOCKET_READ_CHUNK_SIZE,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(read_payload, Some(payload));
+    }
+
+    #[tokio::test]
+    async fn should_reassemble_payload_read_in_small_chunks() {
+        let payload = TransportPayload((0..10).collect());
+        let mut message =
+            TransportImpl::pack_header(TRANSPORT_HEADER_VERSION, Some(&payload), false, false);
+        message.extend_from_slice(&payload.0);
+        let mut reader: &[u8] = &message;
+
+        let (_header, read_payload) = TransportImpl::read_one_message(
+            &mut reader,
+            Duration::from_secs(1),
+            DEFAULT_MAX_PAYLOAD_LENGTH,
+            3,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(read_payload, Some(payload));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn should_reject_zero_socket_io_sizes() {
+        let write_timeout = Duration::from_millis(DEFAULT_SOCKET_WRITE_TIMEOUT_MS);
+        assert_eq!(
+            SocketIoConfig::new(0, DEFAULT_SOCKET_READ_CHUNK_SIZE, write_timeout),
+            Err(TransportErrorCode::InvalidSocketIoConfig)
+        );
+        assert_eq!(
+            SocketIoConfig::new(DEFAULT_DEQUEUE_BYTES, 0, write_timeout),
+            Err(TransportErrorCode::InvalidSocketIoConfig)
+        );
+        assert_eq!(
+            SocketIoConfig::new(
+                DEFAULT_DEQUEUE_BYTES,
+                DEFAULT_SOCKET_READ_CHUNK_SIZE,
+                Duration::from_millis(0)
+            ),
+            Err(TransportErrorCode::InvalidSocketIoConfig)
+        );
+        assert!(SocketIoConfig::new(1, 1, Duration::from_millis(1)).is_ok());
+    }
+
+    #[test]
+    fn should_reject_zero_write_rate_limit() {
+        assert_eq!(
+            default_socket_io_config().with_max_write_bytes_per_sec(0),
+            Err(TransportErrorCode::InvalidSocketIoConfig)
+        );
+    }
+}
@@ -80,13 +80,22 @@ pub(crate) struct DataPlaneMetrics {
     pub(crate) client_send_fail: IntCounterVec,
     pub(crate) client_send_time_msec: HistogramVec,
     pub(crate) socket_write_bytes: IntCounterVec,
+    pub(crate) socket_write_header_bytes: IntCounterVec,
+    pub(crate) socket_write_payload_bytes: IntCounterVec,
     pub(crate) socket_write_size: HistogramVec,
     pub(crate) socket_write_time_msec: HistogramVec,
+    pub(crate) socket_write_coalesced_messages: HistogramVec,
+    pub(crate) socket_write_coalesced_bytes: HistogramVec,
+    pub(crate) send_queue_depth: IntGaugeVec,
     pub(crate) socket_read_bytes: IntCounterVec,
+    pub(crate) socket_read_header_bytes: IntCounterVec,
+    pub(crate) socket_read_payload_bytes: IntCounterVec,
     pub(crate) socket_heart_beat_timeouts: IntCounterVec,
+    pub(crate) socket_write_timeouts: IntCounterVec,
     pub(crate) heart_beats_sent: IntCounterVec,
     pub(crate) heart_beats_received: IntCounterVec,
     pub(crate) send_errors_received: IntCounterVec,
+    pub(crate) event_handler_timeouts: IntCounterVec,
     pub(crate) write_tasks: IntGauge,
     pub(crate) read_tasks: IntGauge,
     pub(crate) write_task_overhead_time_msec: HistogramVec,
@@ -114,6 +123,16 @@ impl DataPlaneMetrics {
                 "Bytes written to sockets",
                 &["flow_peer_id", "flow_tag"],
             ),
+            socket_write_header_bytes: metrics_registry.int_counter_vec(
+                "transport_socket_write_header_bytes",
+                "Transport header bytes written to sockets, including heartbeats",
+                &["flow_peer_id", "flow_tag"],
+            ),
+            socket_write_payload_bytes: metrics_registry.int_counter_vec(
+                "transport_socket_write_payload_bytes",
+                "Message payload bytes written to sockets",
+                &["flow_peer_id", "flow_tag"],
+            ),
             socket_write_size: metrics_registry.histogram_vec(
                 "transport_socket_write_size",
                 "Bytes written per socket write",
@@ -128,9 +147,38 @@ impl DataPlaneMetrics {
                 decimal_buckets(0, 5),
                 &["flow_peer_id", "flow_tag"],
             ),
+            socket_write_coalesced_messages: metrics_registry.histogram_vec(
+                "transport_socket_write_coalesced_messages",
+                "Messages aggregated per socket write",
+                // 1, 2, 5 - 1K, 2K, 5K
+                decimal_buckets(0, 3),
+                &["flow_peer_id", "flow_tag"],
+            ),
+            socket_write_coalesced_bytes: metrics_registry.histogram_vec(
+                "transport_socket_write_coalesced_bytes",
+                "Message bytes aggregated per socket write, excluding heartbeats",
+                // 1K, 2K, 5K - 1MB, 2MB, 5MB
+                decimal_buckets(3, 6),
+                &["flow_peer_id", "flow_tag"],
+            ),
+            send_queue_depth: metrics_registry.int_gauge_vec(
+                "transport_send_queue_depth",
+                "Messages left in the send queue after the last dequeue",
+                &["flow_peer_id", "flow_tag"],
+            ),
             socket_read_bytes: metrics_registry.int_counter_vec(
                 "transport_socket_read_bytes",
-                "Bytes read from sockets",
+                "Bytes read from sockets, including transport headers",
+                &["flow_peer_id", "flow_tag"],
+            ),
+            socket_read_header_bytes: metrics_registry.int_counter_vec(
+                "transport_socket_read_header_bytes",
+                "Transport header bytes read from sockets, including heartbeats",
+                &["flow_peer_id", "flow_tag"],
+            ),
+            socket_read_payload_bytes: metrics_registry.int_counter_vec(
+                "transport_socket_read_payload_bytes",
+                "Message payload bytes read from sockets",
                 &["flow_peer_id", "flow_tag"],
             ),
             socket_heart_beat_timeouts: metrics_registry.int_counter_vec(
@@ -138,11 +186,21 @@ impl DataPlaneMetrics {
                 "Number of times the heart beat timed out.",
                 &["flow_peer_id", "flow_tag"],
             ),
+            socket_write_timeouts: metrics_registry.int_counter_vec(
+                "transport_socket_write_timeouts",
+                "Number of times a socket write timed out",
+                &["flow_peer_id", "flow_tag"],
+            ),
             send_errors_received: metrics_registry.int_counter_vec(
                 "transport_send_errors_received",
                 "Number of peer send error notifications",
                 &["flow_peer_id", "flow_tag"],
             ),
+            event_handler_timeouts: metrics_registry.int_counter_vec(
+                "transport_event_handler_timeouts",
+                "Number of times the event handler did not accept a received message in time",
+                &["flow_peer_id", "flow_tag"],
+            ),
             heart_beats_received: metrics_registry.int_counter_vec(
                 "transport_heart_beats_received",
                 "Number of heart beats as seen by receiver",
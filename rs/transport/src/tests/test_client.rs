@@ -489,11 +489,13 @@ fn generate_config_and_registry(node_id: &NodeId) -> ConfigAndRecords {
                         flow_tag: FLOW_TAG_1,
                         server_port: n.2,
                         queue_size: 1024,
+                        ..Default::default()
                     },
                     TransportFlowConfig {
                         flow_tag: FLOW_TAG_2,
                         server_port: n.3,
                         queue_size: 1024,
+                        ..Default::default()
                     },
                 ],
             });
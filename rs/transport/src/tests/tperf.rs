@@ -639,6 +639,7 @@ fn generate_config_and_registry(
             flow_tag: FLOW_TAG,
             server_port: FLOW_PORT as u16,
             queue_size: 8192,
+            ..Default::default()
         }],
     };
 
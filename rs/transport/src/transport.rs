@@ -67,14 +67,14 @@
 //! ```
 
 use crate::metrics::{ControlPlaneMetrics, DataPlaneMetrics, SendQueueMetrics};
-use crate::types::TransportImpl;
+use crate::types::{HeartbeatConfig, SocketIoConfig, TransportImpl};
 use ic_crypto_tls_interfaces::TlsHandshake;
 use ic_interfaces::transport::{AsyncTransportEventHandler, Transport};
 use ic_logger::ReplicaLogger;
 use ic_metrics::MetricsRegistry;
 use ic_protobuf::registry::node::v1::NodeRecord;
 use ic_types::transport::{
-    FlowTag, TransportClientType, TransportConfig, TransportErrorCode, TransportPayload,
+    FlowId, FlowTag, TransportClientType, TransportConfig, TransportErrorCode, TransportPayload,
 };
 use ic_types::{NodeId, RegistryVersion};
 
@@ -86,6 +86,10 @@ use tokio::runtime::Handle;
 
 impl TransportImpl {
     /// Creates a new Transport instance
+    ///
+    /// Panics if the node IP or the config of any flow is invalid, so that a
+    /// misconfigured flow is caught on startup rather than when a peer is
+    /// added.
     pub fn new(
         node_id: NodeId,
         config: TransportConfig,
@@ -97,6 +101,20 @@ impl TransportImpl {
     ) -> Arc<Self> {
         let node_ip = IpAddr::from_str(&config.node_ip)
             .unwrap_or_else(|_| panic!("Invalid node IP: {}", &config.node_ip));
+        for flow_config in &config.p2p_flows {
+            HeartbeatConfig::from_flow_config(flow_config).unwrap_or_else(|e| {
+                panic!(
+                    "Invalid heartbeat config for flow {}: {:?}",
+                    flow_config.flow_tag, e
+                )
+            });
+            SocketIoConfig::from_flow_config(flow_config).unwrap_or_else(|e| {
+                panic!(
+                    "Invalid socket IO config for flow {}: {:?}",
+                    flow_config.flow_tag, e
+                )
+            });
+        }
         let arc = Arc::new(Self {
             node_id,
             node_ip,
@@ -189,6 +207,10 @@ impl Transport for TransportImpl {
             Some(flow_state) => flow_state,
             None => return Err(TransportErrorCode::FlowNotFound),
         };
+        if flow_state.stop_requested {
+            return Err(TransportErrorCode::FlowStopped);
+        }
+        flow_state.socket_io_config.check_message(&message)?;
         match flow_state.send_queue.enqueue(message) {
             Some(unsent) => Err(TransportErrorCode::TransportBusy(unsent)),
             None => Ok(()),
@@ -235,4 +257,17 @@ impl Transport for TransportImpl {
                 }
             });
     }
+
+    fn stop_connection(
+        &self,
+        client_type: TransportClientType,
+        peer_id: &NodeId,
+        flow_tag: FlowTag,
+    ) -> Result<(), TransportErrorCode> {
+        self.stop_flow(&FlowId {
+            client_type,
+            peer_id: *peer_id,
+            flow_tag,
+        })
+    }
 }
@@ -5,7 +5,8 @@ use ic_crypto_tls_interfaces::TlsHandshake;
 use ic_interfaces::transport::AsyncTransportEventHandler;
 use ic_logger::ReplicaLogger;
 use ic_types::transport::{
-    FlowId, FlowTag, TransportClientType, TransportConfig, TransportPayload,
+    ConnectionMode, FlowId, FlowTag, TransportClientType, TransportConfig, TransportErrorCode,
+    TransportFlowConfig, TransportPayload,
 };
 use ic_types::{NodeId, RegistryVersion};
 use phantom_newtype::{AmountOf, Id};
@@ -19,6 +20,7 @@ use std::net::SocketAddr;
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, RwLock, Weak};
 use tokio::runtime::Handle;
+use tokio::sync::Notify;
 use tokio::time::Duration;
 
 /// A tag for the server port
@@ -36,6 +38,19 @@ pub type QueueSize = AmountOf<QueueSizeTag, usize>;
 /// The size (in bytes) of the transport header
 pub const TRANSPORT_HEADER_SIZE: usize = 8;
 
+/// The transport header version written by this node
+///
+/// This stays below `HEADER_CHECKSUM_VERSION` until all peers are able to
+/// read headers carrying a checksum.
+pub(crate) const TRANSPORT_HEADER_VERSION: u8 = 0;
+
+/// The highest transport header version this node can read
+pub(crate) const MAX_SUPPORTED_VERSION: u8 = 1;
+
+/// The lowest transport header version whose `reserved` field carries a
+/// checksum over the other header fields
+pub(crate) const HEADER_CHECKSUM_VERSION: u8 = 1;
+
 /// Flag: sender-indicated error
 ///
 /// When a message has this flag on, it means that the sender of this message
@@ -49,6 +64,261 @@ pub const TRANSPORT_FLAGS_SENDER_ERROR: u8 = 1;
 /// connection alive.
 pub const TRANSPORT_FLAGS_IS_HEARTBEAT: u8 = 2;
 
+/// Default heartbeat send interval (timeout on sender side)
+pub(crate) const DEFAULT_HEARTBEAT_SEND_INTERVAL_MS: u64 = 200;
+/// Default heartbeat wait interval (timeout on receiver side)
+pub(crate) const DEFAULT_HEARTBEAT_WAIT_INTERVAL_MS: u64 = 5000;
+
+/// Default maximum payload length accepted from a peer
+pub(crate) const DEFAULT_MAX_PAYLOAD_LENGTH: u32 = 256 * 1024 * 1024;
+
+// DEFAULT_DEQUEUE_BYTES is the number of bytes which we will attempt to dequeue
+// and aggregate before sending to the network via write_all(). Tokio currently
+// does not support writev so aggregation is performed manually. This is
+// necessary because we are setting TCP_NODELAY which causes each write to be
+// pushed to the network. Without aggregation, we would have many small writes
+// and thus many small packets. A value of ~800K here works well with a queue
+// size of 1K. Values down to 8K work with queue size >= 4K. Smaller sizes make
+// the system more responsive in clearing the queues at the cost of increased
+// CPU usage. Larger sizes effectively add to the queue size but make the system
+// less responsive to queue clearing. A good compromise size might be 32K with a
+// larger queue size.
+/// Default number of bytes which will be attempted to dequeue and aggregate
+/// before sending to the network
+pub(crate) const DEFAULT_DEQUEUE_BYTES: usize = 100 * 4 * 1490;
+
+/// Default size of the chunks in which payloads are read from the socket
+pub(crate) const DEFAULT_SOCKET_READ_CHUNK_SIZE: usize = 32 * 1024;
+
+/// Default time after which a pending socket write is aborted. This is much
+/// larger than the heartbeat wait interval, as a single write may carry up to
+/// `DEFAULT_DEQUEUE_BYTES` or one maximum-sized payload.
+pub(crate) const DEFAULT_SOCKET_WRITE_TIMEOUT_MS: u64 = 60_000;
+
+/// Default time after which the read task reports the event handler as slow.
+/// While the event handler is busy, the read task does not read from the
+/// socket, so this is well below the default heartbeat wait interval.
+pub(crate) const DEFAULT_EVENT_HANDLER_TIMEOUT_MS: u64 = 1_000;
+
+/// Sizes of the socket writes and reads of a flow, the time after which a
+/// socket write is aborted, and the optional limit on the write rate. All of
+/// them are always positive. Also holds the mode of the flow's connections,
+/// which decides whether the socket carries messages at all, how the read
+/// task deals with a slow event handler, and the optional time after which
+/// the read task drops a connection that only carries heartbeats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SocketIoConfig {
+    /// Number of bytes the write task attempts to dequeue and aggregate into
+    /// a single socket write
+    pub(crate) dequeue_bytes: usize,
+    /// Size of the chunks in which the read task reads payloads
+    pub(crate) read_chunk_size: usize,
+    /// Time after which the write task considers the connection down if a
+    /// socket write did not complete
+    pub(crate) write_timeout: Duration,
+    /// Maximum number of bytes per second the write task writes to the
+    /// socket, not counting heartbeats. Unlimited if `None`.
+    pub(crate) max_write_bytes_per_sec: Option<u64>,
+    /// Whether the write task sends messages or only heartbeats, and whether
+    /// the read task accepts messages
+    pub(crate) connection_mode: ConnectionMode,
+    /// Whether empty messages are rejected when they are enqueued, rather
+    /// than sent as zero-length data frames
+    pub(crate) reject_empty_messages: bool,
+    /// Time after which the read task reports the event handler as slow if
+    /// it has not accepted a received message
+    pub(crate) event_handler_timeout: Duration,
+    /// Whether the read task considers the connection down if the event
+    /// handler is slow, instead of waiting for it
+    pub(crate) disconnect_on_event_handler_timeout: bool,
+    /// Time after which the read task considers the connection down if no
+    /// message other than heartbeats was received. Never if `None`.
+    pub(crate) data_idle_timeout: Option<Duration>,
+}
+
+impl SocketIoConfig {
+    /// Creates a socket IO config, checking that the sizes and the timeout
+    /// are positive.
+    pub(crate) fn new(
+        dequeue_bytes: usize,
+        read_chunk_size: usize,
+        write_timeout: Duration,
+    ) -> Result<Self, TransportErrorCode> {
+        if dequeue_bytes == 0 || read_chunk_size == 0 || write_timeout == Duration::from_millis(0) {
+            return Err(TransportErrorCode::InvalidSocketIoConfig);
+        }
+        Ok(Self {
+            dequeue_bytes,
+            read_chunk_size,
+            write_timeout,
+            max_write_bytes_per_sec: None,
+            connection_mode: ConnectionMode::Data,
+            reject_empty_messages: false,
+            event_handler_timeout: Duration::from_millis(DEFAULT_EVENT_HANDLER_TIMEOUT_MS),
+            disconnect_on_event_handler_timeout: false,
+            data_idle_timeout: None,
+        })
+    }
+
+    /// Sets the mode of the flow's connections.
+    pub(crate) fn with_connection_mode(mut self, connection_mode: ConnectionMode) -> Self {
+        self.connection_mode = connection_mode;
+        self
+    }
+
+    /// Sets whether empty messages are rejected when they are enqueued.
+    pub(crate) fn with_reject_empty_messages(mut self, reject_empty_messages: bool) -> Self {
+        self.reject_empty_messages = reject_empty_messages;
+        self
+    }
+
+    /// Sets how the read task deals with a slow event handler, checking that
+    /// the timeout is positive.
+    pub(crate) fn with_event_handler_timeout(
+        mut self,
+        event_handler_timeout: Duration,
+        disconnect_on_event_handler_timeout: bool,
+    ) -> Result<Self, TransportErrorCode> {
+        if event_handler_timeout == Duration::from_millis(0) {
+            return Err(TransportErrorCode::InvalidSocketIoConfig);
+        }
+        self.event_handler_timeout = event_handler_timeout;
+        self.disconnect_on_event_handler_timeout = disconnect_on_event_handler_timeout;
+        Ok(self)
+    }
+
+    /// Drops connections on which no message other than heartbeats was
+    /// received for `data_idle_timeout`, checking that the timeout is
+    /// positive.
+    pub(crate) fn with_data_idle_timeout(
+        mut self,
+        data_idle_timeout: Duration,
+    ) -> Result<Self, TransportErrorCode> {
+        if data_idle_timeout == Duration::from_millis(0) {
+            return Err(TransportErrorCode::InvalidSocketIoConfig);
+        }
+        self.data_idle_timeout = Some(data_idle_timeout);
+        Ok(self)
+    }
+
+    /// Checks that `message` may be enqueued on the flow. Empty messages are
+    /// only rejected if the flow is configured to do so.
+    pub(crate) fn check_message(
+        &self,
+        message: &TransportPayload,
+    ) -> Result<(), TransportErrorCode> {
+        if self.reject_empty_messages && message.0.is_empty() {
+            return Err(TransportErrorCode::EmptyMessage);
+        }
+        Ok(())
+    }
+
+    /// Limits the rate at which the write task writes to the socket, checking
+    /// that the rate is positive.
+    pub(crate) fn with_max_write_bytes_per_sec(
+        mut self,
+        max_write_bytes_per_sec: u64,
+    ) -> Result<Self, TransportErrorCode> {
+        if max_write_bytes_per_sec == 0 {
+            return Err(TransportErrorCode::InvalidSocketIoConfig);
+        }
+        self.max_write_bytes_per_sec = Some(max_write_bytes_per_sec);
+        Ok(self)
+    }
+
+    /// Creates the socket IO config of a flow, using the defaults for any
+    /// value that is not configured.
+    pub(crate) fn from_flow_config(
+        flow_config: &TransportFlowConfig,
+    ) -> Result<Self, TransportErrorCode> {
+        let config = Self::new(
+            flow_config.dequeue_bytes.unwrap_or(DEFAULT_DEQUEUE_BYTES),
+            flow_config
+                .socket_read_chunk_size
+                .unwrap_or(DEFAULT_SOCKET_READ_CHUNK_SIZE),
+            Duration::from_millis(
+                flow_config
+                    .socket_write_timeout_ms
+                    .unwrap_or(DEFAULT_SOCKET_WRITE_TIMEOUT_MS),
+            ),
+        )?
+        .with_connection_mode(flow_config.connection_mode)
+        .with_reject_empty_messages(flow_config.reject_empty_messages)
+        .with_event_handler_timeout(
+            Duration::from_millis(
+                flow_config
+                    .event_handler_timeout_ms
+                    .unwrap_or(DEFAULT_EVENT_HANDLER_TIMEOUT_MS),
+            ),
+            flow_config.disconnect_on_event_handler_timeout,
+        )?;
+        let config = match flow_config.data_idle_timeout_ms {
+            Some(data_idle_timeout_ms) => {
+                config.with_data_idle_timeout(Duration::from_millis(data_idle_timeout_ms))?
+            }
+            None => config,
+        };
+        match flow_config.max_write_bytes_per_sec {
+            Some(max_write_bytes_per_sec) => {
+                config.with_max_write_bytes_per_sec(max_write_bytes_per_sec)
+            }
+            None => Ok(config),
+        }
+    }
+}
+
+/// Heartbeat intervals of a flow.
+///
+/// Both intervals are always positive, and the send interval is strictly less
+/// than the wait interval, so that the receiver sees at least one heartbeat
+/// before it times out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct HeartbeatConfig {
+    /// Time after which the write task sends a heartbeat if there is nothing
+    /// else to send
+    pub(crate) send_interval: Duration,
+    /// Time after which the read task considers the connection down if
+    /// nothing was received
+    pub(crate) wait_interval: Duration,
+}
+
+impl HeartbeatConfig {
+    /// Creates a heartbeat config, checking that the send interval is positive
+    /// and less than the wait interval. A zero send interval would make the
+    /// write task send heartbeats in a busy loop.
+    pub(crate) fn new(
+        send_interval: Duration,
+        wait_interval: Duration,
+    ) -> Result<Self, TransportErrorCode> {
+        if send_interval == Duration::from_millis(0) || send_interval >= wait_interval {
+            return Err(TransportErrorCode::InvalidHeartbeatConfig);
+        }
+        Ok(Self {
+            send_interval,
+            wait_interval,
+        })
+    }
+
+    /// Creates the heartbeat config of a flow, using the defaults for any
+    /// interval that is not configured.
+    pub(crate) fn from_flow_config(
+        flow_config: &TransportFlowConfig,
+    ) -> Result<Self, TransportErrorCode> {
+        Self::new(
+            Duration::from_millis(
+                flow_config
+                    .heartbeat_send_interval_ms
+                    .unwrap_or(DEFAULT_HEARTBEAT_SEND_INTERVAL_MS),
+            ),
+            Duration::from_millis(
+                flow_config
+                    .heartbeat_wait_interval_ms
+                    .unwrap_or(DEFAULT_HEARTBEAT_WAIT_INTERVAL_MS),
+            ),
+        )
+    }
+}
+
 /// The transport header format.
 ///
 /// A message is sent on the wire as two writes:
@@ -66,13 +336,15 @@ pub const TRANSPORT_FLAGS_IS_HEARTBEAT: u8 = 2;
 /// To maintain the size invariant the header is manually serialized.
 /// This struct is ephemeral hence the lack of derivations or tagging.
 pub(crate) struct TransportHeader {
-    /// The version of the Transport being used (currently 0)
-    pub(crate) version: u8, // Currently 0
+    /// The version of the Transport being used (0 or 1). Receivers reject
+    /// headers with a version above `MAX_SUPPORTED_VERSION`.
+    pub(crate) version: u8,
     /// Transport flags: defined by the constants named `TRANSPORT_FLAGS_*` in
     /// this module
     pub(crate) flags: u8,
-    /// Reserved space (currently 0)
-    pub(crate) reserved: u16, // Currently 0, serialized little endian.
+    /// Reserved space: 0 in version 0, and a checksum over the other header
+    /// fields from version `HEADER_CHECKSUM_VERSION` on
+    pub(crate) reserved: u16, // Serialized little endian.
     /// The length of the byte payload that follows next
     pub(crate) payload_length: u32, // Serialized little endian.
 }
@@ -145,8 +417,19 @@ pub(crate) struct FlowState {
     pub connection_state: ConnectionState,
     /// Handles to stop the send/receive tasks for this flow
     pub abort_handles: Option<(AbortHandle, AbortHandle)>,
+    /// Signals to stop the send/receive tasks for this flow cleanly
+    pub stop_signals: Option<(Arc<Notify>, Arc<Notify>)>,
+    /// Whether the send/receive tasks for this flow were signaled to stop
+    /// and have not exited yet
+    pub stop_requested: bool,
     /// The send queue of this flow
     pub send_queue: Box<dyn SendQueue + Send + Sync>,
+    /// Heartbeat intervals of this flow
+    pub heartbeat_config: HeartbeatConfig,
+    /// The maximum payload length accepted from the peer on this flow
+    pub max_payload_length: u32,
+    /// Socket write and read sizes of this flow
+    pub socket_io_config: SocketIoConfig,
 }
 
 /// Current state of the connection for a flow with a peer
@@ -186,6 +469,9 @@ pub(crate) trait SendQueue {
 pub(crate) trait SendQueueReader {
     /// Called by the scheduler to get the next enqueued message, if any.
     async fn dequeue(&mut self, bytes_limit: usize, timeout: Duration) -> Vec<DequeuedMessage>;
+
+    /// Returns the number of messages currently waiting in the queue.
+    fn len(&self) -> usize;
 }
 
 /// A wrapper for messages that also encloses any related errors
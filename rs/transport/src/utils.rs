@@ -8,7 +8,7 @@ use ic_types::NodeId;
 
 use async_trait::async_trait;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
 use tokio::time::Duration;
@@ -101,6 +101,9 @@ pub(crate) struct SendQueueImpl {
     /// Error flag
     error: Arc<AtomicBool>,
 
+    /// Number of messages in the queue
+    queued: Arc<AtomicUsize>,
+
     /// Metrics
     metrics: SendQueueMetrics,
 }
@@ -120,6 +123,7 @@ impl SendQueueImpl {
             flow_label,
             flow_tag: flow_tag.to_string(),
             error: Arc::new(AtomicBool::new(false)),
+            queued: Arc::new(AtomicUsize::new(0)),
             queue_size,
             channel_ends: Mutex::new((send_end, Arc::new(receieve_end_wrapper))),
             metrics,
@@ -143,6 +147,7 @@ impl SendQueue for SendQueueImpl {
             receive_end_container: channel_ends.1.clone(),
             cur_receive_end: None,
             error: self.error.clone(),
+            queued: self.queued.clone(),
             metrics: self.metrics.clone(),
         };
         Box::new(reader)
@@ -160,7 +165,10 @@ impl SendQueue for SendQueueImpl {
 
         let mut channel_ends = self.channel_ends.lock().unwrap();
         match channel_ends.0.try_send((Instant::now(), message)) {
-            Ok(_) => None,
+            Ok(_) => {
+                self.queued.fetch_add(1, Ordering::Relaxed);
+                None
+            }
             Err(TrySendError::Full((_, unsent))) => {
                 self.error.store(true, Ordering::Release);
                 self.metrics
@@ -186,6 +194,7 @@ impl SendQueue for SendQueueImpl {
             let mut channel_ends = self.channel_ends.lock().unwrap();
             channel_ends.0 = send_end;
             channel_ends.1.update(receive_end);
+            self.queued.store(0, Ordering::Relaxed);
         }
         self.metrics
             .queue_clear
@@ -201,6 +210,7 @@ struct SendQueueReaderImpl {
     receive_end_container: Arc<ReceiveEndContainer>,
     cur_receive_end: Option<ReceiveEnd>,
     error: Arc<AtomicBool>,
+    queued: Arc<AtomicUsize>,
     metrics: SendQueueMetrics,
 }
 
@@ -257,6 +267,13 @@ impl SendQueueReader for SendQueueReaderImpl {
                 .observe(enqueue_time.elapsed().as_millis() as f64);
             removed += 1;
             removed_bytes += payload.0.len();
+            // Messages of a cleared queue may still be drained by an ongoing
+            // dequeue, so the count must not wrap around.
+            let _ = self
+                .queued
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |queued| {
+                    Some(queued.saturating_sub(1))
+                });
 
             let sender_error = if removed == 1 {
                 // Return bool present in error and set error to false.
@@ -303,6 +320,10 @@ impl SendQueueReader for SendQueueReaderImpl {
             .inc_by(removed_bytes as i64);
         result
     }
+
+    fn len(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
 }
 
 /// Returns a map of flow_tag -> peer_ip for that flow.
@@ -58,7 +58,7 @@ pub struct TransportConfig {
 }
 
 /// Per-flow config
-#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TransportFlowConfig {
     /// The flow tag. This should be unique per transport client.
     pub flow_tag: u32,
@@ -69,6 +69,92 @@ pub struct TransportFlowConfig {
 
     /// Flow queue size
     pub queue_size: usize,
+
+    /// Heartbeat send interval in milliseconds (timeout on sender side). The
+    /// transport default is used if not set.
+    #[serde(default)]
+    pub heartbeat_send_interval_ms: Option<u64>,
+
+    /// Heartbeat wait interval in milliseconds (timeout on receiver side). The
+    /// transport default is used if not set. Must be larger than the send
+    /// interval.
+    #[serde(default)]
+    pub heartbeat_wait_interval_ms: Option<u64>,
+
+    /// The maximum payload length in bytes accepted from a peer. The transport
+    /// default is used if not set.
+    #[serde(default)]
+    pub max_payload_length: Option<u32>,
+
+    /// The number of bytes the send task attempts to dequeue and aggregate
+    /// into a single socket write. The transport default is used if not set.
+    #[serde(default)]
+    pub dequeue_bytes: Option<usize>,
+
+    /// The size in bytes of the chunks in which payloads are read from the
+    /// socket. The transport default is used if not set.
+    #[serde(default)]
+    pub socket_read_chunk_size: Option<usize>,
+
+    /// Time in milliseconds after which a pending socket write is aborted and
+    /// the connection is considered down. The transport default is used if
+    /// not set.
+    #[serde(default)]
+    pub socket_write_timeout_ms: Option<u64>,
+
+    /// The maximum rate in bytes per second at which messages are written to
+    /// the socket of a peer connection. Heartbeats are not limited. Writes
+    /// are not rate limited if not set.
+    #[serde(default)]
+    pub max_write_bytes_per_sec: Option<u64>,
+
+    /// What the peer connections of the flow are used for. Defaults to
+    /// `ConnectionMode::Data`.
+    #[serde(default)]
+    pub connection_mode: ConnectionMode,
+
+    /// Whether sending an empty message on the flow is rejected as a misuse.
+    /// If not set, empty messages are sent and delivered to the peer like
+    /// any other message.
+    #[serde(default)]
+    pub reject_empty_messages: bool,
+
+    /// Time in milliseconds after which the event handler is considered slow
+    /// if it has not accepted a received message. The transport default is
+    /// used if not set.
+    #[serde(default)]
+    pub event_handler_timeout_ms: Option<u64>,
+
+    /// Whether the connection is dropped if the event handler is slow. If
+    /// not set, a slow event handler is only reported, and the transport
+    /// keeps waiting for it.
+    #[serde(default)]
+    pub disconnect_on_event_handler_timeout: bool,
+
+    /// Time in milliseconds after which a peer connection is dropped if no
+    /// message other than heartbeats was received. Connections are never
+    /// dropped for being idle if not set.
+    #[serde(default)]
+    pub data_idle_timeout_ms: Option<u64>,
+}
+
+/// The kind of traffic carried by the peer connections of a flow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ConnectionMode {
+    /// The connections carry messages, and heartbeats while there are no
+    /// messages to send.
+    Data,
+
+    /// The connections only carry heartbeats, to monitor the liveness of the
+    /// peer. Messages in the send queue are never sent, and receiving a
+    /// message is a protocol violation that causes a disconnect.
+    KeepaliveOnly,
+}
+
+impl Default for ConnectionMode {
+    fn default() -> Self {
+        ConnectionMode::Data
+    }
 }
 
 /// State changes that can happen in the transport layer.
@@ -124,6 +210,14 @@ pub enum TransportErrorCode {
     /// Unable to find config for the client type.
     TransportClientConfigNotFound,
 
+    /// The heartbeat send interval of a flow is zero or not less than its
+    /// wait interval.
+    InvalidHeartbeatConfig,
+
+    /// The dequeue size, the socket read chunk size or the socket write
+    /// timeout of a flow is zero.
+    InvalidSocketIoConfig,
+
     /// Failed to enqueue/submit a message/request. The error code contains the
     /// entry that could not be submitted.
     TransportBusy(TransportPayload),
@@ -154,6 +248,12 @@ pub enum TransportErrorCode {
     /// Unable to route the message -> queue, based on the config.
     MessageQueueRoutingFailed,
 
+    /// Tried to send an empty message on a flow that rejects empty messages.
+    EmptyMessage,
+
+    /// Tried to send a message on a flow whose connection is being stopped.
+    FlowStopped,
+
     /// Transport queue is full.
     TransportQueueFull,
 